@@ -0,0 +1,19 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+#import bumper::shadertoy::{inputs, spectrum, spectrum_sampler}
+
+// Bars of the audio track's spectrum that flash on beats.
+// Pick a track in the Audio window to see it move.
+@group(2) @binding(1) var<uniform> bar_color: vec4<f32>; // default(0.2, 0.8, 1.0, 1.0)
+@group(2) @binding(2) var<uniform> beat_color: vec4<f32>; // default(1.0, 0.3, 0.6, 1.0)
+@group(2) @binding(3) var<uniform> gap: f32; // range(0.0, 0.9) default(0.2)
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let bands = f32(textureDimensions(spectrum).x);
+    let band = floor(mesh.uv.x * bands);
+    let level = textureSample(spectrum, spectrum_sampler, vec2<f32>((band + 0.5) / bands, 0.5)).r;
+
+    let inside = fract(mesh.uv.x * bands) > gap && 1.0 - mesh.uv.y < level;
+    let color = mix(bar_color, beat_color, inputs.audio.z);
+    return select(vec4<f32>(0.0), color, inside);
+}
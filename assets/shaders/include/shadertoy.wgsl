@@ -0,0 +1,23 @@
+#define_import_path bumper::shadertoy
+
+// Standard inputs every shader module receives, modelled after ShaderToy's uniforms.
+// Bindings 1 to 8 of group 2 are free for the shader's own uniforms.
+// Bindings 9 and 10 hold the spectrum of the audio track at the current frame.
+struct ShaderToyInputs {
+    // playback time in seconds
+    time: f32,
+    // playback frame
+    frame: u32,
+    // size of the module in pixels
+    resolution: vec2<f32>,
+    // xy: cursor position in pixels from the bottom left of the module
+    // z: 1.0 while the left mouse button is held
+    mouse: vec4<f32>,
+    // x: loudness, y: onset, z: 1.0 on a beat fading out right after, w: seconds since the last beat
+    audio: vec4<f32>,
+}
+
+@group(2) @binding(0) var<uniform> inputs: ShaderToyInputs;
+// a pixel per band from low to high frequencies, sample at y = 0.5
+@group(2) @binding(9) var spectrum: texture_2d<f32>;
+@group(2) @binding(10) var spectrum_sampler: sampler;
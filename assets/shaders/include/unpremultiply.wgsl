@@ -0,0 +1,17 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+// Last pass of every shader chain.
+// Blending onto a transparent target leaves colours multiplied by their alpha,
+// and chain shaders work on those. Sprites showing the target blend with straight alpha,
+// so the alpha is divided out again here.
+@group(0) @binding(0) var screen_texture: texture_2d<f32>;
+@group(0) @binding(1) var texture_sampler: sampler;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(screen_texture, texture_sampler, in.uv);
+    if color.a <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(color.rgb / color.a, color.a);
+}
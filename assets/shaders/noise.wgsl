@@ -1,22 +1,25 @@
-#import bevy_sprite::mesh2d_view_bindings::globals 
 #import bevy_sprite::mesh2d_vertex_output::VertexOutput
-#import bevy_render::view::View // Import to get access to the time uniform
-
-// Adjust these constants to change the pattern's look
-const STARBURST_DENSITY: f32 = 15.0; // How many "points" the starburst has
-const PULSE_SPEED: f32 = 40.0;       // How fast the colors change and pulse
-const COLOR_MIX_POWER: f32 = 3.0;   // Controls the intensity of color blending
-
-// Bevy provides a View uniform which includes time!
-@group(0) @binding(0) var<uniform> view: View;
-
-@group(2) @binding(0) var<uniform> material_color: vec4<f32>;
-
-@group(2) @binding(1) var<uniform> width: f32;
-@group(2) @binding(2) var<uniform> height: f32;
-@group(2) @binding(3) var<uniform> speed: f32;
 
+// Mirrors NoiseSettings in src/module/noise.rs
+struct NoiseSettings {
+    time: f32,
+    width: f32,
+    height: f32,
+    speed: f32,
+    offset: vec2<f32>,
+    drift: vec2<f32>,
+    scale: f32,
+    lacunarity: f32,
+    gain: f32,
+    seed: f32,
+    octaves: u32,
+    stop_count: u32,
+    // four stop positions per vector
+    stop_positions: array<vec4<f32>, 2>,
+    stop_colors: array<vec4<f32>, 8>,
+}
 
+@group(2) @binding(0) var<uniform> settings: NoiseSettings;
 
 // MIT License. © Stefan Gustavson, Munrocket
 //
@@ -45,23 +48,57 @@ fn noise3(p: vec3f) -> f32 {
     return o4.y * d.y + o4.x * (1. - d.y);
 }
 
+// Pseudo random offset so every seed and octave samples a different part of the noise
+fn seed_offset(seed: f32, octave: f32) -> vec3f {
+    let h = fract(sin(vec3f(seed * 12.9898 + octave * 78.233, seed * 39.346 + octave * 11.135, seed * 73.156 + octave * 52.235)) * 43758.5453);
+    return h * 200.0;
+}
 
-@fragment
-fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
-    // Center the UV coordinates so (0,0) is the middle of the mesh
-    let resolution = view.viewport.zw;
-    let uv = (mesh.uv - vec2<f32>(0.5, 0.5)) * vec2<f32>(width, height) / 400.0;
+// Fractal brownian motion, normalised to 0..1
+fn fbm(p: vec3f) -> f32 {
+    var value = 0.0;
+    var amplitude = 1.0;
+    var total = 0.0;
+    var frequency = 1.0;
+    for (var i = 0u; i < settings.octaves; i++) {
+        let octave = f32(i);
+        value += noise3(p * frequency + seed_offset(settings.seed, octave)) * amplitude;
+        total += amplitude;
+        frequency *= settings.lacunarity;
+        amplitude *= settings.gain;
+    }
+    return value / max(total, 0.0001);
+}
 
-    // Use time to animate the pattern
-    let time = globals.time;
+fn stop_position(i: u32) -> f32 {
+    return settings.stop_positions[i / 4u][i % 4u];
+}
 
-    // Blend the dynamic color with the original material_color
-    // The COLOR_MIX_POWER makes the blending more intense/sharper
-    let noise_value = noise3(vec3<f32>(uv * 100.0, time * speed));
-    var final_color = vec4<f32>(noise_value, noise_value, noise_value, 1.0);
+fn color_ramp(t: f32) -> vec4f {
+    if (settings.stop_count == 0u) {
+        return vec4f(t, t, t, 1.0);
+    }
+    if (t <= stop_position(0u)) {
+        return settings.stop_colors[0];
+    }
+    for (var i = 1u; i < settings.stop_count; i++) {
+        let position = stop_position(i);
+        if (t <= position) {
+            let previous = stop_position(i - 1u);
+            let f = (t - previous) / max(position - previous, 0.0001);
+            return mix(settings.stop_colors[i - 1u], settings.stop_colors[i], f);
+        }
+    }
+    return settings.stop_colors[settings.stop_count - 1u];
+}
 
-    // You can still apply a global multiplier for overall brightness/alpha
-    final_color *= vec4<f32>(1.0, 1.0, 1.0, 1.0); // Adjust overall transparency
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    // Center the UV coordinates so (0,0) is the middle of the mesh, scaled so the pattern doesn't stretch with the module
+    let uv = (mesh.uv - vec2<f32>(0.5, 0.5)) * vec2<f32>(settings.width, settings.height) / 400.0;
+
+    let p = uv * settings.scale + settings.offset + settings.drift * settings.time;
+    let noise_value = fbm(vec3<f32>(p, settings.time * settings.speed));
 
-    return final_color;
-}
\ No newline at end of file
+    return color_ramp(noise_value);
+}
@@ -0,0 +1,26 @@
+#import bevy_sprite::mesh2d_vertex_output::VertexOutput
+#import bumper::shadertoy::inputs
+
+// Every uniform in group 2 becomes an editable parameter of the shader module.
+// f32 is a slider, vec2 two number fields and vec3/vec4 a colour picker.
+// The optional range(min, max) and default(...) annotations configure the widget.
+@group(2) @binding(1) var<uniform> speed: f32; // range(0.0, 5.0) default(1.0)
+@group(2) @binding(2) var<uniform> rings: f32; // range(1.0, 40.0) default(12.0)
+@group(2) @binding(3) var<uniform> inner_color: vec4<f32>; // default(1.0, 0.35, 0.1, 1.0)
+@group(2) @binding(4) var<uniform> outer_color: vec4<f32>; // default(0.1, 0.2, 0.9, 1.0)
+@group(2) @binding(5) var<uniform> center: vec2<f32>; // range(-1.0, 1.0) default(0.0, 0.0)
+
+@fragment
+fn fragment(mesh: VertexOutput) -> @location(0) vec4<f32> {
+    let aspect = inputs.resolution.x / max(inputs.resolution.y, 1.0);
+    var uv = (mesh.uv - vec2<f32>(0.5, 0.5)) * vec2<f32>(aspect, 1.0) - center;
+
+    // the rings follow the cursor while the mouse button is held
+    if inputs.mouse.z > 0.0 {
+        uv -= (inputs.mouse.xy / inputs.resolution - vec2<f32>(0.5, 0.5)) * vec2<f32>(aspect, -1.0);
+    }
+
+    let d = length(uv);
+    let wave = 0.5 + 0.5 * sin(d * rings * 6.2831 - inputs.time * speed * 4.0);
+    return mix(inner_color, outer_color, clamp(d * 2.0, 0.0, 1.0)) * wave;
+}
@@ -0,0 +1,249 @@
+use std::f32::consts::TAU;
+
+use rustfft::FftPlanner;
+use rustfft::num_complex::Complex;
+
+/// Number of spectrum bands, spaced logarithmically over the audible range
+pub const BANDS: usize = 32;
+/// Samples in an FFT window
+const WINDOW: usize = 2048;
+const MIN_FREQUENCY: f32 = 30.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+/// Beats are at least this many seconds apart
+const MIN_BEAT_GAP: f32 = 0.1;
+/// Seconds around a frame its onset is compared with to find beats
+const BEAT_NEIGHBOURHOOD: f32 = 0.5;
+
+/// The features of one composition frame, from 0 to 1
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioFrame {
+    /// Loudness
+    pub rms: f32,
+    /// Magnitude of every band, low frequencies first
+    pub bands: [f32; BANDS],
+    /// How much louder the spectrum got since the previous frame
+    pub onset: f32,
+    pub beat: bool,
+}
+
+/// The features of every composition frame of a track.
+/// They are computed from the file up front, so a frame looks the same no matter how it is played or exported.
+#[derive(Debug, Default)]
+pub struct AudioFeatures {
+    pub frame_rate: f32,
+    pub frames: Vec<AudioFrame>,
+}
+
+impl AudioFeatures {
+    pub fn frame(&self, frame: u64) -> Option<&AudioFrame> {
+        self.frames.get(frame as usize)
+    }
+
+    /// Seconds since the last beat up to and including `frame`
+    pub fn since_beat(&self, frame: u64) -> Option<f32> {
+        let frame = (frame as usize).min(self.frames.len().checked_sub(1)?);
+        let beat = self.frames[..=frame].iter().rposition(|f| f.beat)?;
+        Some((frame - beat) as f32 / self.frame_rate)
+    }
+}
+
+/// Analyses mono samples frame by frame at the composition frame rate
+pub fn analyse(samples: &[f32], sample_rate: u32, frame_rate: f32) -> AudioFeatures {
+    let rate = sample_rate as f32;
+    let count = (samples.len() as f32 / rate * frame_rate).ceil() as usize;
+    let fft = FftPlanner::new().plan_fft_forward(WINDOW);
+    let hann: Vec<f32> = (0..WINDOW)
+        .map(|i| 0.5 - 0.5 * (TAU * i as f32 / WINDOW as f32).cos())
+        .collect();
+    let edges = band_edges(sample_rate);
+
+    let mut buffer = vec![Complex::default(); WINDOW];
+    let mut frames: Vec<AudioFrame> = Vec::with_capacity(count);
+    for frame in 0..count {
+        let start = ((frame as f32 / frame_rate * rate) as usize).min(samples.len());
+        let end = (((frame + 1) as f32 / frame_rate * rate) as usize).min(samples.len());
+        let own = &samples[start..end];
+        let rms = (own.iter().map(|s| s * s).sum::<f32>() / own.len().max(1) as f32).sqrt();
+
+        // the window is centred on the frame, it reaches into its neighbours
+        let window_start = (start + end) as isize / 2 - WINDOW as isize / 2;
+        for (i, value) in buffer.iter_mut().enumerate() {
+            let sample = usize::try_from(window_start + i as isize)
+                .ok()
+                .and_then(|i| samples.get(i))
+                .copied()
+                .unwrap_or(0.0);
+            *value = Complex::new(sample * hann[i], 0.0);
+        }
+        fft.process(&mut buffer);
+
+        let mut bands = [0.0; BANDS];
+        for (band, value) in bands.iter_mut().enumerate() {
+            let bins = &buffer[edges[band]..edges[band + 1]];
+            let magnitude = bins.iter().map(|c| c.norm()).sum::<f32>() / bins.len().max(1) as f32;
+            *value = magnitude.ln_1p();
+        }
+
+        frames.push(AudioFrame {
+            rms,
+            bands,
+            ..Default::default()
+        });
+    }
+
+    normalize(&mut frames, |f| &mut f.rms);
+    let loudest = frames.iter().flat_map(|f| f.bands).fold(0.0f32, f32::max);
+    if loudest > 0.0 {
+        for value in frames.iter_mut().flat_map(|f| f.bands.iter_mut()) {
+            *value /= loudest;
+        }
+    }
+
+    // spectral flux: what got louder since the previous frame
+    for i in 1..frames.len() {
+        let (previous, current) = frames.split_at_mut(i);
+        let previous = &previous[i - 1];
+        let current = &mut current[0];
+        current.onset = current
+            .bands
+            .iter()
+            .zip(previous.bands.iter())
+            .map(|(now, before)| (now - before).max(0.0))
+            .sum::<f32>()
+            / BANDS as f32;
+    }
+    normalize(&mut frames, |f| &mut f.onset);
+
+    find_beats(&mut frames, frame_rate);
+
+    AudioFeatures { frame_rate, frames }
+}
+
+/// Beats are onsets that peak clearly above the onsets around them
+fn find_beats(frames: &mut [AudioFrame], frame_rate: f32) {
+    let gap = (MIN_BEAT_GAP * frame_rate).ceil() as usize;
+    let neighbourhood = (BEAT_NEIGHBOURHOOD * frame_rate).ceil() as usize;
+    let mut last_beat: Option<usize> = None;
+    for i in 0..frames.len() {
+        let around = |reach: usize| {
+            let start = i.saturating_sub(reach);
+            let end = (i + reach + 1).min(frames.len());
+            frames[start..end].iter().map(|f| f.onset)
+        };
+        let onset = frames[i].onset;
+        let peak = around(gap).all(|other| other <= onset);
+        let mean = around(neighbourhood).sum::<f32>() / (2 * neighbourhood + 1) as f32;
+        let spaced = last_beat.is_none_or(|beat| i - beat >= gap);
+        if peak && spaced && onset > 0.1 && onset > mean * 1.5 {
+            frames[i].beat = true;
+            last_beat = Some(i);
+        }
+    }
+}
+
+/// Scales a feature so its loudest frame is 1
+fn normalize(frames: &mut [AudioFrame], feature: impl Fn(&mut AudioFrame) -> &mut f32) {
+    let max = frames
+        .iter_mut()
+        .map(|f| *feature(f))
+        .fold(0.0f32, f32::max);
+    if max > 0.0 {
+        for frame in frames.iter_mut() {
+            *feature(frame) /= max;
+        }
+    }
+}
+
+/// The FFT bins every band starts at, and where the last one ends
+fn band_edges(sample_rate: u32) -> [usize; BANDS + 1] {
+    let nyquist = sample_rate as f32 / 2.0;
+    let max = MAX_FREQUENCY.min(nyquist);
+    let mut edges = [0; BANDS + 1];
+    for (band, edge) in edges.iter_mut().enumerate() {
+        let frequency = MIN_FREQUENCY * (max / MIN_FREQUENCY).powf(band as f32 / BANDS as f32);
+        *edge = (frequency / sample_rate as f32 * WINDOW as f32) as usize;
+    }
+    // low bands are narrower than a bin, every band gets at least one
+    for band in 1..=BANDS {
+        edges[band] = edges[band].max(edges[band - 1] + 1).min(WINDOW / 2);
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 44100;
+
+    fn sine(frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds * RATE as f32) as usize)
+            .map(|i| (TAU * frequency * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    /// Short bursts of a tone every `interval` seconds, starting at `interval`
+    fn clicks(interval: f32, seconds: f32) -> Vec<f32> {
+        let mut samples = vec![0.0; (seconds * RATE as f32) as usize];
+        let burst = RATE as usize / 100;
+        let mut at = interval;
+        while at < seconds {
+            let start = (at * RATE as f32) as usize;
+            for (i, sample) in samples[start..].iter_mut().take(burst).enumerate() {
+                *sample = (TAU * 2000.0 * i as f32 / RATE as f32).sin();
+            }
+            at += interval;
+        }
+        samples
+    }
+
+    #[test]
+    fn one_frame_per_started_composition_frame() {
+        assert_eq!(analyse(&sine(440.0, 1.0), RATE, 30.0).frames.len(), 30);
+        assert_eq!(analyse(&sine(440.0, 1.01), RATE, 30.0).frames.len(), 31);
+        assert_eq!(analyse(&sine(440.0, 2.0), RATE, 60.0).frames.len(), 120);
+        assert_eq!(analyse(&[], RATE, 30.0).frames.len(), 0);
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let samples = clicks(0.3, 2.0);
+        assert_eq!(
+            analyse(&samples, RATE, 30.0).frames,
+            analyse(&samples, RATE, 30.0).frames
+        );
+    }
+
+    #[test]
+    fn finds_the_beats_of_a_click_track() {
+        let features = analyse(&clicks(0.5, 4.0), RATE, 30.0);
+        let beats: Vec<usize> = (0..features.frames.len())
+            .filter(|frame| features.frames[*frame].beat)
+            .collect();
+        // a click every 15 frames, the first one is where the window starts to hear it
+        assert_eq!(beats.len(), 7, "{beats:?}");
+        for (beat, click) in beats.iter().zip((15..).step_by(15)) {
+            assert!(beat.abs_diff(click) <= 1, "{beats:?}");
+        }
+        assert_eq!(features.since_beat(beats[0] as u64 + 3), Some(0.1));
+    }
+
+    #[test]
+    fn a_sine_fills_its_own_band() {
+        let features = analyse(&sine(1000.0, 1.0), RATE, 30.0);
+        let edges = band_edges(RATE);
+        let bin = (1000.0 / RATE as f32 * WINDOW as f32) as usize;
+        let band = (0..BANDS)
+            .find(|band| (edges[*band]..edges[band + 1]).contains(&bin))
+            .unwrap();
+
+        let frame = &features.frames[15];
+        assert!(frame.bands[band] > 0.9);
+        assert!((frame.rms - 1.0).abs() < 1e-3);
+        for (other, energy) in frame.bands.iter().enumerate() {
+            if other.abs_diff(band) > 2 {
+                assert!(*energy < 0.5, "band {other} has {energy}");
+            }
+        }
+    }
+}
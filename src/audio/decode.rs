@@ -0,0 +1,135 @@
+use std::io::Cursor;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+
+/// A decoded audio file, mixed down to mono
+#[derive(Asset, TypePath, Debug)]
+pub struct AudioSamples {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl AudioSamples {
+    pub fn duration(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate.max(1) as f32
+    }
+}
+
+/// Decodes WAV and OGG Vorbis files
+#[derive(Default, TypePath)]
+pub struct AudioSamplesLoader;
+
+impl AssetLoader for AudioSamplesLoader {
+    type Asset = AudioSamples;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<AudioSamples, BevyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let ogg = load_context
+            .path()
+            .path()
+            .extension()
+            .is_some_and(|ext| ext == "ogg");
+        if ogg {
+            decode_ogg(bytes)
+        } else {
+            decode_wav(bytes)
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["wav", "ogg"]
+    }
+}
+
+fn decode_wav(bytes: Vec<u8>) -> Result<AudioSamples, BevyError> {
+    let mut reader = hound::WavReader::new(Cursor::new(bytes))?;
+    let spec = reader.spec();
+    if spec.sample_rate == 0 {
+        return Err("the file has a sample rate of 0".into());
+    }
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok(AudioSamples {
+        samples: mono(&interleaved, spec.channels as usize),
+        sample_rate: spec.sample_rate,
+    })
+}
+
+fn decode_ogg(bytes: Vec<u8>) -> Result<AudioSamples, BevyError> {
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(Cursor::new(bytes))?;
+    let channels = reader.ident_hdr.audio_channels as usize;
+    if reader.ident_hdr.audio_sample_rate == 0 {
+        return Err("the file has a sample rate of 0".into());
+    }
+    let mut interleaved = vec![];
+    while let Some(packet) = reader.read_dec_packet_itl()? {
+        interleaved.extend(packet.into_iter().map(|s| s as f32 / 32768.0));
+    }
+    Ok(AudioSamples {
+        samples: mono(&interleaved, channels),
+        sample_rate: reader.ident_hdr.audio_sample_rate,
+    })
+}
+
+fn mono(interleaved: &[f32], channels: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(sample_rate: u32, channels: u16, samples: &[i16]) -> Vec<u8> {
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut bytes = Cursor::new(vec![]);
+        let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn mixes_wav_down_to_mono() {
+        let decoded = decode_wav(wav(8000, 2, &[16384, 0, -16384, -16384])).unwrap();
+        assert_eq!(decoded.sample_rate, 8000);
+        assert_eq!(decoded.samples, [0.25, -0.5]);
+    }
+
+    #[test]
+    fn rejects_a_sample_rate_of_0() {
+        let mut bytes = wav(8000, 1, &[0, 1, 2]);
+        // the sample rate and byte rate fields of the fmt chunk
+        bytes[24..32].fill(0);
+        let err = decode_wav(bytes).unwrap_err();
+        assert!(err.to_string().contains("sample rate of 0"), "{err}");
+    }
+}
@@ -0,0 +1,312 @@
+use bevy::asset::RenderAssetUsages;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::futures::check_ready;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use serde::{Deserialize, Serialize};
+
+use crate::common::{AppState, ModuleWin};
+use crate::module::ModuleId;
+use crate::params::{ExternalValue, LiveParams, ModuleParams};
+use crate::playback::Playback;
+
+pub mod analysis;
+mod decode;
+
+pub use analysis::{AudioFeatures, BANDS};
+use decode::{AudioSamples, AudioSamplesLoader};
+
+/// The directory in the assets the track is picked from
+pub const AUDIO_DIR: &str = "audio";
+/// Seconds the beat feature takes to fade out after a beat
+const BEAT_FADE: f32 = 0.25;
+/// Analyses at other frame rates kept around, so switching back to one, like after a capture, is instant
+const KEPT_ANALYSES: usize = 2;
+
+/// What of the track drives a parameter, every feature goes from 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AudioFeature {
+    Rms,
+    Onset,
+    /// 1 on a beat, fading out right after
+    Beat,
+    /// A spectrum band, 0 is the lowest
+    Band(u8),
+}
+
+impl AudioFeature {
+    pub fn value(self, features: &AudioFeatures, frame: u64) -> f32 {
+        let Some(current) = features.frame(frame) else {
+            return 0.0;
+        };
+        match self {
+            AudioFeature::Rms => current.rms,
+            AudioFeature::Onset => current.onset,
+            AudioFeature::Beat => features
+                .since_beat(frame)
+                .map_or(0.0, |since| (1.0 - since / BEAT_FADE).max(0.0)),
+            AudioFeature::Band(band) => current.bands.get(band as usize).copied().unwrap_or(0.0),
+        }
+    }
+}
+
+impl std::fmt::Display for AudioFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioFeature::Rms => write!(f, "rms"),
+            AudioFeature::Onset => write!(f, "onset"),
+            AudioFeature::Beat => write!(f, "beat"),
+            AudioFeature::Band(band) => write!(f, "band {band}"),
+        }
+    }
+}
+
+/// A feature of the track driving a module parameter
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AudioBinding {
+    pub feature: AudioFeature,
+    pub module: ModuleId,
+    pub param: String,
+    /// The values a feature of 0 and 1 map to
+    pub min: f32,
+    pub max: f32,
+}
+
+/// The audio file of the composition and the parameters it drives, saved with the project
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AudioTrack {
+    /// A WAV or OGG file in the audio assets directory
+    pub file: Option<String>,
+    #[serde(default)]
+    pub bindings: Vec<AudioBinding>,
+}
+
+/// The features of the track, analysed at the playback frame rate
+#[derive(Resource, Default)]
+pub struct AudioAnalysis {
+    file: Option<String>,
+    samples: Option<Handle<AudioSamples>>,
+    /// Only there once the track is analysed at the playback frame rate
+    pub features: Option<AudioFeatures>,
+    kept: Vec<AudioFeatures>,
+    task: Option<Task<AudioFeatures>>,
+    /// Seconds
+    pub duration: f32,
+    pub error: Option<String>,
+}
+
+impl AudioAnalysis {
+    /// There is no track, or its features are there for this frame rate
+    pub fn is_ready(&self, frame_rate: f32) -> bool {
+        self.samples.is_none()
+            || self
+                .features
+                .as_ref()
+                .is_some_and(|features| features.frame_rate == frame_rate)
+    }
+
+    #[cfg(feature = "editor")]
+    pub fn is_analysing(&self) -> bool {
+        self.task.is_some()
+    }
+
+    fn keep(&mut self, features: AudioFeatures) {
+        self.kept.push(features);
+        if self.kept.len() > KEPT_ANALYSES {
+            self.kept.remove(0);
+        }
+    }
+
+    /// Rms, onset and beat of the frame, and the seconds since the last beat
+    pub fn levels(&self, frame: u64) -> Vec4 {
+        let Some(features) = &self.features else {
+            return Vec4::ZERO;
+        };
+        Vec4::new(
+            AudioFeature::Rms.value(features, frame),
+            AudioFeature::Onset.value(features, frame),
+            AudioFeature::Beat.value(features, frame),
+            features.since_beat(frame).unwrap_or(0.0),
+        )
+    }
+}
+
+/// The spectrum of the current frame as a texture one pixel high, a pixel per band
+#[derive(Resource)]
+pub struct AudioSpectrum(pub Handle<Image>);
+
+/// Analyses an audio track into features of every frame, to drive module parameters and shaders with
+pub struct AudioTrackPlugin;
+
+impl Plugin for AudioTrackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<AudioSamples>()
+            .init_asset_loader::<AudioSamplesLoader>()
+            .init_resource::<AudioTrack>()
+            .init_resource::<AudioAnalysis>()
+            .add_systems(Startup, create_spectrum)
+            .add_systems(
+                PreUpdate,
+                (
+                    load_audio_track,
+                    analyse_audio_track,
+                    update_live_params,
+                    update_spectrum,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+fn create_spectrum(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = Image::new_fill(
+        Extent3d {
+            width: BANDS as u32,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0],
+        TextureFormat::R8Unorm,
+        RenderAssetUsages::default(),
+    );
+    commands.insert_resource(AudioSpectrum(images.add(image)));
+}
+
+fn load_audio_track(
+    asset_server: Res<AssetServer>,
+    track: Res<AudioTrack>,
+    mut analysis: ResMut<AudioAnalysis>,
+) {
+    if track.file == analysis.file {
+        return;
+    }
+    *analysis = AudioAnalysis {
+        file: track.file.clone(),
+        samples: track
+            .file
+            .as_ref()
+            .map(|file| asset_server.load(format!("{AUDIO_DIR}/{file}"))),
+        ..default()
+    };
+}
+
+/// Analyses the track on the compute task pool once it's loaded, and again when the frame rate changes.
+/// One analysis runs at a time, a frame rate dragged through many values is analysed at the one it ends on.
+fn analyse_audio_track(
+    asset_server: Res<AssetServer>,
+    playback: Res<Playback>,
+    samples: Res<Assets<AudioSamples>>,
+    mut analysis: ResMut<AudioAnalysis>,
+) {
+    let analysis = analysis.as_mut();
+    let Some(handle) = analysis.samples.clone() else {
+        return;
+    };
+    let frame_rate = playback.frame_rate;
+
+    if let Some(features) = analysis.task.as_mut().and_then(check_ready) {
+        info!(
+            "analysed {} frames of {} at {} fps",
+            features.frames.len(),
+            analysis.file.as_deref().unwrap_or_default(),
+            features.frame_rate
+        );
+        analysis.task = None;
+        analysis.keep(features);
+    }
+    if analysis.is_ready(frame_rate) {
+        return;
+    }
+
+    if let Some(features) = analysis.features.take() {
+        analysis.keep(features);
+    }
+    if let Some(kept) = analysis
+        .kept
+        .iter()
+        .position(|features| features.frame_rate == frame_rate)
+    {
+        analysis.features = Some(analysis.kept.remove(kept));
+        return;
+    }
+    // the running analysis is of a frame rate the playback had before
+    if analysis.task.is_some() {
+        return;
+    }
+
+    if let Some(audio) = samples.get(&handle) {
+        let (samples, sample_rate) = (audio.samples.clone(), audio.sample_rate);
+        analysis.duration = audio.duration();
+        analysis.task = Some(
+            AsyncComputeTaskPool::get()
+                .spawn(async move { analysis::analyse(&samples, sample_rate, frame_rate) }),
+        );
+    } else if let Some(bevy::asset::LoadState::Failed(err)) = asset_server.get_load_state(&handle) {
+        error!("couldn't load the audio track: {err}");
+        analysis.error = Some(err.to_string());
+        analysis.samples = None;
+    }
+}
+
+/// Keeps the [`LiveParams`] of every module up to date with its own params and the features of the current frame
+fn update_live_params(
+    mut commands: Commands,
+    track: Res<AudioTrack>,
+    analysis: Res<AudioAnalysis>,
+    playback: Res<Playback>,
+    mut modules: Query<
+        (Entity, &ModuleId, &ModuleParams, Option<&mut LiveParams>),
+        With<ModuleWin>,
+    >,
+) {
+    let frame = playback.frame();
+    for (entity, id, params, current) in modules.iter_mut() {
+        let mut live = params.clone();
+        if let Some(features) = &analysis.features {
+            for binding in track.bindings.iter().filter(|b| b.module == *id) {
+                let Some(param) = live.0.iter_mut().find(|p| p.name == binding.param) else {
+                    continue;
+                };
+                let t = binding.feature.value(features, frame);
+                if let Some(value) = ExternalValue::from_control(param, binding.min, binding.max, t)
+                {
+                    value.apply(param);
+                }
+            }
+        }
+
+        match current {
+            Some(mut current) => {
+                current.set_if_neq(LiveParams(live));
+            }
+            None => {
+                commands.entity(entity).insert(LiveParams(live));
+            }
+        }
+    }
+}
+
+fn update_spectrum(
+    analysis: Res<AudioAnalysis>,
+    playback: Res<Playback>,
+    spectrum: Res<AudioSpectrum>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let bands = analysis
+        .features
+        .as_ref()
+        .and_then(|features| features.frame(playback.frame()))
+        .map(|frame| frame.bands)
+        .unwrap_or_default();
+    let pixels: Vec<u8> = bands.iter().map(|b| (b * 255.0).round() as u8).collect();
+
+    // only touch the image when it changes, a modified image is uploaded again
+    let unchanged = images
+        .get(&spectrum.0)
+        .is_some_and(|image| image.data.as_deref() == Some(pixels.as_slice()));
+    if !unchanged && let Some(image) = images.get_mut(&spectrum.0) {
+        image.data = Some(pixels);
+    }
+}
@@ -37,7 +37,7 @@ pub struct Immortal;
 pub struct ModuleWin {
     pub class: ModuleClass,
     pub width: f32,
-    pub height: f32
+    pub height: f32,
 }
 
 #[derive(Component)]
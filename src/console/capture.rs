@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use bevy::log::BoxedLayer;
+use bevy::log::tracing::field::{Field, Visit};
+use bevy::log::tracing::{Event, Level, Subscriber};
+use bevy::log::tracing_subscriber::Layer;
+use bevy::log::tracing_subscriber::layer::Context;
+use bevy::platform::time::Instant;
+use bevy::prelude::*;
+
+/// Lines kept for the console, older ones are dropped
+const MAX_LINES: usize = 2000;
+
+#[derive(Clone, Debug)]
+pub struct LogLine {
+    /// Seconds since the app started
+    pub time: f32,
+    pub level: Level,
+    /// The module the line was logged from
+    pub target: String,
+    pub message: String,
+}
+
+/// Everything logged through `tracing`, newest last.
+/// Filled by the layer [`capture_layer`] adds to the subscriber, so it sees lines from before the app runs too.
+#[derive(Resource, Clone, Default)]
+pub struct LogBuffer(pub Arc<Mutex<VecDeque<LogLine>>>);
+
+/// Passed to [`LogPlugin::custom_layer`](bevy::log::LogPlugin::custom_layer)
+pub fn capture_layer(app: &mut App) -> Option<BoxedLayer> {
+    let buffer = LogBuffer::default();
+    app.insert_resource(buffer.clone());
+    Some(Box::new(CaptureLayer {
+        buffer,
+        start: Instant::now(),
+    }))
+}
+
+struct CaptureLayer {
+    buffer: LogBuffer,
+    start: Instant,
+}
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+
+        let line = LogLine {
+            time: self.start.elapsed().as_secs_f32(),
+            level: *event.metadata().level(),
+            target: event.metadata().target().to_string(),
+            message: message.0,
+        };
+        if let Ok(mut lines) = self.buffer.0.lock() {
+            if lines.len() == MAX_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+    }
+}
+
+/// Formats the message of an event followed by its other fields
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}{}", self.0);
+        } else {
+            self.0.push_str(&format!(" {}={value:?}", field.name()));
+        }
+    }
+}
@@ -0,0 +1,12 @@
+use bevy::log::DEFAULT_FILTER;
+
+/// Keeps the log for the editor's console window
+#[cfg(feature = "editor")]
+mod capture;
+#[cfg(feature = "editor")]
+pub use capture::{LogBuffer, LogLine, capture_layer};
+
+/// The log filter of the app, our own modules log at debug level
+pub fn log_filter() -> String {
+    format!("{DEFAULT_FILTER}bevycargo=debug")
+}
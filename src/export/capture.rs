@@ -0,0 +1,526 @@
+use bevy::camera::RenderTarget;
+use bevy::gizmos::config::{DefaultGizmoConfigGroup, GizmoConfigStore};
+use bevy::prelude::*;
+use bevy::render::gpu_readback::{Readback, ReadbackComplete};
+use bevy::render::render_resource::{TextureFormat, TextureUsages};
+
+use super::snapshot::PendingSnapshot;
+use crate::audio::AudioAnalysis;
+use crate::playback::Playback;
+use crate::rendering::ShaderChainCamera;
+
+/// Updates to wait after setting the time of a frame before reading it back,
+/// so modules rendering through their own cameras and targets have caught up
+pub const SETTLE_UPDATES: u8 = 2;
+/// Every how many pixels one is compared for the loop check
+const SEAM_SAMPLE_STRIDE: usize = 7;
+
+/// Where captured frames go, like an encoder
+pub trait FrameSink: Send + Sync + 'static {
+    /// Takes the next frame, tightly packed RGBA rows from the top with straight alpha
+    fn write(&mut self, frame: Vec<u8>) -> Result<(), String>;
+    /// Whether the sink has caught up with the written frames, the next frame is held until it has
+    fn is_ready(&mut self) -> bool {
+        true
+    }
+    /// Ends the input and checks whether the output is complete.
+    /// Called every update until it returns the result, so it must not wait on slow work.
+    fn finish(&mut self) -> Option<Result<(), String>>;
+    /// Stops early, throwing away what was written
+    fn cancel(self: Box<Self>);
+}
+
+/// The frames to render, the playback is stepped through them instead of running in real time
+#[derive(Clone, Debug)]
+pub struct CaptureRange {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f32,
+    /// Seconds
+    pub start: f32,
+    pub end: f32,
+    /// Leaves out the background colour, so modules keep their alpha for overlaying elsewhere
+    pub transparent: bool,
+}
+
+impl CaptureRange {
+    pub fn frames(&self) -> u32 {
+        ((self.end - self.start) * self.frame_rate).round().max(1.0) as u32
+    }
+}
+
+/// How smoothly the last captured frame leads back into the first, when the capture plays as a loop
+#[derive(Clone, Copy, Debug)]
+pub struct LoopSeam {
+    /// Mean change from the last frame to the first, from 0 to 1
+    pub jump: f32,
+    /// Mean change between neighbouring frames
+    pub step: f32,
+}
+
+impl LoopSeam {
+    /// The wrap around doesn't stand out from the frames around it
+    pub fn is_perfect(&self) -> bool {
+        self.jump <= self.step * 2.0 + 1.0 / 255.0
+    }
+}
+
+/// Part of the pixels of a frame, enough to tell how much it changed
+fn seam_sample(pixels: &[u8]) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .step_by(SEAM_SAMPLE_STRIDE)
+        .flatten()
+        .copied()
+        .collect()
+}
+
+fn difference(a: &[u8], b: &[u8]) -> f32 {
+    let total: u64 = a.iter().zip(b).map(|(a, b)| a.abs_diff(*b) as u64).sum();
+    total as f32 / (a.len().max(1) as f32 * 255.0)
+}
+
+/// Follows the captured frames to tell how they loop
+#[derive(Default)]
+struct SeamCheck {
+    /// Samples of the first and the latest frame
+    first: Option<Vec<u8>>,
+    last: Option<Vec<u8>>,
+    /// Summed change between neighbouring frames
+    steps: f32,
+    frames: u32,
+}
+
+impl SeamCheck {
+    fn add(&mut self, pixels: &[u8]) {
+        let sample = seam_sample(pixels);
+        if let Some(last) = &self.last {
+            self.steps += difference(last, &sample);
+        }
+        if self.first.is_none() {
+            self.first = Some(sample.clone());
+        }
+        self.last = Some(sample);
+        self.frames += 1;
+    }
+
+    fn seam(&self) -> Option<LoopSeam> {
+        let (first, last) = (self.first.as_ref()?, self.last.as_ref()?);
+        (self.frames >= 2).then(|| LoopSeam {
+            jump: difference(last, first),
+            step: self.steps / (self.frames - 1) as f32,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Stage {
+    Settle(u8),
+    /// The readback was requested this update
+    Requested,
+    Reading,
+}
+
+struct CaptureJob {
+    name: String,
+    range: CaptureRange,
+    sink: Box<dyn FrameSink>,
+    next: u32,
+    stage: Stage,
+    camera: Option<(Entity, Handle<Image>)>,
+    /// The playback to go back to afterwards
+    playback: Option<(f32, bool, f32)>,
+    seam: SeamCheck,
+}
+
+/// Whether gizmos were enabled before captures and snapshots hid them from their cameras.
+/// Shared, so whichever of them ends last puts back the setting from before the first one started.
+#[derive(Resource, Default)]
+pub struct HiddenGizmos(Option<bool>);
+
+impl HiddenGizmos {
+    pub fn hide(&mut self, gizmos: &mut GizmoConfigStore) {
+        let config = gizmos.config_mut::<DefaultGizmoConfigGroup>().0;
+        self.0.get_or_insert(config.enabled);
+        config.enabled = false;
+    }
+
+    pub fn restore(&mut self, gizmos: &mut GizmoConfigStore) {
+        if let Some(enabled) = self.0.take() {
+            gizmos.config_mut::<DefaultGizmoConfigGroup>().0.enabled = enabled;
+        }
+    }
+}
+
+/// A capture with all of its frames written, waiting for its sink to complete the output
+struct FinishingJob {
+    name: String,
+    frames: u32,
+    sink: Box<dyn FrameSink>,
+}
+
+/// Renders the composition frame by frame from an offscreen camera at the world origin
+/// and reads every frame back for a [`FrameSink`]
+#[derive(Resource, Default)]
+pub struct FrameCapture {
+    job: Option<CaptureJob>,
+    finishing: Option<FinishingJob>,
+    /// Why the running capture has to stop
+    stop: Option<String>,
+    /// How the last capture ended
+    pub result: Option<Result<String, String>>,
+    /// How the last finished capture loops
+    pub seam: Option<LoopSeam>,
+}
+
+impl FrameCapture {
+    /// Starts capturing, unless a capture is running already
+    pub fn start(&mut self, name: String, range: CaptureRange, sink: Box<dyn FrameSink>) {
+        if self.job.is_some() || self.finishing.is_some() {
+            sink.cancel();
+            warn!("a capture is running already");
+            return;
+        }
+        info!("capturing {} frames for {name}", range.frames());
+        self.stop = None;
+        self.result = None;
+        self.seam = None;
+        self.job = Some(CaptureJob {
+            name,
+            range,
+            sink,
+            next: 0,
+            stage: Stage::Settle(SETTLE_UPDATES),
+            camera: None,
+            playback: None,
+            seam: SeamCheck::default(),
+        });
+    }
+
+    pub fn cancel(&mut self) {
+        self.stop = Some("cancelled".to_string());
+    }
+
+    /// Frames done and frames in total, while capturing
+    pub fn progress(&self) -> Option<(u32, u32)> {
+        self.job.as_ref().map(|job| (job.next, job.range.frames()))
+    }
+
+    /// All frames are captured and the sink is completing the output, like an encoder flushing the file
+    pub fn is_finishing(&self) -> bool {
+        self.finishing.is_some()
+    }
+
+    /// Checks on the sink of the finished capture, and throws the output away when the capture was cancelled
+    fn poll_finishing(&mut self, stop: Option<String>) {
+        let Some(mut finishing) = self.finishing.take() else {
+            return;
+        };
+        let result = match stop {
+            Some(reason) => {
+                finishing.sink.cancel();
+                Err(reason)
+            }
+            None => match finishing.sink.finish() {
+                Some(result) => {
+                    result.map(|()| format!("{} frames to {}", finishing.frames, finishing.name))
+                }
+                None => {
+                    self.finishing = Some(finishing);
+                    return;
+                }
+            },
+        };
+        match &result {
+            Ok(done) => info!("captured {done}"),
+            Err(err) => warn!("capture of {} stopped: {err}", finishing.name),
+        }
+        self.result = Some(result);
+    }
+}
+
+pub struct FrameCapturePlugin;
+
+impl Plugin for FrameCapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameCapture>()
+            .init_resource::<HiddenGizmos>()
+            .add_systems(First, step_capture.after(crate::playback::advance_playback));
+    }
+}
+
+/// Spawns an offscreen camera rendering the composition around the world origin into a new image of `size`,
+/// which can be read back
+pub fn spawn_capture_camera(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    size: UVec2,
+    transparent: bool,
+) -> (Entity, Handle<Image>) {
+    let mut image = Image::new_target_texture(size.x, size.y, TextureFormat::Rgba8UnormSrgb, None);
+    image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+    let image = images.add(image);
+    let camera = commands
+        .spawn((
+            Camera2d,
+            Camera {
+                // before the window's camera, so the editor UI stays there
+                order: -1,
+                clear_color: if transparent {
+                    ClearColorConfig::Custom(Color::NONE)
+                } else {
+                    ClearColorConfig::Default
+                },
+                ..default()
+            },
+            RenderTarget::Image(image.clone().into()),
+            // an empty chain turns the premultiplied composite into straight alpha
+            ShaderChainCamera::default(),
+        ))
+        .id();
+    (camera, image)
+}
+
+/// Spawns the capture camera and sets the playback time of the frame being captured
+#[allow(clippy::too_many_arguments)]
+fn step_capture(
+    mut commands: Commands,
+    mut capture: ResMut<FrameCapture>,
+    mut playback: ResMut<Playback>,
+    mut images: ResMut<Assets<Image>>,
+    mut gizmos: ResMut<GizmoConfigStore>,
+    mut hidden: ResMut<HiddenGizmos>,
+    analysis: Res<AudioAnalysis>,
+    cameras: Query<(), With<Camera>>,
+    snapshots: Query<(), With<PendingSnapshot>>,
+) {
+    let stop = capture.stop.take();
+    if capture.finishing.is_some() {
+        capture.poll_finishing(stop);
+        return;
+    }
+    let Some(job) = capture.job.as_mut() else {
+        return;
+    };
+
+    let ended = if let Some(reason) = stop {
+        Some(Err(reason))
+    } else if job
+        .camera
+        .as_ref()
+        .is_some_and(|(camera, _)| !cameras.contains(*camera))
+    {
+        Some(Err(
+            "the capture camera was despawned by a restart".to_string()
+        ))
+    } else if job.next >= job.range.frames() {
+        Some(Ok(()))
+    } else {
+        None
+    };
+    if let Some(ended) = ended {
+        let job = capture.job.take().unwrap();
+        if ended.is_ok() {
+            capture.seam = job.seam.seam();
+            if let Some(seam) = capture.seam.filter(|seam| !seam.is_perfect()) {
+                warn!(
+                    "{} doesn't loop seamlessly, going from the last frame back to the first changes {:.1}% where frames change {:.1}% on average",
+                    job.name,
+                    seam.jump * 100.0,
+                    seam.step * 100.0
+                );
+            }
+        }
+        end_capture(&mut commands, &job, &mut playback);
+        // snapshots still rendering restore the gizmos once they are done
+        if snapshots.is_empty() {
+            hidden.restore(&mut gizmos);
+        }
+        match ended {
+            Ok(()) => {
+                capture.finishing = Some(FinishingJob {
+                    name: job.name,
+                    frames: job.next,
+                    sink: job.sink,
+                });
+                capture.poll_finishing(None);
+            }
+            Err(err) => {
+                warn!("capture of {} stopped: {err}", job.name);
+                job.sink.cancel();
+                capture.result = Some(Err(err));
+            }
+        }
+        return;
+    }
+
+    if job.camera.is_none() {
+        let size = UVec2::new(job.range.width, job.range.height);
+        let (camera, image) =
+            spawn_capture_camera(&mut commands, &mut images, size, job.range.transparent);
+        commands.entity(camera).observe(read_frame);
+        job.camera = Some((camera, image));
+        job.playback = Some((playback.time, playback.playing, playback.frame_rate));
+        hidden.hide(&mut gizmos);
+    }
+
+    playback.playing = false;
+    playback.frame_rate = job.range.frame_rate;
+    let mut time = job.range.start + job.next as f32 / job.range.frame_rate;
+    if let Some(duration) = playback.loop_duration
+        && duration > 0.0
+    {
+        time %= duration;
+    }
+    playback.time = time;
+
+    let (camera, image) = job.camera.clone().unwrap();
+    job.stage = match job.stage {
+        // waits for the sink to catch up, and for the audio features at the capture frame rate
+        Stage::Settle(n) if !job.sink.is_ready() || !analysis.is_ready(job.range.frame_rate) => {
+            Stage::Settle(n)
+        }
+        Stage::Settle(0) => {
+            commands.entity(camera).insert(Readback::texture(image));
+            Stage::Requested
+        }
+        Stage::Settle(n) => Stage::Settle(n - 1),
+        // only the update that requested it is read back
+        Stage::Requested => {
+            commands.entity(camera).remove::<Readback>();
+            Stage::Reading
+        }
+        Stage::Reading => Stage::Reading,
+    };
+}
+
+fn read_frame(frame: On<ReadbackComplete>, mut capture: ResMut<FrameCapture>) {
+    let Some(job) = capture.job.as_mut() else {
+        return;
+    };
+    if !matches!(job.stage, Stage::Requested | Stage::Reading) {
+        return;
+    }
+
+    let pixels = unpad_rows(&frame.data, job.range.width, job.range.height);
+
+    job.seam.add(&pixels);
+    match job.sink.write(pixels) {
+        Ok(()) => {
+            job.next += 1;
+            job.stage = Stage::Settle(SETTLE_UPDATES);
+        }
+        Err(err) => {
+            let reason = format!("frame {}: {err}", job.next);
+            capture.stop = Some(reason);
+        }
+    }
+}
+
+/// Tightly packed RGBA rows from a texture readback, whose rows are padded to the copy alignment
+pub fn unpad_rows(data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let row = width as usize * 4;
+    let padded = data.len() / (height as usize).max(1);
+    if padded == row {
+        return data.to_vec();
+    }
+    data.chunks(padded)
+        .flat_map(|chunk| &chunk[..row])
+        .copied()
+        .collect()
+}
+
+/// Puts the playback back the way it was before capturing
+fn end_capture(commands: &mut Commands, job: &CaptureJob, playback: &mut Playback) {
+    if let Some((camera, _)) = &job.camera {
+        commands.entity(*camera).try_despawn();
+    }
+    if let Some((time, playing, frame_rate)) = job.playback {
+        playback.time = time;
+        playback.playing = playing;
+        playback.frame_rate = frame_rate;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame of 16 pixels all of this grey
+    fn frame(grey: u8) -> Vec<u8> {
+        [grey, grey, grey, 255].repeat(16)
+    }
+
+    fn seam_of(frames: impl IntoIterator<Item = Vec<u8>>) -> LoopSeam {
+        let mut check = SeamCheck::default();
+        for frame in frames {
+            check.add(&frame);
+        }
+        check.seam().unwrap()
+    }
+
+    #[test]
+    fn samples_every_few_pixels() {
+        let pixels: Vec<u8> = (0..64).collect();
+        assert_eq!(
+            seam_sample(&pixels),
+            [0, 1, 2, 3, 28, 29, 30, 31, 56, 57, 58, 59]
+        );
+    }
+
+    #[test]
+    fn needs_two_frames() {
+        let mut check = SeamCheck::default();
+        assert!(check.seam().is_none());
+        check.add(&frame(0));
+        assert!(check.seam().is_none());
+    }
+
+    #[test]
+    fn same_first_and_last_frame_loop() {
+        let seam = seam_of([frame(0), frame(100), frame(0)]);
+        assert_eq!(seam.jump, 0.0);
+        assert!(seam.is_perfect());
+        // a still image loops too
+        assert!(seam_of([frame(50), frame(50)]).is_perfect());
+    }
+
+    #[test]
+    fn a_hard_cut_does_not_loop() {
+        let seam = seam_of([frame(0), frame(0), frame(0), frame(255)]);
+        assert_eq!(seam.jump, 0.75);
+        assert_eq!(seam.step, 0.25);
+        assert!(!seam.is_perfect());
+    }
+
+    #[test]
+    fn a_gradient_leading_back_smoothly_loops() {
+        // 0 to 120 and back in steps of 10, the last frame is one step before the first
+        let greys = (0..=120).step_by(10).chain((10..120).step_by(10).rev());
+        let back_and_forth = seam_of(greys.map(frame));
+        assert!((back_and_forth.step - 10.0 / 255.0 * 0.75).abs() < 1e-6);
+        assert!(back_and_forth.is_perfect());
+
+        // only going up jumps back from the top
+        let up = seam_of((0..=120).step_by(10).map(frame));
+        assert!(!up.is_perfect());
+    }
+
+    #[test]
+    fn removes_the_row_padding_of_readbacks() {
+        // 3 pixels are 12 bytes, rows of the copy are 256 byte aligned
+        let rows: Vec<Vec<u8>> = (0..2).map(|row| vec![row + 1; 12]).collect();
+        let padded: Vec<u8> = rows
+            .iter()
+            .flat_map(|row| row.iter().copied().chain([0; 244]))
+            .collect();
+        assert_eq!(unpad_rows(&padded, 3, 2), rows.concat());
+    }
+
+    #[test]
+    fn keeps_rows_without_padding() {
+        // 64 pixels fill the alignment exactly
+        let pixels: Vec<u8> = (0..64 * 4 * 3).map(|i| i as u8).collect();
+        assert_eq!(unpad_rows(&pixels, 64, 3), pixels);
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+
+use bevy::asset::io::file::FileAssetReader;
+use bevy::prelude::*;
+
+use crate::module::SnapshotData;
+use crate::project::{PLAYER_PROJECT_ASSET, Project, ProjectResources};
+
+pub mod capture;
+pub mod sequence;
+pub mod snapshot;
+pub mod video;
+
+pub use capture::{CaptureRange, FrameCapture};
+pub use snapshot::{CaptureFrame, CaptureModuleFrame, Snapshots};
+pub use video::{ExportVideo, GifDither, GifPalette, VideoCodec};
+
+/// Where `just build-web-player` puts the wasm player, next to the assets directory
+pub const WEB_PLAYER_DIR: &str = "out";
+/// The files wasm-bindgen generates for the player
+const WEB_PLAYER_FILES: [&str; 2] = ["bevycargo.js", "bevycargo_bg.wasm"];
+
+/// Publishes the composition as a standalone web page: the wasm player, the assets it uses,
+/// the project and an index.html that starts it
+#[derive(Event, Clone, Debug)]
+pub struct ExportWeb {
+    pub dir: PathBuf,
+    pub autoplay: bool,
+    /// Playback loops every this many seconds
+    pub loop_duration: Option<f32>,
+}
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((capture::FrameCapturePlugin, snapshot::SnapshotPlugin))
+            .add_observer(export_web)
+            .add_observer(video::export_video);
+    }
+}
+
+fn export_web(
+    export: On<ExportWeb>,
+    modules: Query<SnapshotData>,
+    resources: ProjectResources,
+) {
+    let project = Project::take(&modules, &resources);
+    match write_web_export(&export, &project) {
+        Ok(()) => info!(
+            "exported {} modules for the web to {}",
+            project.modules.len(),
+            export.dir.display()
+        ),
+        Err(err) => error!("web export to {} failed: {err}", export.dir.display()),
+    }
+}
+
+fn write_web_export(export: &ExportWeb, project: &Project) -> Result<(), String> {
+    let base = FileAssetReader::get_base_path();
+    let player = base.join(WEB_PLAYER_DIR);
+    if let Some(missing) = WEB_PLAYER_FILES
+        .iter()
+        .find(|file| !player.join(file).exists())
+    {
+        return Err(format!(
+            "{missing} is missing from {}, build the player with `just build-web-player` first",
+            player.display()
+        ));
+    }
+
+    let assets = export.dir.join("assets");
+    copy_dir(&base.join("assets"), &assets).map_err(|err| format!("copying assets: {err}"))?;
+    for file in WEB_PLAYER_FILES {
+        std::fs::copy(player.join(file), export.dir.join(file))
+            .map_err(|err| format!("copying {file}: {err}"))?;
+    }
+
+    let text = ron::ser::to_string_pretty(project, default()).map_err(|err| err.to_string())?;
+    std::fs::write(assets.join(PLAYER_PROJECT_ASSET), text)
+        .map_err(|err| format!("writing the project: {err}"))?;
+
+    std::fs::write(export.dir.join("index.html"), index_html(export))
+        .map_err(|err| format!("writing index.html: {err}"))
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// The page hosting the player, `?autoplay=0` and `?loop=<seconds>` override the exported settings
+fn index_html(export: &ExportWeb) -> String {
+    let loop_duration = export
+        .loop_duration
+        .map_or("null".to_string(), |duration| duration.to_string());
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+
+<head>
+  <meta charset="utf-8">
+  <style>
+    html, body {{ margin: 0; width: 100%; height: 100%; overflow: hidden; background: #000; }}
+    #bumper {{ width: 100%; height: 100%; display: block; }}
+  </style>
+</head>
+
+<body>
+  <canvas id="bumper"></canvas>
+  <script type="module">
+    import init, * as bumper from './{js}'
+
+    const query = new URLSearchParams(location.search);
+    const autoplay = query.has("autoplay") ? query.get("autoplay") !== "0" : {autoplay};
+    const loop = query.has("loop") ? Number(query.get("loop")) || null : {loop_duration};
+
+    // the app starts running when init throws winit's control flow exception, the API works from then on
+    await init().catch((error) => {{
+      if (!error.message.startsWith("Using exceptions for control flow, don't mind me. This isn't actually an error!")) {{
+        throw error;
+      }}
+    }});
+    window.bumper = bumper;
+    bumper.setLoop(loop ?? undefined);
+    if (!autoplay) {{
+      bumper.pause();
+    }}
+  </script>
+</body>
+
+</html>
+"#,
+        js = WEB_PLAYER_FILES[0],
+        autoplay = export.autoplay,
+    )
+}
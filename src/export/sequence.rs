@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use bevy::math::UVec2;
+
+use super::capture::FrameSink;
+
+/// Writes tightly packed RGBA rows to a PNG file with alpha
+pub fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|err| format!("creating {}: {err}", dir.display()))?;
+    }
+    let file = File::create(path).map_err(|err| format!("creating {}: {err}", path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(pixels)?;
+            writer.finish()
+        })
+        .map_err(|err| format!("writing {}: {err}", path.display()))
+}
+
+/// Writes every frame as a numbered PNG with alpha into a directory, without needing ffmpeg
+pub struct PngSequenceSink {
+    dir: PathBuf,
+    width: u32,
+    height: u32,
+    written: Vec<PathBuf>,
+}
+
+impl PngSequenceSink {
+    pub fn create(dir: &Path, width: u32, height: u32) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|err| format!("creating {}: {err}", dir.display()))?;
+        Ok(PngSequenceSink {
+            dir: dir.to_path_buf(),
+            width,
+            height,
+            written: vec![],
+        })
+    }
+}
+
+impl FrameSink for PngSequenceSink {
+    fn write(&mut self, frame: Vec<u8>) -> Result<(), String> {
+        let path = self
+            .dir
+            .join(format!("frame_{:05}.png", self.written.len()));
+        write_png(&path, self.width, self.height, &frame)?;
+        self.written.push(path);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Option<Result<(), String>> {
+        Some(Ok(()))
+    }
+
+    fn cancel(self: Box<Self>) {
+        for path in self.written {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Averages the source pixels every target pixel covers
+pub fn downscale(pixels: &[u8], size: UVec2, scaled: UVec2) -> Vec<u8> {
+    let span = |i: u32, from: u32, to: u32| {
+        let start = (i as u64 * from as u64 / to as u64) as usize;
+        let end = ((i as u64 + 1) * from as u64 / to as u64) as usize;
+        start..end.max(start + 1)
+    };
+    let mut out = Vec::with_capacity((scaled.x * scaled.y * 4) as usize);
+    for y in 0..scaled.y {
+        let rows = span(y, size.y, scaled.y);
+        for x in 0..scaled.x {
+            let columns = span(x, size.x, scaled.x);
+            let mut sum = [0u32; 4];
+            for row in rows.clone() {
+                let start = (row * size.x as usize + columns.start) * 4;
+                let end = (row * size.x as usize + columns.end) * 4;
+                for pixel in pixels[start..end].chunks_exact(4) {
+                    for (sum, value) in sum.iter_mut().zip(pixel) {
+                        *sum += *value as u32;
+                    }
+                }
+            }
+            let count = (rows.len() * columns.len()) as u32;
+            out.extend(sum.map(|sum| (sum / count) as u8));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row of pixels with these greys
+    fn row(greys: &[u8]) -> Vec<u8> {
+        greys
+            .iter()
+            .flat_map(|grey| [*grey, *grey, *grey, 255])
+            .collect()
+    }
+
+    #[test]
+    fn averages_whole_blocks() {
+        let pixels = [row(&[0, 100, 10, 20]), row(&[200, 100, 30, 40])].concat();
+        let scaled = downscale(&pixels, UVec2::new(4, 2), UVec2::new(2, 1));
+        assert_eq!(scaled, row(&[100, 25]));
+    }
+
+    #[test]
+    fn uneven_factors_split_the_source_unevenly() {
+        // 3 to 2: the first pixel covers one source pixel, the second the other two
+        let scaled = downscale(&row(&[10, 20, 40]), UVec2::new(3, 1), UVec2::new(2, 1));
+        assert_eq!(scaled, row(&[10, 30]));
+        // 5 to 3 covers 1, 2 and 2 pixels
+        let scaled = downscale(
+            &row(&[10, 20, 40, 60, 80]),
+            UVec2::new(5, 1),
+            UVec2::new(3, 1),
+        );
+        assert_eq!(scaled, row(&[10, 30, 70]));
+    }
+
+    #[test]
+    fn every_pixel_covers_at_least_one_source_pixel() {
+        // scaling up, where some target pixels start and end inside the same source pixel
+        let scaled = downscale(&row(&[10, 20]), UVec2::new(2, 1), UVec2::new(3, 1));
+        assert_eq!(scaled, row(&[10, 10, 20]));
+        let column = [row(&[10]), row(&[20])].concat();
+        let scaled = downscale(&column, UVec2::new(1, 2), UVec2::new(1, 3));
+        assert_eq!(scaled, [row(&[10]), row(&[10]), row(&[20])].concat());
+    }
+
+    #[test]
+    fn keeps_the_same_size() {
+        let pixels = [row(&[1, 2, 3]), row(&[4, 5, 6])].concat();
+        assert_eq!(
+            downscale(&pixels, UVec2::new(3, 2), UVec2::new(3, 2)),
+            pixels
+        );
+    }
+}
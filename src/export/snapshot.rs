@@ -0,0 +1,309 @@
+use std::path::PathBuf;
+
+use bevy::asset::io::file::FileAssetReader;
+use bevy::camera::RenderTarget;
+use bevy::gizmos::config::GizmoConfigStore;
+use bevy::prelude::*;
+use bevy::render::gpu_readback::{Readback, ReadbackComplete};
+
+use super::capture::{
+    FrameCapture, HiddenGizmos, SETTLE_UPDATES, spawn_capture_camera, unpad_rows,
+};
+use super::sequence::{downscale, write_png};
+use crate::common::AppState;
+use crate::keymap::{Action, action_just_pressed};
+use crate::module::ModuleWithParts;
+use crate::playback::Playback;
+use crate::project::PROJECT_PATH;
+
+/// Width of the project thumbnail, the height follows the composition
+const THUMBNAIL_WIDTH: u32 = 320;
+
+/// Where single frames are captured to and at what size
+#[derive(Resource)]
+pub struct Snapshots {
+    pub dir: PathBuf,
+    /// The composition resolution, captured around the world origin
+    pub size: UVec2,
+    pub transparent: bool,
+    /// How the last capture of the composition or a module ended
+    pub result: Option<Result<String, String>>,
+    /// How saving the last project thumbnail ended
+    pub thumbnail: Option<Result<String, String>>,
+}
+
+impl Default for Snapshots {
+    fn default() -> Self {
+        Snapshots {
+            dir: PathBuf::from("export/frames"),
+            size: UVec2::new(1920, 1080),
+            transparent: false,
+            result: None,
+            thumbnail: None,
+        }
+    }
+}
+
+/// Writes the current frame of the composition to a PNG in [`Snapshots::dir`]
+#[derive(Event, Clone, Debug)]
+pub struct CaptureFrame;
+
+/// Writes the current content of a module's render target to a PNG in [`Snapshots::dir`]
+#[derive(Event, Clone, Debug)]
+pub struct CaptureModuleFrame {
+    pub module: Entity,
+}
+
+/// A frame of the composition its own capture camera renders, read back once the camera has settled.
+/// Separate from [`FrameCapture`], so saving a picture doesn't disturb a running export or its result.
+#[derive(Component)]
+pub(super) struct PendingSnapshot {
+    path: PathBuf,
+    size: UVec2,
+    /// The size the picture is scaled down to
+    scaled: UVec2,
+    image: Handle<Image>,
+    /// Updates left before the readback is requested, none once it is
+    settle: Option<u8>,
+    thumbnail: bool,
+}
+
+/// The module frame a readback entity is waiting for
+#[derive(Component)]
+struct PendingModuleFrame {
+    path: PathBuf,
+    size: UVec2,
+}
+
+pub struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Snapshots>()
+            .add_observer(capture_frame)
+            .add_observer(capture_module_frame)
+            .add_systems(
+                Update,
+                (
+                    press_capture_frame.run_if(action_just_pressed(Action::CaptureFrame)),
+                    capture_thumbnail.run_if(action_just_pressed(Action::Save)),
+                    settle_snapshots,
+                )
+                    .run_if(in_state(AppState::Running)),
+            )
+            // also when a restart tore down the snapshot cameras before they were read back
+            .add_systems(Last, restore_gizmos);
+    }
+}
+
+fn press_capture_frame(mut commands: Commands) {
+    commands.trigger(CaptureFrame);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_snapshot(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    gizmos: &mut GizmoConfigStore,
+    hidden: &mut HiddenGizmos,
+    path: PathBuf,
+    size: UVec2,
+    scaled: UVec2,
+    transparent: bool,
+    thumbnail: bool,
+) {
+    let (camera, image) = spawn_capture_camera(commands, images, size, transparent);
+    commands
+        .entity(camera)
+        .insert(PendingSnapshot {
+            path,
+            size,
+            scaled: scaled.min(size).max(UVec2::ONE),
+            image,
+            settle: Some(SETTLE_UPDATES),
+            thumbnail,
+        })
+        .observe(save_snapshot);
+    hidden.hide(gizmos);
+}
+
+fn capture_frame(
+    _: On<CaptureFrame>,
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut gizmos: ResMut<GizmoConfigStore>,
+    mut hidden: ResMut<HiddenGizmos>,
+    snapshots: Res<Snapshots>,
+    playback: Res<Playback>,
+) {
+    let path = snapshots
+        .dir
+        .join(format!("frame_{:06}.png", playback.frame()));
+    spawn_snapshot(
+        &mut commands,
+        &mut images,
+        &mut gizmos,
+        &mut hidden,
+        path,
+        snapshots.size,
+        snapshots.size,
+        snapshots.transparent,
+        false,
+    );
+}
+
+/// Saves a small picture of the composition next to the project, for browsing projects
+fn capture_thumbnail(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut gizmos: ResMut<GizmoConfigStore>,
+    mut hidden: ResMut<HiddenGizmos>,
+    snapshots: Res<Snapshots>,
+) {
+    let path = FileAssetReader::get_base_path()
+        .join(PROJECT_PATH)
+        .with_extension("png");
+    let size = snapshots.size;
+    let scaled = UVec2::new(
+        THUMBNAIL_WIDTH,
+        (THUMBNAIL_WIDTH as u64 * size.y as u64 / size.x.max(1) as u64) as u32,
+    );
+    spawn_snapshot(
+        &mut commands,
+        &mut images,
+        &mut gizmos,
+        &mut hidden,
+        path,
+        size,
+        scaled,
+        false,
+        true,
+    );
+}
+
+/// Requests the readback of snapshot cameras once modules rendering through their own targets have caught up
+fn settle_snapshots(mut commands: Commands, mut pending: Query<(Entity, &mut PendingSnapshot)>) {
+    for (entity, mut snapshot) in pending.iter_mut() {
+        snapshot.settle = match snapshot.settle {
+            Some(0) => {
+                commands
+                    .entity(entity)
+                    .insert(Readback::texture(snapshot.image.clone()));
+                None
+            }
+            Some(n) => Some(n - 1),
+            None => None,
+        };
+    }
+}
+
+/// Shows the gizmos again once no snapshot camera is left and no capture is running
+fn restore_gizmos(
+    pending: Query<(), With<PendingSnapshot>>,
+    capture: Res<FrameCapture>,
+    mut gizmos: ResMut<GizmoConfigStore>,
+    mut hidden: ResMut<HiddenGizmos>,
+) {
+    if pending.is_empty() && capture.progress().is_none() {
+        hidden.restore(&mut gizmos);
+    }
+}
+
+fn save_snapshot(
+    frame: On<ReadbackComplete>,
+    mut commands: Commands,
+    pending: Query<&PendingSnapshot>,
+    mut snapshots: ResMut<Snapshots>,
+) {
+    let Ok(snapshot) = pending.get(frame.entity) else {
+        return;
+    };
+    // the readback repeats every frame until the camera is gone
+    commands.entity(frame.entity).despawn();
+
+    let mut pixels = unpad_rows(&frame.data, snapshot.size.x, snapshot.size.y);
+    if snapshot.scaled != snapshot.size {
+        pixels = downscale(&pixels, snapshot.size, snapshot.scaled);
+    }
+    let result = write_png(
+        &snapshot.path,
+        snapshot.scaled.x,
+        snapshot.scaled.y,
+        &pixels,
+    )
+    .map(|()| snapshot.path.display().to_string());
+    match &result {
+        Ok(path) => info!("captured {path}"),
+        Err(err) => error!("couldn't capture the frame: {err}"),
+    }
+    if snapshot.thumbnail {
+        snapshots.thumbnail = Some(result);
+    } else {
+        snapshots.result = Some(result);
+    }
+}
+
+fn capture_module_frame(
+    capture: On<CaptureModuleFrame>,
+    mut commands: Commands,
+    mut snapshots: ResMut<Snapshots>,
+    modules: Query<(&Name, &ModuleWithParts)>,
+    targets: Query<&RenderTarget>,
+    images: Res<Assets<Image>>,
+    playback: Res<Playback>,
+) {
+    let target = modules.get(capture.module).ok().and_then(|(name, parts)| {
+        parts.iter().find_map(|part| match targets.get(part) {
+            Ok(RenderTarget::Image(target)) => Some((name, target.handle.clone())),
+            _ => None,
+        })
+    });
+    let Some((name, handle)) = target else {
+        snapshots.result = Some(Err("the module doesn't render into a target".to_string()));
+        return;
+    };
+    let Some(image) = images.get(&handle) else {
+        snapshots.result = Some(Err(format!("the target of {name} isn't loaded")));
+        return;
+    };
+
+    let file_name: String = name
+        .as_str()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = snapshots
+        .dir
+        .join(format!("{file_name}_{:06}.png", playback.frame()));
+    commands
+        .spawn((
+            Readback::texture(handle),
+            PendingModuleFrame {
+                path,
+                size: image.size(),
+            },
+        ))
+        .observe(save_module_frame);
+}
+
+fn save_module_frame(
+    frame: On<ReadbackComplete>,
+    mut commands: Commands,
+    pending: Query<&PendingModuleFrame>,
+    mut snapshots: ResMut<Snapshots>,
+) {
+    let Ok(pending) = pending.get(frame.entity) else {
+        return;
+    };
+    // the readback repeats every frame until the entity is gone
+    commands.entity(frame.entity).despawn();
+
+    let pixels = unpad_rows(&frame.data, pending.size.x, pending.size.y);
+    let result = write_png(&pending.path, pending.size.x, pending.size.y, &pixels)
+        .map(|()| pending.path.display().to_string());
+    match &result {
+        Ok(path) => info!("captured {path}"),
+        Err(err) => error!("couldn't capture the module: {err}"),
+    }
+    snapshots.result = Some(result);
+}
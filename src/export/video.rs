@@ -0,0 +1,390 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{SyncSender, TrySendError, sync_channel};
+use std::thread::JoinHandle;
+
+use bevy::prelude::*;
+
+use super::capture::{CaptureRange, FrameCapture, FrameSink};
+use super::sequence::PngSequenceSink;
+
+/// The encoder, found on the PATH
+const FFMPEG: &str = "ffmpeg";
+/// Frames waiting for the encoder before capturing waits for it
+const QUEUED_FRAMES: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VideoCodec {
+    #[default]
+    H264,
+    H265,
+    Vp9,
+    /// ProRes 4444, what video editors take for overlays
+    ProRes,
+    /// Numbered PNG files in a directory instead of a video
+    PngSequence,
+    /// An animated GIF playing in a loop, reduced to a palette
+    Gif,
+    /// An animated WebP playing in a loop
+    WebP,
+}
+
+impl VideoCodec {
+    pub const ALL: [VideoCodec; 7] = [
+        VideoCodec::H264,
+        VideoCodec::H265,
+        VideoCodec::Vp9,
+        VideoCodec::ProRes,
+        VideoCodec::PngSequence,
+        VideoCodec::Gif,
+        VideoCodec::WebP,
+    ];
+
+    fn encoder(self) -> &'static str {
+        match self {
+            VideoCodec::H264 => "libx264",
+            VideoCodec::H265 => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::ProRes => "prores_ks",
+            VideoCodec::PngSequence => "png",
+            VideoCodec::Gif => "gif",
+            VideoCodec::WebP => "libwebp_anim",
+        }
+    }
+
+    /// The container the codec is written in, none for a directory of frames
+    pub fn extension(self) -> &'static str {
+        match self {
+            VideoCodec::H264 | VideoCodec::H265 => "mp4",
+            VideoCodec::Vp9 => "webm",
+            VideoCodec::ProRes => "mov",
+            VideoCodec::PngSequence => "",
+            VideoCodec::Gif => "gif",
+            VideoCodec::WebP => "webp",
+        }
+    }
+
+    /// Whether a transparent background survives the encoding, GIFs only keep fully transparent pixels
+    pub fn keeps_alpha(self) -> bool {
+        !matches!(self, VideoCodec::H264 | VideoCodec::H265)
+    }
+
+    /// Whether the file plays on repeat, so the loop check matters
+    pub fn loops(self) -> bool {
+        matches!(self, VideoCodec::Gif | VideoCodec::WebP)
+    }
+
+    /// Whether the quality is set by a bitrate
+    pub fn has_bitrate(self) -> bool {
+        matches!(self, VideoCodec::H264 | VideoCodec::H265 | VideoCodec::Vp9)
+    }
+}
+
+impl std::fmt::Display for VideoCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            VideoCodec::H264 => "H.264",
+            VideoCodec::H265 => "H.265",
+            VideoCodec::Vp9 => "VP9",
+            VideoCodec::ProRes => "ProRes 4444",
+            VideoCodec::PngSequence => "PNG sequence",
+            VideoCodec::Gif => "GIF",
+            VideoCodec::WebP => "WebP",
+        })
+    }
+}
+
+/// How colours missing from the palette are approximated by mixing pixels of the ones in it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GifDither {
+    None,
+    /// An ordered pattern, compresses best
+    Bayer,
+    FloydSteinberg,
+    #[default]
+    Sierra,
+}
+
+impl GifDither {
+    pub const ALL: [GifDither; 4] = [
+        GifDither::None,
+        GifDither::Bayer,
+        GifDither::FloydSteinberg,
+        GifDither::Sierra,
+    ];
+
+    fn filter_name(self) -> &'static str {
+        match self {
+            GifDither::None => "none",
+            GifDither::Bayer => "bayer",
+            GifDither::FloydSteinberg => "floyd_steinberg",
+            GifDither::Sierra => "sierra2_4a",
+        }
+    }
+}
+
+/// The palette GIF frames are quantised to, made from the colours of the whole loop
+#[derive(Clone, Debug)]
+pub struct GifPalette {
+    /// From 2 to 256
+    pub colors: u16,
+    pub dither: GifDither,
+}
+
+impl Default for GifPalette {
+    fn default() -> Self {
+        GifPalette {
+            colors: 256,
+            dither: GifDither::default(),
+        }
+    }
+}
+
+/// Renders a range of the composition into a video file by piping the frames into ffmpeg,
+/// or into a directory of PNG files
+#[derive(Event, Clone, Debug)]
+pub struct ExportVideo {
+    pub path: PathBuf,
+    pub codec: VideoCodec,
+    /// Kilobits per second
+    pub bitrate: u32,
+    pub palette: GifPalette,
+    /// WebP quality from 0 to 100
+    pub quality: u8,
+    pub range: CaptureRange,
+}
+
+pub fn export_video(export: On<ExportVideo>, mut capture: ResMut<FrameCapture>) {
+    let mut range = export.range.clone();
+    if range.transparent && !export.codec.keeps_alpha() {
+        capture.result = Some(Err(format!(
+            "{} can't keep a transparent background, use VP9, ProRes, WebP, GIF or a PNG sequence",
+            export.codec
+        )));
+        return;
+    }
+
+    let sink: Result<Box<dyn FrameSink>, String> = if export.codec == VideoCodec::PngSequence {
+        PngSequenceSink::create(&export.path, range.width, range.height)
+            .map(|sink| Box::new(sink) as Box<dyn FrameSink>)
+    } else {
+        // the 4:2:0 chroma subsampling every player supports needs even sizes
+        range.width += range.width % 2;
+        range.height += range.height % 2;
+        FfmpegSink::spawn(&ffmpeg_args(&export, &range), &export.path)
+            .map(|sink| Box::new(sink) as Box<dyn FrameSink>)
+    };
+
+    match sink {
+        Ok(sink) => capture.start(export.path.display().to_string(), range, sink),
+        Err(err) => {
+            error!("couldn't start the video export: {err}");
+            capture.result = Some(Err(err));
+        }
+    }
+}
+
+fn ffmpeg_args(export: &ExportVideo, range: &CaptureRange) -> Vec<String> {
+    let codec = export.codec;
+    let mut args = vec![
+        "-f".to_string(),
+        "rawvideo".to_string(),
+        "-pix_fmt".to_string(),
+        "rgba".to_string(),
+        "-s".to_string(),
+        format!("{}x{}", range.width, range.height),
+        "-r".to_string(),
+        range.frame_rate.to_string(),
+        "-i".to_string(),
+        "-".to_string(),
+        "-c:v".to_string(),
+        codec.encoder().to_string(),
+    ];
+    if codec.has_bitrate() {
+        args.extend(["-b:v".to_string(), format!("{}k", export.bitrate)]);
+    }
+    if codec.loops() {
+        args.extend(["-loop".to_string(), "0".to_string()]);
+    }
+    if codec == VideoCodec::Gif {
+        // the palette is made from all frames before they're mapped onto it
+        let palette = &export.palette;
+        args.extend([
+            "-filter_complex".to_string(),
+            format!(
+                "split[frames][stats];[stats]palettegen=max_colors={}:reserve_transparent={}:stats_mode=full[palette];[frames][palette]paletteuse=dither={}",
+                palette.colors.clamp(2, 256),
+                range.transparent as u8,
+                palette.dither.filter_name()
+            ),
+        ]);
+        return args;
+    }
+    if codec == VideoCodec::WebP {
+        args.extend(["-quality".to_string(), export.quality.min(100).to_string()]);
+    }
+    let pix_fmt = match (codec, range.transparent) {
+        (VideoCodec::ProRes, true) => "yuva444p10le",
+        (VideoCodec::ProRes, false) => "yuv444p10le",
+        (_, true) => "yuva420p",
+        (_, false) => "yuv420p",
+    };
+    args.extend(["-pix_fmt".to_string(), pix_fmt.to_string()]);
+    match codec {
+        // lets Apple's players recognise the stream
+        VideoCodec::H265 => args.extend(["-tag:v".to_string(), "hvc1".to_string()]),
+        VideoCodec::ProRes => args.extend(["-profile:v".to_string(), "4444".to_string()]),
+        // libvpx can't encode the alpha plane with alternate reference frames
+        VideoCodec::Vp9 if range.transparent => {
+            args.extend(["-auto-alt-ref".to_string(), "0".to_string()])
+        }
+        _ => {}
+    }
+    args
+}
+
+/// An ffmpeg process reading raw frames from its stdin, fed from a thread through a short queue.
+/// While the queue is full the sink isn't ready, so the capture holds the next frame instead of blocking the editor.
+pub struct FfmpegSink {
+    path: PathBuf,
+    child: Child,
+    frames: Option<SyncSender<Vec<u8>>>,
+    /// A written frame the queue had no room for yet
+    waiting: Option<Vec<u8>>,
+    writer: Option<JoinHandle<std::io::Result<()>>>,
+    /// Reads stderr as it comes, a full pipe would block ffmpeg
+    errors: Option<JoinHandle<String>>,
+}
+
+impl FfmpegSink {
+    /// Starts ffmpeg with the input and output options in `args`, writing to `path`
+    pub fn spawn(args: &[String], path: &Path) -> Result<Self, String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|err| format!("creating {}: {err}", dir.display()))?;
+        }
+        let mut child = Command::new(FFMPEG)
+            .args(["-y", "-loglevel", "error", "-nostats"])
+            .args(args)
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => {
+                    format!("{FFMPEG} wasn't found, install it and make sure it's on the PATH")
+                }
+                _ => format!("starting {FFMPEG}: {err}"),
+            })?;
+
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let errors = std::thread::spawn(move || {
+            let mut text = String::new();
+            let _ = stderr.read_to_string(&mut text);
+            text
+        });
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let (frames, queue) = sync_channel::<Vec<u8>>(QUEUED_FRAMES);
+        let writer = std::thread::spawn(move || {
+            let mut stdin: ChildStdin = stdin;
+            for frame in queue {
+                stdin.write_all(&frame)?;
+            }
+            Ok(())
+        });
+
+        Ok(FfmpegSink {
+            path: path.to_path_buf(),
+            child,
+            frames: Some(frames),
+            waiting: None,
+            writer: Some(writer),
+            errors: Some(errors),
+        })
+    }
+
+    /// What ffmpeg complained about, once it has exited
+    fn errors(&mut self) -> String {
+        let text = self
+            .errors
+            .take()
+            .and_then(|errors| errors.join().ok())
+            .unwrap_or_default();
+        text.trim().to_string()
+    }
+}
+
+impl FrameSink for FfmpegSink {
+    fn write(&mut self, frame: Vec<u8>) -> Result<(), String> {
+        let sent = match &self.frames {
+            Some(frames) => frames.try_send(frame),
+            None => Err(TrySendError::Disconnected(frame)),
+        };
+        match sent {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(frame)) => {
+                self.waiting = Some(frame);
+                Ok(())
+            }
+            // the writer thread stopped, ffmpeg most likely exited
+            Err(TrySendError::Disconnected(_)) => {
+                let _ = self.child.wait();
+                Err(format!("{FFMPEG} stopped: {}", self.errors()))
+            }
+        }
+    }
+
+    fn is_ready(&mut self) -> bool {
+        let Some(frame) = self.waiting.take() else {
+            return true;
+        };
+        match self.frames.as_ref().map(|frames| frames.try_send(frame)) {
+            Some(Err(TrySendError::Full(frame))) => {
+                self.waiting = Some(frame);
+                false
+            }
+            // a stopped writer is reported by the next write or by finish
+            _ => true,
+        }
+    }
+
+    fn finish(&mut self) -> Option<Result<(), String>> {
+        if !self.is_ready() {
+            return None;
+        }
+        // closing stdin ends the input, ffmpeg then finishes the file
+        self.frames = None;
+        if self
+            .writer
+            .as_ref()
+            .is_some_and(|writer| !writer.is_finished())
+        {
+            return None;
+        }
+        let status = match self.child.try_wait() {
+            Ok(Some(status)) => status,
+            Ok(None) => return None,
+            Err(err) => return Some(Err(format!("waiting for {FFMPEG}: {err}"))),
+        };
+        if !status.success() {
+            return Some(Err(format!("{FFMPEG} failed: {}", self.errors())));
+        }
+        match self.writer.take().map(JoinHandle::join) {
+            Some(Ok(Err(err))) => Some(Err(format!("writing frames to {FFMPEG}: {err}"))),
+            _ => Some(Ok(())),
+        }
+    }
+
+    fn cancel(mut self: Box<Self>) {
+        let _ = self.child.kill();
+        self.frames = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
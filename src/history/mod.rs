@@ -0,0 +1,650 @@
+use std::mem::discriminant;
+
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use bevy_egui::{EguiPostUpdateSet, egui};
+
+use crate::common::{AppState, ModuleWin};
+use crate::keymap::{Action, action_just_pressed};
+use crate::module::{
+    DespawnModule, ModuleAnchor, ModuleId, ModuleLayer, ModuleSnapshot, RestoreModule,
+    SHADER_CHAIN_PARAM, SnapshotData, SpawnModuleEvent,
+};
+use crate::params::ModuleParams;
+
+/// Where a module is on the canvas, what the canvas handles, the transform panel and arrange commands edit
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    pub position: Vec2,
+    /// Radians around the anchor
+    pub rotation: f32,
+    pub scale: Vec2,
+    pub anchor: Vec2,
+    pub size: Vec2,
+}
+
+impl Placement {
+    pub fn new(transform: &Transform, anchor: &ModuleAnchor, mw: &ModuleWin) -> Self {
+        Placement {
+            position: transform.translation.truncate(),
+            rotation: transform.rotation.to_euler(EulerRot::XYZ).2,
+            scale: transform.scale.truncate(),
+            anchor: anchor.0,
+            size: Vec2::new(mw.width, mw.height),
+        }
+    }
+
+    fn of(snapshot: &ModuleSnapshot) -> Self {
+        Placement {
+            position: snapshot.position,
+            rotation: snapshot.rotation,
+            scale: snapshot.scale,
+            anchor: snapshot.anchor,
+            size: snapshot.size,
+        }
+    }
+}
+
+/// The part of a module an edit changes, as it was before or after the edit
+#[derive(Clone, Debug, PartialEq)]
+pub enum ModuleState {
+    /// The whole module, `None` while it doesn't exist
+    Module(Option<ModuleSnapshot>),
+    Placement(Placement),
+    Params(ModuleParams),
+    Name(String),
+    Layer(ModuleLayer),
+}
+
+impl ModuleState {
+    /// The same part of the module in `snapshot`, `None` if the module doesn't have it
+    fn of(&self, snapshot: Option<&ModuleSnapshot>) -> Option<ModuleState> {
+        let part = match (self, snapshot) {
+            (ModuleState::Module(_), snapshot) => ModuleState::Module(snapshot.cloned()),
+            (_, None) => return None,
+            (ModuleState::Placement(_), Some(s)) => ModuleState::Placement(Placement::of(s)),
+            (ModuleState::Params(_), Some(s)) => ModuleState::Params(s.params.clone()?),
+            (ModuleState::Name(_), Some(s)) => ModuleState::Name(s.name.clone()),
+            (ModuleState::Layer(_), Some(s)) => ModuleState::Layer(s.layer.clone()),
+        };
+        Some(part)
+    }
+
+    /// Puts this part into the state of a module
+    fn apply(&self, module: &mut Option<ModuleSnapshot>) {
+        match (self, module.as_mut()) {
+            (ModuleState::Module(snapshot), _) => *module = snapshot.clone(),
+            (ModuleState::Placement(placement), Some(s)) => {
+                s.position = placement.position;
+                s.rotation = placement.rotation;
+                s.scale = placement.scale;
+                s.anchor = placement.anchor;
+                s.size = placement.size;
+            }
+            (ModuleState::Params(params), Some(s)) => s.params = Some(params.clone()),
+            (ModuleState::Name(name), Some(s)) => s.name = name.clone(),
+            (ModuleState::Layer(layer), Some(s)) => s.layer = layer.clone(),
+            // a part of a module that doesn't exist
+            (_, None) => {}
+        }
+    }
+}
+
+/// What a history entry did to one module
+#[derive(Clone, Debug)]
+pub struct ModuleChange {
+    pub id: ModuleId,
+    /// The name of the module, for the label
+    pub name: String,
+    pub before: ModuleState,
+    pub after: ModuleState,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry {
+    pub label: String,
+    pub changes: Vec<ModuleChange>,
+}
+
+/// The edit the user is making, with the parts of the modules it touched as they were before it
+#[derive(Default)]
+struct OpenEdit {
+    before: Vec<(ModuleId, ModuleState)>,
+    /// Modules spawned or deleted meanwhile are part of the edit
+    spawns: bool,
+    /// Somebody is still dragging or typing this frame
+    held: bool,
+}
+
+/// Undo and redo stacks of editor operations.
+///
+/// The places the user edits modules from [`touch`](History::touch) the parts they are about to change.
+/// At the end of the frame the touched parts are compared with how they are now and the difference becomes one entry,
+/// unless the edit is [held](History::hold) across frames, so a slider drag or a window drag ends up as a single step.
+/// Changes nobody touched, like MIDI, OSC or a shader's uniforms showing up once it loaded, aren't recorded.
+/// Entries refer to modules by [`ModuleId`], so they survive a restart respawning the modules.
+#[derive(Resource, Default)]
+pub struct History {
+    pub undo: Vec<HistoryEntry>,
+    pub redo: Vec<HistoryEntry>,
+    /// Positive steps redo, negative steps undo, applied once no edit is open
+    pub requested_steps: isize,
+    open: Option<OpenEdit>,
+}
+
+impl History {
+    pub fn request_undo(&mut self) {
+        self.requested_steps -= 1;
+    }
+
+    pub fn request_redo(&mut self) {
+        self.requested_steps += 1;
+    }
+
+    /// Remembers a part of a module as it is before the user changes it.
+    /// Only the first state of every part counts until the edit is recorded.
+    pub fn touch(&mut self, id: ModuleId, before: ModuleState) {
+        let edit = self.open.get_or_insert_default();
+        let touched = edit
+            .before
+            .iter()
+            .any(|(other, state)| *other == id && discriminant(state) == discriminant(&before));
+        if !touched {
+            edit.before.push((id, before));
+        }
+    }
+
+    /// Records the modules spawned and deleted this frame as the user's edit
+    pub fn record_spawns(&mut self) {
+        self.open.get_or_insert_default().spawns = true;
+    }
+
+    /// Keeps the open edit from being recorded this frame, call it every frame of a drag
+    pub fn hold(&mut self) {
+        if let Some(edit) = self.open.as_mut() {
+            edit.held = true;
+        }
+    }
+
+    /// Holds an edit made through egui widgets while the pointer is down or a text field has focus,
+    /// `editing` is the caller's flag that it touched something
+    pub fn hold_widget_edit(&mut self, ctx: &egui::Context, editing: &mut bool) {
+        *editing &= ctx.input(|i| i.pointer.any_down()) || ctx.wants_keyboard_input();
+        if *editing {
+            self.hold();
+        }
+    }
+
+    fn is_recording_spawns(&self) -> bool {
+        self.open.as_ref().is_some_and(|edit| edit.spawns)
+    }
+}
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .add_observer(record_spawned)
+            .add_observer(record_despawned)
+            .add_systems(
+                Update,
+                (
+                    undo.run_if(action_just_pressed(Action::Undo)),
+                    redo.run_if(action_just_pressed(Action::Redo)),
+                )
+                    .run_if(in_state(AppState::Running)),
+            )
+            .add_systems(
+                PostUpdate,
+                // after the UI, whose edits count in the same frame
+                (finish_edit, step)
+                    .chain()
+                    .after(EguiPostUpdateSet::EndPass)
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+fn undo(mut history: ResMut<History>) {
+    history.request_undo();
+}
+
+fn redo(mut history: ResMut<History>) {
+    history.request_redo();
+}
+
+fn record_spawned(add: On<Add, ModuleId>, mut history: ResMut<History>, ids: Query<&ModuleId>) {
+    if history.is_recording_spawns()
+        && let Ok(id) = ids.get(add.entity)
+    {
+        history.touch(*id, ModuleState::Module(None));
+    }
+}
+
+fn record_despawned(
+    despawn: On<DespawnModule>,
+    mut history: ResMut<History>,
+    modules: Query<SnapshotData>,
+) {
+    if history.is_recording_spawns()
+        && let Ok(data) = modules.get(despawn.entity)
+    {
+        history.touch(
+            *data.1,
+            ModuleState::Module(Some(ModuleSnapshot::take(data))),
+        );
+    }
+}
+
+/// Turns the open edit into an entry once nobody holds it anymore
+fn finish_edit(mut history: ResMut<History>, modules: Query<SnapshotData>) {
+    let Some(edit) = history.open.as_mut() else {
+        return;
+    };
+    if std::mem::take(&mut edit.held) {
+        return;
+    }
+    let Some(edit) = history.open.take() else {
+        return;
+    };
+
+    let snapshots: HashMap<ModuleId, ModuleSnapshot> = modules
+        .iter()
+        .filter(|data| edit.before.iter().any(|(id, _)| id == data.1))
+        .map(|data| (*data.1, ModuleSnapshot::take(data)))
+        .collect();
+    let changes: Vec<ModuleChange> = edit
+        .before
+        .into_iter()
+        .filter_map(|(id, before)| {
+            let snapshot = snapshots.get(&id);
+            let after = before.of(snapshot)?;
+            let name = match (snapshot, &before) {
+                (Some(snapshot), _) | (None, ModuleState::Module(Some(snapshot))) => {
+                    snapshot.name.clone()
+                }
+                _ => String::new(),
+            };
+            (after != before).then_some(ModuleChange {
+                id,
+                name,
+                before,
+                after,
+            })
+        })
+        .collect();
+    if changes.is_empty() {
+        return;
+    }
+
+    history.undo.push(HistoryEntry {
+        label: label(&changes),
+        changes,
+    });
+    history.redo.clear();
+}
+
+/// Walks the stacks by the requested number of steps and puts the modules in the resulting state
+fn step(mut commands: Commands, mut history: ResMut<History>, modules: Query<SnapshotData>) {
+    if history.requested_steps == 0 || history.open.is_some() {
+        return;
+    }
+    let history = history.as_mut();
+
+    let current: HashMap<ModuleId, (Entity, ModuleSnapshot)> = modules
+        .iter()
+        .map(|data| (*data.1, (data.0, ModuleSnapshot::take(data))))
+        .collect();
+    let mut target: HashMap<ModuleId, Option<ModuleSnapshot>> = HashMap::new();
+    let mut apply = |id: ModuleId, state: &ModuleState| {
+        let module = target
+            .entry(id)
+            .or_insert_with(|| current.get(&id).map(|(_, snapshot)| snapshot.clone()));
+        state.apply(module);
+    };
+
+    while history.requested_steps < 0 {
+        history.requested_steps += 1;
+        let Some(entry) = history.undo.pop() else {
+            break;
+        };
+        for change in entry.changes.iter().rev() {
+            apply(change.id, &change.before);
+        }
+        history.redo.push(entry);
+    }
+    while history.requested_steps > 0 {
+        history.requested_steps -= 1;
+        let Some(entry) = history.redo.pop() else {
+            break;
+        };
+        for change in entry.changes.iter() {
+            apply(change.id, &change.after);
+        }
+        history.undo.push(entry);
+    }
+    history.requested_steps = 0;
+
+    for (id, snapshot) in target {
+        let existing = current.get(&id);
+        if existing.map(|(_, snapshot)| snapshot) == snapshot.as_ref() {
+            continue;
+        }
+        match (existing, snapshot) {
+            (Some(&(entity, _)), None) => commands.trigger(DespawnModule { entity }),
+            (Some(&(entity, _)), Some(snapshot)) => {
+                commands.trigger(RestoreModule { entity, snapshot })
+            }
+            (None, Some(snapshot)) => commands.trigger(SpawnModuleEvent {
+                moduleclass: snapshot.class,
+                snapshot: Some(snapshot),
+            }),
+            (None, None) => {}
+        }
+    }
+}
+
+/// Describes an entry for the history panel
+fn label(changes: &[ModuleChange]) -> String {
+    if let [change] = changes {
+        let name = &change.name;
+        return match (&change.before, &change.after) {
+            (ModuleState::Module(None), _) => format!("Spawn {name}"),
+            (_, ModuleState::Module(None)) => format!("Delete {name}"),
+            (ModuleState::Name(before), ModuleState::Name(after)) => {
+                format!("Rename {before} to {after}")
+            }
+            (ModuleState::Placement(before), ModuleState::Placement(after)) => {
+                if before.size != after.size {
+                    format!("Resize {name}")
+                } else if before.scale != after.scale {
+                    format!("Scale {name}")
+                } else if before.rotation != after.rotation {
+                    format!("Rotate {name}")
+                } else if before.anchor != after.anchor {
+                    format!("Move {name} anchor")
+                } else {
+                    format!("Move {name}")
+                }
+            }
+            (ModuleState::Params(before), ModuleState::Params(after)) => {
+                if only_shader_chain_changed(before, after) {
+                    format!("Edit {name} shader chain")
+                } else {
+                    format!("Edit {name} params")
+                }
+            }
+            (ModuleState::Layer(_), _) => format!("Change {name} layer"),
+            _ => format!("Edit {name}"),
+        };
+    }
+
+    let all = |part: fn(&ModuleChange) -> bool| changes.iter().all(part);
+    if all(|change| change.before == ModuleState::Module(None)) {
+        format!("Spawn {} modules", changes.len())
+    } else if all(|change| change.after == ModuleState::Module(None)) {
+        format!("Delete {} modules", changes.len())
+    } else if all(|change| matches!(change.after, ModuleState::Layer(_))) {
+        "Reorder layers".to_string()
+    } else if all(|change| matches!(change.after, ModuleState::Placement(_))) {
+        format!("Move {} modules", changes.len())
+    } else {
+        format!("Edit {} modules", changes.len())
+    }
+}
+
+fn only_shader_chain_changed(before: &ModuleParams, after: &ModuleParams) -> bool {
+    before.0.len() == after.0.len()
+        && before
+            .0
+            .iter()
+            .zip(after.0.iter())
+            .all(|(b, a)| b == a || a.name == SHADER_CHAIN_PARAM)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::state::app::StatesPlugin;
+
+    use super::*;
+    use crate::keymap::PressedActions;
+    use crate::module::{
+        ModuleClass, ModuleLayerCounter, ModuleSpawnerConfig, NextModuleId, Selection,
+        spawn_module_observer,
+    };
+
+    /// The history with the module spawning it drives, without any module classes rendering
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((StatesPlugin, HistoryPlugin))
+            .insert_state(AppState::Running)
+            .init_resource::<PressedActions>()
+            .init_resource::<Assets<Image>>()
+            .init_resource::<Selection>()
+            .init_resource::<NextModuleId>()
+            .init_resource::<ModuleLayerCounter>()
+            .insert_resource(ModuleSpawnerConfig {
+                observers: default(),
+            })
+            .add_observer(spawn_module_observer);
+        app.update();
+        app
+    }
+
+    fn history(app: &mut App) -> Mut<'_, History> {
+        app.world_mut().resource_mut::<History>()
+    }
+
+    fn modules(app: &mut App) -> Vec<ModuleSnapshot> {
+        let world = app.world_mut();
+        let mut modules: Vec<_> = world
+            .query::<SnapshotData>()
+            .iter(world)
+            .map(ModuleSnapshot::take)
+            .collect();
+        modules.sort_by_key(|module| module.id.0);
+        modules
+    }
+
+    fn entity(app: &mut App, id: ModuleId) -> Entity {
+        let world = app.world_mut();
+        world
+            .query::<(Entity, &ModuleId)>()
+            .iter(world)
+            .find(|(_, other)| **other == id)
+            .unwrap()
+            .0
+    }
+
+    fn labels(entries: &[HistoryEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.label.as_str()).collect()
+    }
+
+    /// Spawns a module as the user's edit, returns its id
+    fn spawn(app: &mut App, snapshot: Option<ModuleSnapshot>) -> ModuleId {
+        history(app).record_spawns();
+        app.world_mut().trigger(SpawnModuleEvent {
+            moduleclass: ModuleClass::Noise,
+            snapshot,
+        });
+        app.update();
+        modules(app).last().unwrap().id
+    }
+
+    fn delete(app: &mut App, id: ModuleId) {
+        let entity = entity(app, id);
+        history(app).record_spawns();
+        app.world_mut().trigger(DespawnModule { entity });
+        app.update();
+    }
+
+    fn move_by(app: &mut App, id: ModuleId, offset: Vec2) {
+        let entity = entity(app, id);
+        app.world_mut()
+            .get_mut::<Transform>(entity)
+            .unwrap()
+            .translation += offset.extend(0.0);
+    }
+
+    fn touch_placement(app: &mut App, id: ModuleId) {
+        let module = modules(app).into_iter().find(|m| m.id == id).unwrap();
+        history(app).touch(id, ModuleState::Placement(Placement::of(&module)));
+    }
+
+    fn undo(app: &mut App) {
+        history(app).request_undo();
+        app.update();
+    }
+
+    fn redo(app: &mut App) {
+        history(app).request_redo();
+        app.update();
+    }
+
+    #[test]
+    fn a_held_drag_is_one_entry() {
+        let mut app = app();
+        let id = spawn(&mut app, None);
+        touch_placement(&mut app, id);
+        for _ in 0..3 {
+            move_by(&mut app, id, Vec2::X);
+            history(&mut app).hold();
+            app.update();
+            assert_eq!(history(&mut app).undo.len(), 1);
+        }
+        app.update();
+        assert_eq!(
+            labels(&history(&mut app).undo),
+            ["Spawn Noise 1", "Move Noise 1"]
+        );
+
+        undo(&mut app);
+        assert_eq!(modules(&mut app)[0].position, Vec2::ZERO);
+        redo(&mut app);
+        assert_eq!(modules(&mut app)[0].position, Vec2::new(3.0, 0.0));
+    }
+
+    #[test]
+    fn changes_nobody_touched_are_not_recorded() {
+        let mut app = app();
+        let id = spawn(&mut app, None);
+        move_by(&mut app, id, Vec2::X);
+        app.update();
+        // and touching without changing anything leaves no entry
+        touch_placement(&mut app, id);
+        app.update();
+        assert_eq!(labels(&history(&mut app).undo), ["Spawn Noise 1"]);
+    }
+
+    #[test]
+    fn undo_and_redo_spawn_and_delete() {
+        let mut app = app();
+        let id = spawn(&mut app, None);
+        let spawned = modules(&mut app);
+        delete(&mut app, id);
+        assert_eq!(modules(&mut app), []);
+        assert_eq!(
+            labels(&history(&mut app).undo),
+            ["Spawn Noise 1", "Delete Noise 1"]
+        );
+
+        undo(&mut app);
+        assert_eq!(modules(&mut app), spawned);
+        undo(&mut app);
+        assert_eq!(modules(&mut app), []);
+        assert_eq!(history(&mut app).undo.len(), 0);
+
+        redo(&mut app);
+        assert_eq!(modules(&mut app), spawned);
+        redo(&mut app);
+        assert_eq!(modules(&mut app), []);
+        assert_eq!(history(&mut app).redo.len(), 0);
+    }
+
+    #[test]
+    fn redo_finds_a_module_respawned_under_a_new_entity() {
+        let mut app = app();
+        let id = spawn(&mut app, None);
+        let entity = entity(&mut app, id);
+        touch_placement(&mut app, id);
+        move_by(&mut app, id, Vec2::Y);
+        app.update();
+        delete(&mut app, id);
+
+        // the delete and the move are undone on the respawned module
+        undo(&mut app);
+        undo(&mut app);
+        assert_ne!(self::entity(&mut app, id), entity);
+        assert_eq!(modules(&mut app)[0].position, Vec2::ZERO);
+
+        redo(&mut app);
+        assert_eq!(modules(&mut app)[0].position, Vec2::Y);
+        redo(&mut app);
+        assert_eq!(modules(&mut app), []);
+    }
+
+    #[test]
+    fn undo_and_redo_a_duplicate() {
+        let mut app = app();
+        let id = spawn(&mut app, None);
+        let mut copy = modules(&mut app)[0].clone();
+        copy.id = ModuleId(id.0 + 1);
+        copy.name = "Noise 2".to_string();
+        spawn(&mut app, Some(copy.clone()));
+        assert_eq!(
+            labels(&history(&mut app).undo),
+            ["Spawn Noise 1", "Spawn Noise 2"]
+        );
+
+        undo(&mut app);
+        assert_eq!(modules(&mut app).len(), 1);
+        redo(&mut app);
+        // the copy comes back with the id and name it was duplicated with
+        let modules = modules(&mut app);
+        assert_eq!(modules.len(), 2);
+        assert_eq!(modules[1].id, copy.id);
+        assert_eq!(modules[1].name, copy.name);
+    }
+
+    #[test]
+    fn undo_waits_for_the_open_edit() {
+        let mut app = app();
+        let id = spawn(&mut app, None);
+        touch_placement(&mut app, id);
+        move_by(&mut app, id, Vec2::X);
+        history(&mut app).hold();
+        history(&mut app).request_undo();
+        app.update();
+        assert_eq!(modules(&mut app).len(), 1);
+        assert_eq!(history(&mut app).requested_steps, -1);
+
+        // the drag is recorded first and then undone
+        app.update();
+        assert_eq!(modules(&mut app)[0].position, Vec2::ZERO);
+        assert_eq!(labels(&history(&mut app).undo), ["Spawn Noise 1"]);
+        assert_eq!(labels(&history(&mut app).redo), ["Move Noise 1"]);
+    }
+
+    #[test]
+    fn a_new_edit_clears_the_redo_stack() {
+        let mut app = app();
+        let id = spawn(&mut app, None);
+        touch_placement(&mut app, id);
+        move_by(&mut app, id, Vec2::X);
+        app.update();
+        undo(&mut app);
+        assert_eq!(history(&mut app).redo.len(), 1);
+
+        touch_placement(&mut app, id);
+        move_by(&mut app, id, Vec2::Y);
+        app.update();
+        assert_eq!(history(&mut app).redo.len(), 0);
+        assert_eq!(
+            labels(&history(&mut app).undo),
+            ["Spawn Noise 1", "Move Noise 1"]
+        );
+        redo(&mut app);
+        assert_eq!(modules(&mut app)[0].position, Vec2::Y);
+    }
+}
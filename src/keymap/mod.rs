@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use bevy::input::InputSystems;
+use bevy::prelude::*;
+#[cfg(feature = "editor")]
+use bevy_egui::EguiContexts;
+use serde::{Deserialize, Serialize};
+
+/// The config file the keymap is read from, next to the assets directory.
+/// Actions listed in it replace the default bindings of that action, an empty list unbinds it.
+pub const KEYMAP_PATH: &str = "keymap.ron";
+
+/// Something the editor does when its shortcut is pressed
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Restart,
+    Quit,
+    PlayPause,
+    Save,
+    CaptureFrame,
+    DeleteModule,
+    DuplicateModule,
+    Undo,
+    Redo,
+    Shortcuts,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::Restart,
+        Action::Quit,
+        Action::PlayPause,
+        Action::Save,
+        Action::CaptureFrame,
+        Action::DeleteModule,
+        Action::DuplicateModule,
+        Action::Undo,
+        Action::Redo,
+        Action::Shortcuts,
+    ];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Restart => "Restart",
+            Action::Quit => "Quit",
+            Action::PlayPause => "Play / pause",
+            Action::Save => "Save project",
+            Action::CaptureFrame => "Capture frame",
+            Action::DeleteModule => "Delete selected modules",
+            Action::DuplicateModule => "Duplicate selected modules",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::Shortcuts => "Show shortcuts",
+        }
+    }
+}
+
+/// A key together with the modifiers that have to be held for it, other modifiers must be released
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct KeyBinding {
+    pub key: KeyCode,
+    #[serde(default)]
+    pub ctrl: bool,
+    #[serde(default)]
+    pub shift: bool,
+    #[serde(default)]
+    pub alt: bool,
+}
+
+impl KeyBinding {
+    pub const fn key(key: KeyCode) -> Self {
+        KeyBinding {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    pub const fn ctrl(key: KeyCode) -> Self {
+        KeyBinding {
+            ctrl: true,
+            ..KeyBinding::key(key)
+        }
+    }
+
+    pub const fn ctrl_shift(key: KeyCode) -> Self {
+        KeyBinding {
+            shift: true,
+            ..KeyBinding::ctrl(key)
+        }
+    }
+
+    fn just_pressed(&self, keys: &ButtonInput<KeyCode>) -> bool {
+        keys.just_pressed(self.key)
+            && keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) == self.ctrl
+            && keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) == self.shift
+            && keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) == self.alt
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        let key = format!("{:?}", self.key);
+        let key = key
+            .strip_prefix("Key")
+            .or_else(|| key.strip_prefix("Digit"))
+            .unwrap_or(&key);
+        write!(f, "{key}")
+    }
+}
+
+/// The shortcuts of every action
+#[derive(Resource, Clone, Debug)]
+pub struct Keymap(pub BTreeMap<Action, Vec<KeyBinding>>);
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap(BTreeMap::from([
+            (Action::Restart, vec![KeyBinding::key(KeyCode::KeyR)]),
+            (Action::Quit, vec![KeyBinding::ctrl(KeyCode::KeyQ)]),
+            (Action::PlayPause, vec![KeyBinding::key(KeyCode::Space)]),
+            (Action::Save, vec![KeyBinding::ctrl(KeyCode::KeyS)]),
+            (Action::CaptureFrame, vec![KeyBinding::key(KeyCode::F12)]),
+            (
+                Action::DeleteModule,
+                vec![
+                    KeyBinding::key(KeyCode::Delete),
+                    KeyBinding::key(KeyCode::Backspace),
+                ],
+            ),
+            (
+                Action::DuplicateModule,
+                vec![KeyBinding::ctrl(KeyCode::KeyD)],
+            ),
+            (Action::Undo, vec![KeyBinding::ctrl(KeyCode::KeyZ)]),
+            (
+                Action::Redo,
+                vec![
+                    KeyBinding::ctrl_shift(KeyCode::KeyZ),
+                    KeyBinding::ctrl(KeyCode::KeyY),
+                ],
+            ),
+            (Action::Shortcuts, vec![KeyBinding::key(KeyCode::F1)]),
+        ]))
+    }
+}
+
+impl Keymap {
+    /// The default keymap with the bindings from the config file on top
+    fn load() -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = bevy::asset::io::file::FileAssetReader::get_base_path().join(KEYMAP_PATH);
+            match std::fs::read_to_string(&path) {
+                Ok(text) => match Keymap::from_config(&text) {
+                    Ok(keymap) => return keymap,
+                    Err(err) => warn!("ignoring {}: {err}", path.display()),
+                },
+                Err(_) => info!("no {}, using the default keymap", path.display()),
+            }
+        }
+
+        Keymap::default()
+    }
+
+    /// The default keymap with the bindings of the config file's text on top
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_config(text: &str) -> Result<Self, ron::error::SpannedError> {
+        let mut keymap = Keymap::default();
+        keymap
+            .0
+            .extend(ron::from_str::<BTreeMap<Action, Vec<KeyBinding>>>(text)?);
+        Ok(keymap)
+    }
+
+    /// The actions with a binding that was pressed this frame
+    fn just_pressed<'a>(
+        &'a self,
+        keys: &'a ButtonInput<KeyCode>,
+    ) -> impl Iterator<Item = Action> + 'a {
+        self.0
+            .iter()
+            .filter(|(_, bindings)| bindings.iter().any(|binding| binding.just_pressed(keys)))
+            .map(|(action, _)| *action)
+    }
+}
+
+/// The actions whose shortcut was pressed this frame
+#[derive(Resource, Default)]
+pub struct PressedActions(Vec<Action>);
+
+impl PressedActions {
+    pub fn contains(&self, action: Action) -> bool {
+        self.0.contains(&action)
+    }
+
+    /// Handles `action` this frame as if its shortcut was pressed
+    pub fn press(&mut self, action: Action) {
+        if !self.contains(action) {
+            self.0.push(action);
+        }
+    }
+}
+
+/// Run condition for systems that handle an action
+pub fn action_just_pressed(action: Action) -> impl FnMut(Res<PressedActions>) -> bool + Clone {
+    move |pressed: Res<PressedActions>| pressed.contains(action)
+}
+
+pub struct KeymapPlugin;
+
+impl Plugin for KeymapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Keymap::load())
+            .init_resource::<PressedActions>()
+            .add_systems(PreUpdate, read_actions.after(InputSystems));
+    }
+}
+
+pub fn read_actions(
+    keys: Res<ButtonInput<KeyCode>>,
+    keymap: Res<Keymap>,
+    #[cfg(feature = "editor")] mut contexts: EguiContexts,
+    mut pressed: ResMut<PressedActions>,
+) {
+    pressed.0.clear();
+
+    // keys typed into a text field are not shortcuts
+    #[cfg(feature = "editor")]
+    if let Ok(ctx) = contexts.ctx_mut()
+        && ctx.wants_keyboard_input()
+    {
+        return;
+    }
+
+    pressed.0.extend(keymap.just_pressed(&keys));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(keymap: &Keymap, keys: &[KeyCode]) -> Vec<Action> {
+        let mut input = ButtonInput::default();
+        for key in keys {
+            input.press(*key);
+        }
+        keymap.just_pressed(&input).collect()
+    }
+
+    #[test]
+    fn the_config_replaces_the_bindings_of_its_actions() {
+        let keymap = Keymap::from_config(
+            "{ Undo: [(key: KeyU, ctrl: true)], Shortcuts: [], Save: [(key: F2)] }",
+        )
+        .unwrap();
+        assert_eq!(keymap.0[&Action::Undo], [KeyBinding::ctrl(KeyCode::KeyU)]);
+        assert_eq!(keymap.0[&Action::Shortcuts], []);
+        assert_eq!(keymap.0[&Action::Save], [KeyBinding::key(KeyCode::F2)]);
+        // the others keep their defaults
+        assert_eq!(keymap.0[&Action::Redo], Keymap::default().0[&Action::Redo]);
+        assert_eq!(keymap.0.len(), Action::ALL.len());
+    }
+
+    #[test]
+    fn bad_configs_are_errors() {
+        assert!(Keymap::from_config("{ Jump: [(key: Space)] }").is_err());
+        assert!(Keymap::from_config("{ Undo: [(key: NotAKey)] }").is_err());
+        assert!(Keymap::from_config("{ Undo: [(ctrl: true)] }").is_err());
+        assert!(Keymap::from_config("{ Undo: (key: KeyZ) }").is_err());
+        assert!(Keymap::from_config("{}").is_ok());
+    }
+
+    #[test]
+    fn modifiers_have_to_match() {
+        use KeyCode::*;
+        let keymap = Keymap::default();
+        assert_eq!(pressed(&keymap, &[ControlLeft, KeyZ]), [Action::Undo]);
+        assert_eq!(
+            pressed(&keymap, &[ControlLeft, ShiftLeft, KeyZ]),
+            [Action::Redo]
+        );
+        assert_eq!(
+            pressed(&keymap, &[ControlRight, ShiftRight, KeyZ]),
+            [Action::Redo]
+        );
+        assert_eq!(pressed(&keymap, &[ControlLeft, KeyY]), [Action::Redo]);
+        assert_eq!(pressed(&keymap, &[ControlLeft, AltLeft, KeyZ]), []);
+        assert_eq!(pressed(&keymap, &[KeyZ]), []);
+        assert_eq!(pressed(&keymap, &[KeyR]), [Action::Restart]);
+        assert_eq!(pressed(&keymap, &[ControlLeft, KeyR]), []);
+    }
+
+    #[test]
+    fn a_held_key_is_pressed_once() {
+        let keymap = Keymap::default();
+        let mut input = ButtonInput::default();
+        input.press(KeyCode::Space);
+        assert_eq!(
+            keymap.just_pressed(&input).collect::<Vec<_>>(),
+            [Action::PlayPause]
+        );
+        input.clear();
+        assert_eq!(keymap.just_pressed(&input).count(), 0);
+    }
+}
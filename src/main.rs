@@ -1,22 +1,40 @@
+mod audio;
 mod common;
+mod console;
+#[cfg(feature = "editor")]
+mod export;
+#[cfg(feature = "editor")]
+mod history;
+mod keymap;
+mod midi;
 mod module;
+#[cfg(not(target_arch = "wasm32"))]
+mod osc;
+mod params;
 mod pipeline;
+mod playback;
+#[cfg(not(feature = "editor"))]
+mod player;
+mod project;
 mod rendering;
+#[cfg(feature = "editor")]
 mod ui;
+#[cfg(all(target_arch = "wasm32", not(feature = "editor")))]
+mod web;
 
 use common::*;
 
-use bevy::{
-    dev_tools::fps_overlay::{FpsOverlayConfig, FpsOverlayPlugin, FrameTimeGraphConfig},
-    prelude::*,
-    sprite_render::Material2dPlugin,
-};
+use bevy::{prelude::*, sprite_render::Material2dPlugin};
+#[cfg(feature = "editor")]
+use bevy::dev_tools::fps_overlay::{FpsOverlayConfig, FpsOverlayPlugin, FrameTimeGraphConfig};
 
 use crate::rendering::ShaderChainCamera;
 
+#[cfg(feature = "editor")]
 struct OverlayColor;
 
 // use bevy::prelude::ComputedNode; TODO find feature flag for this
+#[cfg(feature = "editor")]
 impl OverlayColor {
     const GREEN: Color = Color::srgb(0.0, 1.0, 0.0);
 }
@@ -29,6 +47,13 @@ fn main() {
             title: "I am the window!".into(),
             name: Some("bevy.app".into()),
             resolution: (1000, 700).into(),
+            // The canvas the web player renders into, desktop builds ignore this
+            canvas: Some("#bumper".into()),
+            // The player plays fullscreen, on the web it fills the canvas instead
+            #[cfg(not(feature = "editor"))]
+            mode: bevy::window::WindowMode::BorderlessFullscreen(
+                bevy::window::MonitorSelection::Current,
+            ),
             // present_mode: PresentMode::AutoNoVsync,
             // Tells Wasm to resize the window according to the available canvas
             fit_canvas_to_parent: true,
@@ -46,12 +71,18 @@ fn main() {
             ..default()
         }),
         ..default()
+    })
+    .set(bevy::log::LogPlugin {
+        filter: console::log_filter(),
+        #[cfg(feature = "editor")]
+        custom_layer: console::capture_layer,
+        ..default()
     });
 
     // Conditionally add the AssetPlugin for Linux
     #[cfg(all(target_os = "linux"))]
     {
-        print!("ADDING WATCHER PLUGIN");
+        info!("watching assets for changes");
         default_plugins = default_plugins.set(AssetPlugin {
             watch_for_changes_override: Some(true),
             ..Default::default()
@@ -60,34 +91,7 @@ fn main() {
 
     bevyapp
         .insert_resource(ClearColor(Color::srgba(0.2, 0.2, 0.2, 1.0)))
-        .add_plugins((
-            default_plugins,
-            FpsOverlayPlugin {
-                config: FpsOverlayConfig {
-                    text_config: TextFont {
-                        // Here we define size of our overlay
-                        font_size: 18.0,
-                        // If we want, we can use a custom font
-                        font: default(),
-                        // We could also disable font smoothing,
-                        // font_smoothing: FontSmoothing::default(),
-                        ..default()
-                    },
-                    // We can also change color of the overlay
-                    text_color: OverlayColor::GREEN,
-                    // We can also set the refresh interval for the FPS counter
-                    refresh_interval: core::time::Duration::from_millis(100),
-                    enabled: true,
-                    frame_time_graph_config: FrameTimeGraphConfig {
-                        enabled: false,
-                        // The minimum acceptable fps
-                        min_fps: 30.0,
-                        // The target fps
-                        target_fps: 144.0,
-                    },
-                },
-            },
-        ))
+        .add_plugins(default_plugins)
         .add_plugins(Material2dPlugin::<CustomMaterial>::default())
         // .edit_schedule(Update, |schedule| {
         //     schedule.set_executor_kind(ExecutorKind::SingleThreaded);
@@ -97,10 +101,57 @@ fn main() {
         .add_systems(OnEnter(AppState::Restarting), restart)
         .add_systems(OnEnter(AppState::Startup), setup)
         .add_systems(OnExit(AppState::Running), teardown)
-        .add_systems(PreUpdate, trigger_restart)
         .add_systems(PreStartup, spawn_immortals)
+        .add_plugins(keymap::KeymapPlugin)
+        .add_systems(Update, quit.run_if(keymap::action_just_pressed(keymap::Action::Quit)))
+        .add_plugins(params::ParamsPlugin)
+        .add_plugins(midi::MidiPlugin)
+        .add_plugins(audio::AudioTrackPlugin)
+        .add_plugins(playback::PlaybackPlugin)
         .add_plugins(module::ModulePlugin)
-        .add_systems(Startup, (pipeline::create_render_target,))
+        .add_plugins(project::ProjectPlugin)
+        .add_plugins(pipeline::RenderTargetPoolPlugin);
+
+    // OSC controllers talk UDP, which the web doesn't have
+    #[cfg(not(target_arch = "wasm32"))]
+    bevyapp.add_plugins(osc::OscPlugin);
+
+    // Without the editor the app only plays a project, like on kiosk displays and in the web export
+    #[cfg(not(feature = "editor"))]
+    bevyapp.add_plugins(player::PlayerPlugin);
+    #[cfg(all(target_arch = "wasm32", not(feature = "editor")))]
+    bevyapp.add_plugins(web::WebPlugin);
+
+    #[cfg(feature = "editor")]
+    bevyapp
+        .add_plugins(FpsOverlayPlugin {
+            config: FpsOverlayConfig {
+                text_config: TextFont {
+                    // Here we define size of our overlay
+                    font_size: 18.0,
+                    // If we want, we can use a custom font
+                    font: default(),
+                    // We could also disable font smoothing,
+                    // font_smoothing: FontSmoothing::default(),
+                    ..default()
+                },
+                // We can also change color of the overlay
+                text_color: OverlayColor::GREEN,
+                // We can also set the refresh interval for the FPS counter
+                refresh_interval: core::time::Duration::from_millis(100),
+                enabled: true,
+                frame_time_graph_config: FrameTimeGraphConfig {
+                    enabled: false,
+                    // The minimum acceptable fps
+                    min_fps: 30.0,
+                    // The target fps
+                    target_fps: 144.0,
+                },
+            },
+        })
+        .add_systems(Update, trigger_restart.run_if(keymap::action_just_pressed(keymap::Action::Restart)))
+        .add_plugins(history::HistoryPlugin)
+        .add_plugins(export::ExportPlugin)
         .add_plugins(ui::BumpUiPlugin);
 
     bevyapp.run();
@@ -109,30 +160,28 @@ fn main() {
 /// Boilerplate for setting up a basic restarting architecture:
 /// Moves the state into AppState::Running so that the OnEnter(AppState::Running) system is called
 fn restart(mut next_state: ResMut<NextState<AppState>>) {
-    println!("restart!");
+    debug!("restart");
     next_state.set(AppState::Startup);
 }
 
 /// Boilerplate for setting up a basic restarting architecture:
-/// Moves the state into AppState::Running so that the OnEnter(AppState::Running) system is called
-fn trigger_restart(
-    input: Res<ButtonInput<KeyCode>>,
-    mut next_state: ResMut<NextState<AppState>>,
-    mut app_exit_events: ResMut<Messages<bevy::app::AppExit>>,
-) {
-    if input.just_pressed(KeyCode::KeyR) {
-        println!("user triggered restart");
-        next_state.set(AppState::Restarting);
-    } else if input.just_pressed(KeyCode::KeyT) {
-        println!("user triggered FULL restart");
-        app_exit_events.write(bevy::app::AppExit::Success);
-    }
+/// Moves the state into AppState::Restarting when the restart shortcut is pressed.
+/// The modules are torn down and set up again, the project module puts every module instance back afterwards
+#[cfg(feature = "editor")]
+fn trigger_restart(mut next_state: ResMut<NextState<AppState>>) {
+    info!("user triggered restart");
+    next_state.set(AppState::Restarting);
+}
+
+fn quit(mut app_exit_events: ResMut<Messages<bevy::app::AppExit>>) {
+    info!("user quit");
+    app_exit_events.write(bevy::app::AppExit::Success);
 }
 
 /// Code that is actually! run once on startup of your program
 /// You can spawn entities with the Immortal component (above) here and they will not be removed when restarting
 fn spawn_immortals(mut commands: Commands) {
-    println!("immortal");
+    debug!("spawning immortals");
 
     // main camera
     commands.spawn((
@@ -141,17 +190,6 @@ fn spawn_immortals(mut commands: Commands) {
     ));
 }
 
-fn get_component_names(world: &World, entity: Entity) -> Option<Vec<String>> {
-    world
-        .inspect_entity(entity)
-        .ok() // Convert Result<EntityInspector, EntityDoesNotExistError> to Option<EntityInspector>
-        .map(|entity_inspector| {
-            entity_inspector
-                .map(|component_info| component_info.name().to_string()) // Get the name and convert to String
-                .collect::<Vec<String>>() // Collect into a Vec<String>
-        })
-}
-
 /// User-defined teardown code can live here
 /// If you kill all the Windows it will quit the app, so we use Without<PrimaryWindow> here
 /// We also don't despawn the "immortals"
@@ -169,27 +207,13 @@ fn teardown(
             // Without<EguiContext>,
         ),
     >,
-    world: &World,
 ) {
-    // if you want to see what components that entities about to be despawned have
-
-    for entity in query.iter() {
-        if let Some(component_names) = get_component_names(world, entity) {
-            println!(
-                "Component names for entity {:?}: {:?}",
-                entity, component_names
-            );
-        } else {
-            // This branch is now reached if the entity doesn't exist.
-            println!("Entity {:?} does not exist.", entity);
-        }
-    }
-
-    println!("teardown!");
+    // the inspector panel shows what components these entities have
+    debug!("teardown");
     for entity in query.iter() {
         // Drain to clear the vec
         commands.entity(entity).despawn();
-        println!("Despawned entity: {:?}", entity);
+        trace!("Despawned entity: {:?}", entity);
     }
 }
 
@@ -197,7 +221,7 @@ fn teardown(
 /// Sets up a circle that gets rendered to a texture and then shown on the main context
 
 fn setup(mut next_state: ResMut<NextState<AppState>>) {
-    println!("setup!");
+    debug!("setup");
 
     next_state.set(AppState::Running);
 }
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+
+/// A control on a MIDI controller, channels count from 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MidiSource {
+    ControlChange {
+        channel: u8,
+        controller: u8,
+    },
+    /// Notes map their velocity, releasing them sends 0
+    Note {
+        channel: u8,
+        note: u8,
+    },
+    PitchBend {
+        channel: u8,
+    },
+}
+
+impl std::fmt::Display for MidiSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MidiSource::ControlChange {
+                channel,
+                controller,
+            } => write!(f, "ch{} CC {controller}", channel + 1),
+            MidiSource::Note { channel, note } => write!(f, "ch{} note {note}", channel + 1),
+            MidiSource::PitchBend { channel } => write!(f, "ch{} pitch bend", channel + 1),
+        }
+    }
+}
+
+/// A control that moved, with its value from 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MidiMessage {
+    pub source: MidiSource,
+    pub value: f32,
+}
+
+/// Turns the raw bytes of a MIDI stream into the messages we map,
+/// keeping the running status between reads
+#[derive(Default)]
+pub struct MidiParser {
+    status: Option<u8>,
+    data: Vec<u8>,
+    in_sysex: bool,
+}
+
+impl MidiParser {
+    pub fn parse(&mut self, bytes: &[u8], messages: &mut Vec<MidiMessage>) {
+        for &byte in bytes {
+            match byte {
+                // real time messages can come in between the bytes of any other message
+                0xf8..=0xff => {}
+                // system exclusive cancels the running status like the other system common messages
+                0xf0 => {
+                    self.in_sysex = true;
+                    self.status = None;
+                    self.data.clear();
+                }
+                0xf7 => self.in_sysex = false,
+                // and so do the other system common messages
+                0xf1..=0xf6 => {
+                    self.in_sysex = false;
+                    self.status = None;
+                    self.data.clear();
+                }
+                0x80..=0xef => {
+                    self.in_sysex = false;
+                    self.status = Some(byte);
+                    self.data.clear();
+                }
+                _ if self.in_sysex => {}
+                _ => {
+                    let Some(status) = self.status else {
+                        continue;
+                    };
+                    self.data.push(byte);
+                    if self.data.len() < data_len(status) {
+                        continue;
+                    }
+                    if let Some(message) = message(status, &self.data) {
+                        messages.push(message);
+                    }
+                    self.data.clear();
+                }
+            }
+        }
+    }
+}
+
+fn data_len(status: u8) -> usize {
+    match status & 0xf0 {
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
+
+fn message(status: u8, data: &[u8]) -> Option<MidiMessage> {
+    let channel = status & 0x0f;
+    let (source, value) = match status & 0xf0 {
+        0xb0 => (
+            MidiSource::ControlChange {
+                channel,
+                controller: data[0],
+            },
+            data[1] as f32 / 127.0,
+        ),
+        0x90 => (
+            MidiSource::Note {
+                channel,
+                note: data[0],
+            },
+            data[1] as f32 / 127.0,
+        ),
+        0x80 => (
+            MidiSource::Note {
+                channel,
+                note: data[0],
+            },
+            0.0,
+        ),
+        0xe0 => (
+            MidiSource::PitchBend { channel },
+            (data[0] as u16 | (data[1] as u16) << 7) as f32 / 16383.0,
+        ),
+        _ => return None,
+    };
+    Some(MidiMessage { source, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<MidiMessage> {
+        let mut messages = vec![];
+        MidiParser::default().parse(bytes, &mut messages);
+        messages
+    }
+
+    fn cc(channel: u8, controller: u8, value: u8) -> MidiMessage {
+        MidiMessage {
+            source: MidiSource::ControlChange {
+                channel,
+                controller,
+            },
+            value: value as f32 / 127.0,
+        }
+    }
+
+    #[test]
+    fn control_change() {
+        assert_eq!(parse(&[0xb3, 7, 127]), [cc(3, 7, 127)]);
+    }
+
+    #[test]
+    fn running_status_repeats_the_last_status() {
+        assert_eq!(
+            parse(&[0xb0, 1, 10, 2, 20, 3, 30]),
+            [cc(0, 1, 10), cc(0, 2, 20), cc(0, 3, 30)]
+        );
+    }
+
+    #[test]
+    fn running_status_continues_across_reads() {
+        let mut parser = MidiParser::default();
+        let mut messages = vec![];
+        parser.parse(&[0xb0, 1], &mut messages);
+        assert!(messages.is_empty());
+        parser.parse(&[64, 2, 32], &mut messages);
+        assert_eq!(messages, [cc(0, 1, 64), cc(0, 2, 32)]);
+    }
+
+    #[test]
+    fn note_on_without_velocity_releases() {
+        let note = MidiSource::Note {
+            channel: 9,
+            note: 36,
+        };
+        let messages = parse(&[0x99, 36, 100, 36, 0, 0x89, 36, 64]);
+        let values: Vec<(MidiSource, f32)> = messages.iter().map(|m| (m.source, m.value)).collect();
+        assert_eq!(values, [(note, 100.0 / 127.0), (note, 0.0), (note, 0.0)]);
+    }
+
+    #[test]
+    fn real_time_bytes_inside_a_message_are_skipped() {
+        assert_eq!(
+            parse(&[0xf8, 0xb0, 0xf8, 1, 0xfa, 0xfe, 99, 0xf8]),
+            [cc(0, 1, 99)]
+        );
+    }
+
+    #[test]
+    fn sysex_data_is_ignored() {
+        assert_eq!(
+            parse(&[0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7, 0xb0, 1, 2]),
+            [cc(0, 1, 2)]
+        );
+    }
+
+    #[test]
+    fn sysex_cancels_running_status() {
+        assert_eq!(parse(&[0xb0, 1, 2, 0xf0, 0x01, 0xf7, 3, 4]), [cc(0, 1, 2)]);
+    }
+
+    #[test]
+    fn system_common_cancels_running_status() {
+        assert_eq!(parse(&[0xb0, 1, 2, 0xf2, 0, 0, 3, 4]), [cc(0, 1, 2)]);
+    }
+
+    #[test]
+    fn data_without_status_is_dropped() {
+        assert!(parse(&[1, 2, 3]).is_empty());
+    }
+
+    #[test]
+    fn pitch_bend_combines_both_bytes() {
+        let bend = |lsb: u8, msb: u8| parse(&[0xe2, lsb, msb])[0];
+        assert_eq!(bend(0, 0).source, MidiSource::PitchBend { channel: 2 });
+        assert_eq!(bend(0, 0).value, 0.0);
+        assert_eq!(bend(0x7f, 0x7f).value, 1.0);
+        // the centre is 0x2000, split into 7 bit halves
+        assert_eq!(bend(0x00, 0x40).value, 8192.0 / 16383.0);
+        // the low byte only moves the value a little
+        assert!(bend(0x7f, 0x00).value < bend(0x00, 0x01).value);
+    }
+
+    #[test]
+    fn program_change_has_one_data_byte_and_is_skipped() {
+        assert_eq!(parse(&[0xc0, 5, 0xb0, 1, 2]), [cc(0, 1, 2)]);
+    }
+}
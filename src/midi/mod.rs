@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{AppState, ModuleWin};
+use crate::module::ModuleId;
+use crate::params::{ExternalValue, ModuleParams, Param, SetParam};
+
+pub mod message;
+mod port;
+
+use message::{MidiMessage, MidiParser, MidiSource};
+use port::MidiPort;
+pub use port::list_ports;
+
+/// The config file remembering the selected MIDI port, next to the assets directory
+pub const MIDI_CONFIG_PATH: &str = "midi.ron";
+/// Received messages kept for the MIDI window
+const RECENT_MESSAGES: usize = 50;
+
+/// Shapes the 0 to 1 value of a control before it is scaled to the range of a mapping
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MidiCurve {
+    #[default]
+    Linear,
+    /// Fine control at the low end
+    Exponential,
+    /// Fine control at the high end
+    Logarithmic,
+    /// Fine control at both ends
+    Smooth,
+}
+
+impl MidiCurve {
+    pub const ALL: [MidiCurve; 4] = [
+        MidiCurve::Linear,
+        MidiCurve::Exponential,
+        MidiCurve::Logarithmic,
+        MidiCurve::Smooth,
+    ];
+
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            MidiCurve::Linear => t,
+            MidiCurve::Exponential => t * t,
+            MidiCurve::Logarithmic => t.sqrt(),
+            MidiCurve::Smooth => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A control driving a module parameter, saved with the project
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub source: MidiSource,
+    pub module: ModuleId,
+    pub param: String,
+    /// The values the bottom and top of the control map to, swap them to invert the control
+    pub min: f32,
+    pub max: f32,
+    pub curve: MidiCurve,
+}
+
+impl MidiMapping {
+    fn new(source: MidiSource, module: ModuleId, param: &Param) -> Self {
+        MidiMapping {
+            source,
+            module,
+            param: param.name.clone(),
+            min: *param.range.start(),
+            max: *param.range.end(),
+            curve: MidiCurve::default(),
+        }
+    }
+
+    /// The parameter value for a control at `t`
+    fn value(&self, param: &Param, t: f32) -> Option<ExternalValue> {
+        ExternalValue::from_control(param, self.min, self.max, self.curve.apply(t))
+    }
+}
+
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct MidiMappings(pub Vec<MidiMapping>);
+
+/// While enabled, clicking a parameter in the params window picks it as the target,
+/// the next control that moves is mapped to it
+#[derive(Resource, Default)]
+pub struct MidiLearn {
+    pub enabled: bool,
+    pub target: Option<(ModuleId, String)>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct MidiConfig {
+    port: Option<String>,
+}
+
+/// Reads MIDI from the selected port.
+/// Raw MIDI bytes can also be injected, they are handled like bytes from the port.
+#[derive(Resource)]
+pub struct MidiInput {
+    port: Option<MidiPort>,
+    pub port_name: Option<String>,
+    pub error: Option<String>,
+    parser: MidiParser,
+    injector: Sender<Vec<u8>>,
+    injected: Mutex<Receiver<Vec<u8>>>,
+    /// The last received messages, newest last
+    pub recent: VecDeque<String>,
+}
+
+impl Default for MidiInput {
+    fn default() -> Self {
+        let (injector, injected) = channel();
+        MidiInput {
+            port: None,
+            port_name: None,
+            error: None,
+            parser: MidiParser::default(),
+            injector,
+            injected: Mutex::new(injected),
+            recent: VecDeque::new(),
+        }
+    }
+}
+
+impl MidiInput {
+    /// Opens a port from [`list_ports`], None closes the current one.
+    /// The choice is remembered for the next start.
+    #[cfg(feature = "editor")]
+    pub fn select(&mut self, port: Option<String>) {
+        self.open(port.clone());
+        let path = bevy::asset::io::file::FileAssetReader::get_base_path().join(MIDI_CONFIG_PATH);
+        let result = ron::to_string(&MidiConfig { port })
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(&path, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            error!("couldn't save {}: {err}", path.display());
+        }
+    }
+
+    fn open(&mut self, port: Option<String>) {
+        self.port = None;
+        self.error = None;
+        self.parser = MidiParser::default();
+        if let Some(name) = port.as_deref() {
+            match MidiPort::open(name) {
+                Ok(opened) => {
+                    info!("reading MIDI from {name}");
+                    self.port = Some(opened);
+                }
+                Err(err) => {
+                    error!("couldn't open MIDI port {name}: {err}");
+                    self.error = Some(err.to_string());
+                }
+            }
+        }
+        self.port_name = port;
+    }
+
+    /// A sender for raw MIDI bytes, for virtual controllers and testing the mappings
+    pub fn injector(&self) -> Sender<Vec<u8>> {
+        self.injector.clone()
+    }
+}
+
+/// Maps knobs, faders and keys of MIDI controllers to module parameters
+pub struct MidiPlugin;
+
+impl Plugin for MidiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MidiInput>()
+            .init_resource::<MidiMappings>()
+            .init_resource::<MidiLearn>()
+            .add_systems(Startup, open_configured_port)
+            .add_systems(Update, read_midi.run_if(in_state(AppState::Running)));
+    }
+}
+
+fn open_configured_port(mut input: ResMut<MidiInput>) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let path = bevy::asset::io::file::FileAssetReader::get_base_path().join(MIDI_CONFIG_PATH);
+        if let Ok(text) = std::fs::read_to_string(&path) {
+            match ron::from_str::<MidiConfig>(&text) {
+                Ok(config) => input.open(config.port),
+                Err(err) => warn!("ignoring {}: {err}", path.display()),
+            }
+        }
+    }
+}
+
+fn read_midi(
+    mut commands: Commands,
+    mut input: ResMut<MidiInput>,
+    mut learn: ResMut<MidiLearn>,
+    mut mappings: ResMut<MidiMappings>,
+    modules: Query<(&ModuleId, &Name, &ModuleParams), With<ModuleWin>>,
+) {
+    let input = input.as_mut();
+    let mut bytes = vec![];
+    if let Ok(injected) = input.injected.lock() {
+        bytes.extend(injected.try_iter().flatten());
+    }
+    if let Some(port) = input.port.as_mut()
+        && let Err(err) = port.read(&mut bytes)
+    {
+        error!("lost MIDI port: {err}");
+        input.port = None;
+        input.error = Some(err.to_string());
+    }
+    if bytes.is_empty() {
+        return;
+    }
+
+    let mut messages: Vec<MidiMessage> = vec![];
+    input.parser.parse(&bytes, &mut messages);
+    for message in &messages {
+        input
+            .recent
+            .push_back(format!("{} {:.3}", message.source, message.value));
+        if input.recent.len() > RECENT_MESSAGES {
+            input.recent.pop_front();
+        }
+    }
+
+    let modules: Vec<_> = modules
+        .iter()
+        .map(|(id, name, params)| (*id, name.as_str(), params))
+        .collect();
+    for set in map_messages(&mut mappings, &mut learn, &messages, &modules) {
+        commands.trigger(set);
+    }
+}
+
+/// Maps the learn target to the first message if there is one, and turns the other messages
+/// into the parameter values of their mappings. `modules` are the id, name and params of each module.
+fn map_messages(
+    mappings: &mut MidiMappings,
+    learn: &mut MidiLearn,
+    messages: &[MidiMessage],
+    modules: &[(ModuleId, &str, &ModuleParams)],
+) -> Vec<SetParam> {
+    let find_param = |module: ModuleId, param: &str| {
+        modules
+            .iter()
+            .find(|(id, _, _)| *id == module)
+            .and_then(|(_, name, params)| {
+                let param = params.0.iter().find(|p| p.name == param)?;
+                Some((*name, param))
+            })
+    };
+
+    let mut sets = vec![];
+    for message in messages {
+        if let Some((module, param)) = learn.target.take() {
+            if let Some((name, param)) = find_param(module, &param) {
+                info!("mapped {} to {} of {name}", message.source, param.name);
+                mappings.0.retain(|m| {
+                    !(m.source == message.source && m.module == module && m.param == param.name)
+                });
+                mappings
+                    .0
+                    .push(MidiMapping::new(message.source, module, param));
+            }
+            continue;
+        }
+
+        for mapping in mappings.0.iter().filter(|m| m.source == message.source) {
+            if let Some((name, param)) = find_param(mapping.module, &mapping.param)
+                && let Some(value) = mapping.value(param, message.value)
+            {
+                sets.push(SetParam {
+                    module: name.to_string(),
+                    param: param.name.clone(),
+                    value,
+                });
+            }
+        }
+    }
+    sets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: ModuleId = ModuleId(1);
+
+    fn params() -> ModuleParams {
+        ModuleParams(vec![
+            Param::float("speed", 1.0, 0.0..=10.0),
+            Param::bool("invert", false),
+            Param::choice(
+                "mode",
+                "a",
+                vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            ),
+        ])
+    }
+
+    fn parse(bytes: &[u8]) -> Vec<MidiMessage> {
+        let mut messages = vec![];
+        MidiParser::default().parse(bytes, &mut messages);
+        messages
+    }
+
+    fn map(mappings: &mut MidiMappings, learn: &mut MidiLearn, bytes: &[u8]) -> Vec<ExternalValue> {
+        let params = params();
+        map_messages(
+            mappings,
+            learn,
+            &parse(bytes),
+            &[(MODULE, "Noise", &params)],
+        )
+        .into_iter()
+        .map(|set| {
+            assert_eq!(set.module, "Noise");
+            set.value
+        })
+        .collect()
+    }
+
+    fn learn(mappings: &mut MidiMappings, param: &str, bytes: &[u8]) {
+        let mut learn = MidiLearn {
+            enabled: true,
+            target: Some((MODULE, param.to_string())),
+        };
+        assert_eq!(map(mappings, &mut learn, bytes), []);
+        assert_eq!(learn.target, None);
+    }
+
+    fn number(value: &ExternalValue) -> f32 {
+        match value {
+            ExternalValue::Number(n) => *n as f32,
+            other => panic!("not a number: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn learns_the_first_control_that_moves() {
+        let mut mappings = MidiMappings::default();
+        let mut learn = MidiLearn {
+            enabled: true,
+            target: Some((MODULE, "speed".to_string())),
+        };
+        // the rest of the read already drives the new mapping
+        let values = map(&mut mappings, &mut learn, &[0xb0, 7, 0, 0xb0, 7, 127]);
+        assert_eq!(values, [ExternalValue::Number(10.0)]);
+        assert_eq!(mappings.0.len(), 1);
+        assert_eq!(
+            mappings.0[0].source,
+            MidiSource::ControlChange {
+                channel: 0,
+                controller: 7
+            }
+        );
+    }
+
+    #[test]
+    fn learning_again_replaces_the_mapping() {
+        let mut mappings = MidiMappings::default();
+        learn(&mut mappings, "speed", &[0x90, 60, 100]);
+        mappings.0[0].min = 5.0;
+        mappings.0[0].curve = MidiCurve::Smooth;
+        learn(&mut mappings, "speed", &[0x90, 60, 1]);
+        assert_eq!(mappings.0.len(), 1);
+        assert_eq!(mappings.0[0].min, 0.0);
+        assert_eq!(mappings.0[0].curve, MidiCurve::Linear);
+
+        learn(&mut mappings, "speed", &[0xe3, 0, 64]);
+        assert_eq!(mappings.0.len(), 2);
+        assert_eq!(mappings.0[1].source, MidiSource::PitchBend { channel: 3 });
+    }
+
+    #[test]
+    fn learning_a_missing_param_maps_nothing() {
+        let mut mappings = MidiMappings::default();
+        learn(&mut mappings, "gone", &[0xb0, 7, 10]);
+        assert_eq!(mappings.0, []);
+    }
+
+    #[test]
+    fn swapped_min_and_max_invert_the_control() {
+        let mut mappings = MidiMappings::default();
+        learn(&mut mappings, "speed", &[0xb0, 1, 0]);
+        mappings.0[0].min = 8.0;
+        mappings.0[0].max = 2.0;
+        let values = map(
+            &mut mappings,
+            &mut MidiLearn::default(),
+            &[0xb0, 1, 0, 0xb0, 1, 127],
+        );
+        assert_eq!(
+            values,
+            [ExternalValue::Number(8.0), ExternalValue::Number(2.0)]
+        );
+    }
+
+    #[test]
+    fn notes_and_pitch_bend_drive_their_mappings() {
+        let mut mappings = MidiMappings::default();
+        learn(&mut mappings, "speed", &[0x91, 60, 127]);
+        let values = map(
+            &mut mappings,
+            &mut MidiLearn::default(),
+            // a release maps to the bottom, other notes and channels are ignored
+            &[0x91, 60, 127, 0x81, 60, 64, 0x90, 60, 127, 0x91, 61, 127],
+        );
+        assert_eq!(
+            values,
+            [ExternalValue::Number(10.0), ExternalValue::Number(0.0)]
+        );
+
+        let mut mappings = MidiMappings::default();
+        learn(&mut mappings, "speed", &[0xe0, 0, 0]);
+        let values = map(
+            &mut mappings,
+            &mut MidiLearn::default(),
+            &[0xe0, 0, 0, 0xe0, 0x7f, 0x7f],
+        );
+        assert_eq!(
+            values,
+            [ExternalValue::Number(0.0), ExternalValue::Number(10.0)]
+        );
+    }
+
+    #[test]
+    fn curve_endpoints_and_midpoints() {
+        for curve in MidiCurve::ALL {
+            assert_eq!(curve.apply(0.0), 0.0, "{curve:?}");
+            assert_eq!(curve.apply(1.0), 1.0, "{curve:?}");
+            assert_eq!(curve.apply(-1.0), 0.0, "{curve:?}");
+            assert_eq!(curve.apply(2.0), 1.0, "{curve:?}");
+        }
+        assert_eq!(MidiCurve::Linear.apply(0.5), 0.5);
+        assert_eq!(MidiCurve::Exponential.apply(0.5), 0.25);
+        assert_eq!(MidiCurve::Logarithmic.apply(0.25), 0.5);
+        assert_eq!(MidiCurve::Smooth.apply(0.5), 0.5);
+        assert!(MidiCurve::Smooth.apply(0.1) < 0.1);
+        assert!(MidiCurve::Smooth.apply(0.9) > 0.9);
+    }
+
+    #[test]
+    fn curves_shape_the_mapped_value() {
+        let mut mappings = MidiMappings::default();
+        learn(&mut mappings, "speed", &[0xb0, 1, 0]);
+        mappings.0[0].curve = MidiCurve::Exponential;
+        // 64 of 127 is just over half
+        let values = map(&mut mappings, &mut MidiLearn::default(), &[0xb0, 1, 64]);
+        let t = 64.0 / 127.0;
+        assert!((number(&values[0]) - t * t * 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn bool_and_choice_mappings() {
+        let mut mappings = MidiMappings::default();
+        learn(&mut mappings, "invert", &[0xb0, 1, 0]);
+        learn(&mut mappings, "mode", &[0xb0, 2, 0]);
+        let values = map(
+            &mut mappings,
+            &mut MidiLearn::default(),
+            &[
+                0xb0, 1, 63, 0xb0, 1, 64, 0xb0, 2, 0, 0xb0, 2, 64, 0xb0, 2, 127,
+            ],
+        );
+        assert_eq!(
+            values,
+            [
+                ExternalValue::Bool(false),
+                ExternalValue::Bool(true),
+                ExternalValue::Text("a".to_string()),
+                ExternalValue::Text("b".to_string()),
+                ExternalValue::Text("c".to_string()),
+            ]
+        );
+    }
+}
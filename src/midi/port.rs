@@ -0,0 +1,56 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+
+/// The raw MIDI ports of the system, virtual ones like the ports of ALSA's `snd-virmidi` included.
+/// Only Linux exposes its ports as device files, elsewhere this finds none
+pub fn list_ports() -> Vec<String> {
+    let mut ports: Vec<String> = ["/dev/snd", "/dev"]
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("midi"))
+        .map(|entry| entry.path().to_string_lossy().to_string())
+        .collect();
+    ports.sort();
+    ports
+}
+
+/// A MIDI input port read without blocking
+pub struct MidiPort {
+    file: File,
+}
+
+impl MidiPort {
+    #[cfg(unix)]
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)?;
+        Ok(MidiPort { file })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(_path: &str) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "MIDI ports can only be opened on Linux and other unix systems",
+        ))
+    }
+
+    /// Appends the bytes that arrived since the last read
+    pub fn read(&mut self, bytes: &mut Vec<u8>) -> std::io::Result<()> {
+        let mut buffer = [0; 1024];
+        loop {
+            match self.file.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(len) => bytes.extend_from_slice(&buffer[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
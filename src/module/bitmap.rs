@@ -0,0 +1,341 @@
+use bevy::asset::LoadedFolder;
+use bevy::camera::RenderTarget;
+use bevy::prelude::*;
+
+use crate::module::*;
+use crate::pipeline::RenderTargetPool;
+use crate::params::{LiveParams, ModuleParams, Param};
+use crate::playback::Playback;
+
+pub struct BitmapModule;
+
+impl Plugin for BitmapModule {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Startup), setup)
+            .add_systems(
+                Update,
+                (load_bitmap_source, sort_sequence_frames, show_bitmap_frame)
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, mut spawnerconfig: ResMut<ModuleSpawnerConfig>) {
+    let eid = commands
+        .spawn((ModuleSpawner {
+            class: ModuleClass::Image,
+        },))
+        .observe(spawn_bitmap_module)
+        .observe(resize_bitmap)
+        .id();
+
+    spawnerconfig
+        .observers
+        .insert(ModuleClass::Image, vec![eid]);
+}
+
+/// Where the frames of an image module come from.
+/// A path to a single file loads a still image, a path to a folder loads every image in it as a sequence,
+/// ordered by file name with numbers compared by value, so `frame2` comes before `frame10`.
+#[derive(Default)]
+enum BitmapSource {
+    #[default]
+    None,
+    Still(Handle<Image>),
+    Sequence {
+        folder: Handle<LoadedFolder>,
+        /// The images of the folder in order, sorted once it has loaded
+        frames: Vec<Handle<Image>>,
+    },
+}
+
+/// The sprite inside the module's render layer showing the current frame
+#[derive(Component, Default)]
+pub struct BitmapContent {
+    path: String,
+    source: BitmapSource,
+}
+
+const FIT_MODES: [&str; 3] = ["contain", "cover", "stretch"];
+const LOOP_MODES: [&str; 3] = ["loop", "once", "ping-pong"];
+
+fn default_params() -> ModuleParams {
+    ModuleParams(vec![
+        Param::text("path", "icon.png"),
+        Param::choice(
+            "fit",
+            "contain",
+            FIT_MODES.iter().map(|f| f.to_string()).collect(),
+        ),
+        Param::float("frame rate", 24.0, 1.0..=120.0),
+        Param::choice(
+            "loop mode",
+            "loop",
+            LOOP_MODES.iter().map(|l| l.to_string()).collect(),
+        ),
+        Param::float("start time", 0.0, 0.0..=600.0),
+        Param::text(SHADER_CHAIN_PARAM, ""),
+    ])
+}
+
+fn spawn_bitmap_module(
+    spawn: On<SpawnModuleInternalEvent>,
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
+) {
+    debug!("spawning image module");
+
+    let image_handle = pool.acquire(
+        &mut images,
+        spawn.root_id,
+        UVec2::new(BOXWIDTH as u32, BOXHEIGHT as u32),
+        TextureFormat::bevy_default(),
+    );
+
+    // a module restored from a snapshot already has its parameters
+    commands
+        .entity(spawn.root_id)
+        .insert_if_new(default_params());
+
+    commands.spawn((
+        Sprite::default(),
+        BitmapContent::default(),
+        Visibility::Hidden,
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
+    ));
+
+    commands.spawn((
+        Camera2d,
+        RenderTarget::Image(image_handle.clone().into()),
+        Camera {
+            clear_color: Color::NONE.into(),
+            ..default()
+        },
+        ShaderChainCamera::default(),
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
+    ));
+
+    //Sprite to display the rendered texture
+    let sprite = commands
+        .spawn((ModulePart(spawn.root_id), Sprite::from_image(image_handle)))
+        .id();
+
+    commands.entity(spawn.root_id).add_child(sprite);
+}
+
+fn resize_bitmap(
+    resize: On<ResizeModuleInternal>,
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
+) {
+    pool.resize(
+        &mut images,
+        resize.moduleroot,
+        UVec2::new(resize.width as u32, resize.height as u32),
+    );
+}
+
+/// (Re)loads the still image or sequence folder when the path parameter changes
+fn load_bitmap_source(
+    asset_server: Res<AssetServer>,
+    roots: Query<&LiveParams, Changed<LiveParams>>,
+    mut contents: Query<(&mut BitmapContent, &ModulePart)>,
+) {
+    for (mut content, part) in contents.iter_mut() {
+        let Ok(LiveParams(params)) = roots.get(part.0) else {
+            continue;
+        };
+        let path = params.text("path").trim();
+        if path == content.path {
+            continue;
+        }
+
+        content.path = path.to_string();
+        content.source = if path.is_empty() {
+            BitmapSource::None
+        } else if std::path::Path::new(path).extension().is_some() {
+            BitmapSource::Still(asset_server.load(path.to_string()))
+        } else {
+            BitmapSource::Sequence {
+                folder: asset_server.load_folder(path.to_string()),
+                frames: vec![],
+            }
+        };
+    }
+}
+
+/// Orders the images of a sequence once its folder has loaded, and again when the folder changes
+fn sort_sequence_frames(
+    folders: Res<Assets<LoadedFolder>>,
+    mut events: MessageReader<AssetEvent<LoadedFolder>>,
+    mut contents: Query<&mut BitmapContent>,
+) {
+    let modified: Vec<AssetId<LoadedFolder>> = events
+        .read()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { id } => Some(*id),
+            _ => None,
+        })
+        .collect();
+
+    for mut content in contents.iter_mut() {
+        let BitmapSource::Sequence { folder, frames } = &content.source else {
+            continue;
+        };
+        if !frames.is_empty() && !modified.contains(&folder.id()) {
+            continue;
+        }
+        let Some(loaded) = folders.get(folder) else {
+            continue;
+        };
+
+        let mut sorted: Vec<(String, Handle<Image>)> = loaded
+            .handles
+            .iter()
+            .filter_map(|handle| handle.clone().try_typed::<Image>().ok())
+            .map(|frame| {
+                let path = frame.path().map(|path| path.to_string()).unwrap_or_default();
+                (path, frame)
+            })
+            .collect();
+        sorted.sort_by(|(a, _), (b, _)| natural_cmp(a, b));
+        if let BitmapSource::Sequence { frames, .. } = &mut content.source {
+            *frames = sorted.into_iter().map(|(_, frame)| frame).collect();
+        }
+    }
+}
+
+fn digit_run(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+/// Compares names with runs of digits by their value, `frame2` sorts before `frame10`
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return std::cmp::Ordering::Equal,
+            (None, Some(_)) => return std::cmp::Ordering::Less,
+            (Some(_), None) => return std::cmp::Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (digit_run(&mut a), digit_run(&mut b));
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                // longer numbers are bigger, equal values with fewer leading zeros go first
+                let order = x_value
+                    .len()
+                    .cmp(&y_value.len())
+                    .then_with(|| x_value.cmp(y_value))
+                    .then_with(|| x.len().cmp(&y.len()));
+                if order.is_ne() {
+                    return order;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+/// Picks the frame for the current playback time and fits it into the module
+fn show_bitmap_frame(
+    playback: Res<Playback>,
+    images: Res<Assets<Image>>,
+    roots: Query<(&LiveParams, &ModuleWin)>,
+    mut contents: Query<(&BitmapContent, &ModulePart, &mut Sprite, &mut Visibility)>,
+) {
+    for (content, part, mut sprite, mut visibility) in contents.iter_mut() {
+        let Ok((LiveParams(params), mw)) = roots.get(part.0) else {
+            continue;
+        };
+
+        let frame = match &content.source {
+            BitmapSource::None => None,
+            BitmapSource::Still(handle) => Some(handle.clone()),
+            BitmapSource::Sequence { frames, .. } => {
+                let time = (playback.time - params.float("start time")).max(0.0);
+                frame_index(
+                    (time * params.float("frame rate")).floor() as usize,
+                    frames.len(),
+                    params.text("loop mode"),
+                )
+                .map(|index| frames[index].clone())
+            }
+        };
+
+        let Some(frame) = frame else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+        let Some(size) = images.get(&frame).map(|image| image.size_f32()) else {
+            continue;
+        };
+        visibility.set_if_neq(Visibility::Inherited);
+
+        if sprite.image != frame {
+            sprite.image = frame;
+        }
+
+        let area = Vec2::new(mw.width, mw.height);
+        let custom_size = match params.text("fit") {
+            "stretch" => area,
+            // everything outside the render target is cropped
+            "cover" => size * (area / size).max_element(),
+            _ => size * (area / size).min_element(),
+        };
+        if sprite.custom_size != Some(custom_size) {
+            sprite.custom_size = Some(custom_size);
+        }
+    }
+}
+
+/// Maps a frame counter onto a sequence of `len` frames
+fn frame_index(frame: usize, len: usize, loop_mode: &str) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+
+    Some(match loop_mode {
+        "once" => frame.min(len - 1),
+        "ping-pong" if len > 1 => {
+            let period = 2 * len - 2;
+            let i = frame % period;
+            if i < len { i } else { period - i }
+        }
+        _ => frame % len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::natural_cmp;
+
+    #[test]
+    fn numbers_sort_by_value() {
+        let mut names = vec!["frame10.png", "frame2.png", "frame1.png", "frame11.png", "frame02.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["frame1.png", "frame2.png", "frame02.png", "frame10.png", "frame11.png"]
+        );
+    }
+
+    #[test]
+    fn text_sorts_by_character() {
+        let mut names = vec!["b1.png", "a10.png", "a9.png", "a.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a.png", "a9.png", "a10.png", "b1.png"]);
+    }
+}
@@ -1,24 +1,43 @@
 use crate::common::*;
+use crate::keymap::{Action, action_just_pressed};
 use crate::module::noise::spawn_noise_module;
-use crate::rendering::ShaderChainPlugin;
+use crate::params::{LiveParams, ModuleParams};
+use crate::rendering::{ShaderChainCamera, ShaderChainPlugin};
 
 use bevy::platform::collections::HashMap;
 use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
 // use bevy_simple_subsecond_system::prelude::*;
 
 //import noisemodule
+mod bitmap;
 mod noise;
 mod pong;
+mod shadertoy;
+mod text;
 
-use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::render_resource::TextureFormat;
 
-use bevy::asset::RenderAssetUsages;
 use bevy::camera::visibility::RenderLayers;
 
-#[derive(Clone, Copy, PartialEq, Debug, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Debug, Eq, Hash, Serialize, Deserialize)]
 pub enum ModuleClass {
     Pong,
     Noise,
+    Text,
+    Image,
+    Shader,
+}
+
+impl ModuleClass {
+    pub const ALL: [ModuleClass; 5] = [
+        ModuleClass::Pong,
+        ModuleClass::Noise,
+        ModuleClass::Text,
+        ModuleClass::Image,
+        ModuleClass::Shader,
+    ];
 }
 
 trait ModuleClassAttrs {
@@ -32,6 +51,92 @@ trait HasModuleClass {
 #[derive(Event)]
 pub struct SpawnModuleEvent {
     pub moduleclass: ModuleClass,
+    /// Recreates a module as it was instead of spawning a new one
+    pub snapshot: Option<ModuleSnapshot>,
+}
+
+/// Identifies a module instance across despawning and respawning it from a snapshot
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ModuleId(pub u64);
+
+#[derive(Resource, Default)]
+pub struct NextModuleId(pub u64);
+
+/// Everything needed to recreate a module instance
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModuleSnapshot {
+    pub id: ModuleId,
+    pub class: ModuleClass,
+    pub name: String,
+    /// Where the anchor of the module is on the canvas
+    pub position: Vec2,
+    /// Radians around the anchor
+    pub rotation: f32,
+    pub scale: Vec2,
+    pub anchor: Vec2,
+    pub size: Vec2,
+    pub layer: ModuleLayer,
+    pub params: Option<ModuleParams>,
+}
+
+/// The components a [`ModuleSnapshot`] is taken from
+pub type SnapshotData = (
+    Entity,
+    &'static ModuleId,
+    &'static ModuleWin,
+    &'static Name,
+    &'static Transform,
+    &'static ModuleAnchor,
+    &'static ModuleLayer,
+    Option<&'static ModuleParams>,
+);
+
+impl ModuleSnapshot {
+    pub fn take(
+        (_, id, mw, name, transform, anchor, layer, params): (
+            Entity,
+            &ModuleId,
+            &ModuleWin,
+            &Name,
+            &Transform,
+            &ModuleAnchor,
+            &ModuleLayer,
+            Option<&ModuleParams>,
+        ),
+    ) -> Self {
+        ModuleSnapshot {
+            id: *id,
+            class: mw.class,
+            name: name.to_string(),
+            position: transform.translation.truncate(),
+            rotation: transform.rotation.to_euler(EulerRot::XYZ).2,
+            scale: transform.scale.truncate(),
+            anchor: anchor.0,
+            size: Vec2::new(mw.width, mw.height),
+            layer: layer.clone(),
+            params: params.cloned(),
+        }
+    }
+}
+
+/// The point of a module its [`Transform`] positions, rotates and scales it around.
+/// Like a sprite [`Anchor`](bevy::sprite::Anchor), (-0.5, -0.5) is the bottom left corner and (0, 0) the centre.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModuleAnchor(pub Vec2);
+
+impl ModuleAnchor {
+    /// The area a module of this size covers in its own, untransformed space
+    pub fn local_rect(&self, size: Vec2) -> Rect {
+        let min = (Vec2::splat(-0.5) - self.0) * size;
+        Rect::from_corners(min, min + size)
+    }
+}
+
+/// Puts an existing module back into the state of a snapshot of it
+#[derive(EntityEvent)]
+pub struct RestoreModule {
+    pub entity: Entity,
+    pub snapshot: ModuleSnapshot,
 }
 
 #[derive(EntityEvent)]
@@ -65,8 +170,125 @@ pub struct ResizeModuleInternal {
     pub height: f32,
 }
 
+/// Despawns a module root together with all of its parts
+#[derive(EntityEvent)]
+pub struct DespawnModule {
+    pub entity: Entity,
+}
+
+/// Renames a module, the name is made unique among all modules
+#[derive(EntityEvent)]
+pub struct RenameModule {
+    pub entity: Entity,
+    pub name: String,
+}
+
+/// The module instances the editor is working on.
+/// The last one is the active module, its parameter window is the one that is shown.
+#[derive(Resource, Default)]
+pub struct Selection(pub Vec<Entity>);
+
+impl Selection {
+    pub fn active(&self) -> Option<Entity> {
+        self.0.last().copied()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.0.contains(&entity)
+    }
+
+    /// Selects only this module
+    pub fn select(&mut self, entity: Entity) {
+        self.0.clear();
+        self.0.push(entity);
+    }
+
+    /// Makes the module the active one, adding it to the selection if it wasn't selected
+    pub fn activate(&mut self, entity: Entity) {
+        self.remove(entity);
+        self.0.push(entity);
+    }
+
+    /// Adds the module to the selection, or removes it if it already was selected
+    pub fn toggle(&mut self, entity: Entity) {
+        if self.contains(entity) {
+            self.remove(entity);
+        } else {
+            self.0.push(entity);
+        }
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        self.0.retain(|e| *e != entity);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Where a module sits in the layer stack, edited in the layers panel
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModuleLayer {
+    /// Modules with a higher order are drawn on top
+    pub order: u32,
+    pub visible: bool,
+    /// Locked modules can't be moved or resized on the canvas
+    pub locked: bool,
+    pub solo: bool,
+}
+
+impl ModuleLayer {
+    pub fn new(order: u32) -> Self {
+        ModuleLayer {
+            order,
+            visible: true,
+            locked: false,
+            solo: false,
+        }
+    }
+
+    /// While any module is soloed only the soloed ones are shown
+    pub fn is_shown(&self, any_solo: bool) -> bool {
+        self.visible && (!any_solo || self.solo)
+    }
+}
+
+/// Hands out the render layers modules render their first pass on.
+/// Layer 0 is the canvas, the layers of despawned modules are handed out again.
 #[derive(Resource)]
-pub struct ModuleLayerCounter(pub u8);
+pub struct ModuleLayerCounter {
+    next: usize,
+    free: Vec<usize>,
+}
+
+impl Default for ModuleLayerCounter {
+    fn default() -> Self {
+        ModuleLayerCounter {
+            next: 1,
+            free: vec![],
+        }
+    }
+}
+
+impl ModuleLayerCounter {
+    pub fn acquire(&mut self) -> usize {
+        self.free.pop().unwrap_or_else(|| {
+            self.next += 1;
+            self.next - 1
+        })
+    }
+
+    pub fn release(&mut self, layer: usize) {
+        if !self.free.contains(&layer) {
+            self.free.push(layer);
+        }
+    }
+}
+
+/// The render layer a module root got from the [`ModuleLayerCounter`]
+#[derive(Component)]
+pub struct ModuleRenderLayer(pub usize);
 
 #[derive(Resource)]
 pub struct ModuleSpawnerConfig {
@@ -88,11 +310,29 @@ pub struct ModulePlugin;
 impl Plugin for ModulePlugin {
     fn build(&self, app: &mut App) {
         app
-            .insert_resource(ModuleLayerCounter(1))
+            .init_resource::<ModuleLayerCounter>()
             .insert_resource(ModuleSpawnerConfig{observers: HashMap::new()})
+            .init_resource::<Selection>()
+            .init_resource::<NextModuleId>()
             .add_observer(spawn_module_observer)
+            .add_systems(OnEnter(AppState::Startup), reset_modules)
+            .add_plugins(ShaderChainPlugin)
+            .add_systems(Update, apply_shader_chain.run_if(in_state(AppState::Running)))
+            .add_systems(Update, apply_module_layers.run_if(in_state(AppState::Running)))
+            .add_systems(Update, apply_module_anchor.run_if(in_state(AppState::Running)))
+            .add_systems(
+                Update,
+                (
+                    delete_selected.run_if(action_just_pressed(Action::DeleteModule)),
+                    duplicate_selected.run_if(action_just_pressed(Action::DuplicateModule)),
+                )
+                    .run_if(in_state(AppState::Running)),
+            )
             .add_plugins(noise::NoiseModule)
             .add_plugins(pong::PongModule)
+            .add_plugins(text::TextModule)
+            .add_plugins(bitmap::BitmapModule)
+            .add_plugins(shadertoy::ShaderToyModule)
             // .add_systems(Update, (
             //     handle_click
             //         .run_if(input_just_pressed(MouseButton::Left)),
@@ -106,6 +346,29 @@ pub struct FirstPassEntity {
     module_id: Entity,
 }
 
+/// Lists the file names in an asset directory that have one of the given extensions, sorted.
+/// Assets can't be listed on the web, so this is always empty there.
+pub fn asset_files(dir: &str, extensions: &[&str]) -> Vec<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Ok(entries) =
+        std::fs::read_dir(bevy::asset::io::file::FileAssetReader::get_base_path().join("assets").join(dir))
+    {
+        let mut found: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+            })
+            .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+            .collect();
+        found.sort();
+        return found;
+    }
+
+    vec![]
+}
+
 fn trigger_spawner<'a, E: Event<Trigger<'a>: Default>, F>(
     mut commands: Commands,
     spawnconfig: &ModuleSpawnerConfig,
@@ -125,55 +388,81 @@ fn trigger_spawner<'a, E: Event<Trigger<'a>: Default>, F>(
 pub fn spawn_module_observer(
     spawn: On<SpawnModuleEvent>,
     mut commands: Commands,
-    mut images: ResMut<Assets<Image>>,
     mut layer_counter: ResMut<ModuleLayerCounter>,
     spawnconfig: Res<ModuleSpawnerConfig>,
+    modules: Query<(&Name, &ModuleLayer), With<ModuleWin>>,
+    mut next_id: ResMut<NextModuleId>,
 ) {
-    println!("module setup!");
-    // rendered texture
-    let size = Extent3d {
-        width: BOXWIDTH as u32,
-        height: BOXHEIGHT as u32,
-        ..default()
+    debug!("spawning {:?} module instance", spawn.moduleclass);
+    // each module gets its render targets from the RenderTargetPool in its own spawn observer
+    let snapshot = spawn.snapshot.as_ref();
+    let id = match snapshot {
+        Some(snapshot) => {
+            next_id.0 = next_id.0.max(snapshot.id.0 + 1);
+            snapshot.id
+        }
+        None => {
+            next_id.0 += 1;
+            ModuleId(next_id.0 - 1)
+        }
     };
-
-    // This is the texture that will be rendered to.
-    let mut image = Image::new_fill(
-        size,
-        TextureDimension::D2,
-        &[0, 0, 0, 255],
-        TextureFormat::Bgra8UnormSrgb,
-        RenderAssetUsages::default(),
+    let name = match snapshot {
+        Some(snapshot) => snapshot.name.clone(),
+        None => unique_name(
+            &format!("{:?} 1", spawn.moduleclass),
+            &modules
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let size = snapshot.map_or(Vec2::new(BOXWIDTH, BOXHEIGHT), |snapshot| snapshot.size);
+    // new modules go on top of the others
+    let layer = snapshot.map_or_else(
+        || {
+            let top = modules.iter().map(|(_, layer)| layer.order).max();
+            ModuleLayer::new(top.map_or(1, |top| top + 1))
+        },
+        |snapshot| snapshot.layer.clone(),
     );
+    let transform = snapshot.map_or(
+        Transform::from_translation(Vec3::new(0.0, 0.0, layer.order as f32 * 0.01)),
+        |snapshot| {
+            Transform::from_translation(snapshot.position.extend(layer.order as f32 * 0.01))
+                .with_rotation(Quat::from_rotation_z(snapshot.rotation))
+                .with_scale(snapshot.scale.extend(1.0))
+        },
+    );
+    let render_layer = layer_counter.acquire();
 
-    // You need to set these texture usage flags in order to use the image as a render target
-    image.texture_descriptor.usage =
-        TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
-
-    let image_handle = images.add(image);
-
-    //Sprite to display the rendered texture
-    let mut sprite = Sprite::from_image(image_handle.clone());
-    sprite.custom_size = Some(Vec2 {
-        x: BOXWIDTH,
-        y: BOXHEIGHT,
-    });
     let spriteid = commands
         .spawn((
             // sprite,
             ModuleWin {
                 class: spawn.moduleclass,
-                width: BOXWIDTH,
-                height: BOXHEIGHT,
+                width: size.x,
+                height: size.y,
             },
-            Transform::from_translation(Vec3::new(0.0, 0.0, layer_counter.0 as f32 * 0.01)),
+            id,
+            Name::new(name),
+            layer,
+            ModuleRenderLayer(render_layer),
+            ModuleAnchor(snapshot.map_or(Vec2::ZERO, |snapshot| snapshot.anchor)),
+            Visibility::default(),
+            transform,
         ))
         .observe(resize_image_observer)
+        .observe(despawn_module_observer)
+        .observe(rename_module_observer)
+        .observe(restore_module_observer)
         .id();
 
+    if let Some(params) = snapshot.and_then(|snapshot| snapshot.params.clone()) {
+        commands.entity(spriteid).insert(params);
+    }
+
     // This specifies the layer used for the first pass, which will be attached to the first pass camera and cube.
-    let first_pass_layer = RenderLayers::layer(layer_counter.0 as usize);
-    layer_counter.0 += 1;
+    let first_pass_layer = RenderLayers::layer(render_layer);
 
     //first pass camera
     // commands.spawn((
@@ -188,7 +477,7 @@ pub fn spawn_module_observer(
     // ));
 
     trigger_spawner::<SpawnModuleInternalEvent, _>(
-        commands,
+        commands.reborrow(),
         &spawnconfig,
         spawn.moduleclass,
         |spawner| SpawnModuleInternalEvent {
@@ -198,6 +487,58 @@ pub fn spawn_module_observer(
             root_id: spriteid,
         },
     );
+
+    // modules create their render targets at the default size
+    if size != Vec2::new(BOXWIDTH, BOXHEIGHT) {
+        commands.trigger(ResizeModule {
+            entity: spriteid,
+            width: size.x,
+            height: size.y,
+        });
+    }
+}
+
+fn restore_module_observer(
+    restore: On<RestoreModule>,
+    mut commands: Commands,
+    mut roots: Query<(
+        &mut Name,
+        &mut ModuleWin,
+        &mut Transform,
+        &mut ModuleAnchor,
+        &mut ModuleLayer,
+    )>,
+) {
+    let Ok((mut name, mut mw, mut transform, mut anchor, mut layer)) =
+        roots.get_mut(restore.entity)
+    else {
+        return;
+    };
+    let snapshot = &restore.snapshot;
+
+    if name.as_str() != snapshot.name {
+        name.set(snapshot.name.clone());
+    }
+    *layer = snapshot.layer.clone();
+
+    anchor.0 = snapshot.anchor;
+    transform.translation = snapshot.position.extend(transform.translation.z);
+    transform.rotation = Quat::from_rotation_z(snapshot.rotation);
+    transform.scale = snapshot.scale.extend(1.0);
+
+    if Vec2::new(mw.width, mw.height) != snapshot.size {
+        mw.width = snapshot.size.x;
+        mw.height = snapshot.size.y;
+        commands.trigger(ResizeModule {
+            entity: restore.entity,
+            width: snapshot.size.x,
+            height: snapshot.size.y,
+        });
+    }
+
+    if let Some(params) = &snapshot.params {
+        commands.entity(restore.entity).insert(params.clone());
+    }
 }
 
 fn resize_image_observer(
@@ -219,3 +560,230 @@ fn resize_image_observer(
         });
     }
 }
+
+fn despawn_module_observer(
+    despawn: On<DespawnModule>,
+    mut commands: Commands,
+    mut selection: ResMut<Selection>,
+    mut layer_counter: ResMut<ModuleLayerCounter>,
+    render_layers: Query<&ModuleRenderLayer>,
+) {
+    selection.remove(despawn.entity);
+    if let Ok(layer) = render_layers.get(despawn.entity) {
+        layer_counter.release(layer.0);
+    }
+    // parts are despawned through the ModuleWithParts relationship
+    commands.entity(despawn.entity).despawn();
+}
+
+/// The modules were torn down by a restart, their render layers and selection are free again
+fn reset_modules(mut layer_counter: ResMut<ModuleLayerCounter>, mut selection: ResMut<Selection>) {
+    *layer_counter = ModuleLayerCounter::default();
+    selection.clear();
+}
+
+/// Locked modules are kept
+fn delete_selected(
+    mut commands: Commands,
+    selection: Res<Selection>,
+    #[cfg(feature = "editor")] mut history: ResMut<crate::history::History>,
+    layers: Query<&ModuleLayer>,
+) {
+    #[cfg(feature = "editor")]
+    history.record_spawns();
+    for entity in selection.0.iter() {
+        if layers.get(*entity).is_ok_and(|layer| !layer.locked) {
+            commands.trigger(DespawnModule { entity: *entity });
+        }
+    }
+}
+
+/// Where a duplicate is put relative to its original
+const DUPLICATE_OFFSET: Vec2 = Vec2::new(20.0, -20.0);
+
+/// Spawns a copy of every selected module on top of the others, with its own id and name
+fn duplicate_selected(
+    mut commands: Commands,
+    selection: Res<Selection>,
+    #[cfg(feature = "editor")] mut history: ResMut<crate::history::History>,
+    mut next_id: ResMut<NextModuleId>,
+    modules: Query<SnapshotData>,
+) {
+    #[cfg(feature = "editor")]
+    history.record_spawns();
+    let mut taken: Vec<String> = modules.iter().map(|data| data.3.to_string()).collect();
+    let mut top = modules.iter().map(|data| data.6.order).max().unwrap_or(0);
+
+    for entity in selection.0.iter() {
+        let Ok(data) = modules.get(*entity) else {
+            continue;
+        };
+        let mut snapshot = ModuleSnapshot::take(data);
+        snapshot.id = ModuleId(next_id.0);
+        next_id.0 += 1;
+        snapshot.name = unique_name(
+            &snapshot.name,
+            &taken.iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        taken.push(snapshot.name.clone());
+        snapshot.position += DUPLICATE_OFFSET;
+        top += 1;
+        snapshot.layer.order = top;
+
+        commands.trigger(SpawnModuleEvent {
+            moduleclass: snapshot.class,
+            snapshot: Some(snapshot),
+        });
+    }
+}
+
+/// Returns `name` if it's free, otherwise the first free "name N" from 2 on, where a number already ending `name` is replaced
+pub fn unique_name(name: &str, taken: &[&str]) -> String {
+    if !taken.contains(&name) {
+        return name.to_string();
+    }
+
+    let base = name
+        .rsplit_once(' ')
+        .filter(|(_, number)| number.parse::<u32>().is_ok())
+        .map_or(name, |(base, _)| base);
+    // the name without a number counts as the first
+    (2..)
+        .map(|n| format!("{base} {n}"))
+        .find(|candidate| !taken.contains(&candidate.as_str()))
+        .unwrap()
+}
+
+fn rename_module_observer(
+    rename: On<RenameModule>,
+    mut modules: Query<(Entity, &mut Name), With<ModuleWin>>,
+) {
+    let name = rename.name.trim();
+    if name.is_empty() {
+        return;
+    }
+
+    let taken: Vec<String> = modules
+        .iter()
+        .filter(|(entity, _)| *entity != rename.entity)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    let name = unique_name(name, &taken.iter().map(String::as_str).collect::<Vec<_>>());
+
+    if let Ok((_, mut current)) = modules.get_mut(rename.entity) {
+        current.set(name);
+    }
+}
+
+/// Anchors the sprites showing a module's render target at the module's [`ModuleAnchor`]
+fn apply_module_anchor(
+    roots: Query<(&ModuleAnchor, &Children), With<ModuleWin>>,
+    mut sprites: Query<&mut Anchor, With<Sprite>>,
+) {
+    for (anchor, children) in roots.iter() {
+        for child in children.iter() {
+            if let Ok(mut sprite_anchor) = sprites.get_mut(child) {
+                sprite_anchor.set_if_neq(Anchor(anchor.0));
+            }
+        }
+    }
+}
+
+/// Derives the z position and visibility of every module from its [`ModuleLayer`]
+fn apply_module_layers(
+    mut roots: Query<(&ModuleLayer, &mut Transform, &mut Visibility), With<ModuleWin>>,
+) {
+    let any_solo = roots.iter().any(|(layer, ..)| layer.solo);
+    for (layer, mut transform, mut visibility) in roots.iter_mut() {
+        transform.translation.z = layer.order as f32 * 0.01;
+        visibility.set_if_neq(if layer.is_shown(any_solo) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+/// Modules that expose this parameter get its comma separated list of shaders
+/// applied to the [`ShaderChainCamera`] that renders their target
+pub const SHADER_CHAIN_PARAM: &str = "shader chain";
+
+fn apply_shader_chain(
+    roots: Query<(&LiveParams, &ModuleWithParts), Changed<LiveParams>>,
+    mut cameras: Query<&mut ShaderChainCamera>,
+) {
+    for (LiveParams(params), parts) in roots.iter() {
+        if params.get(SHADER_CHAIN_PARAM).is_none() {
+            continue;
+        }
+
+        let shaders: Vec<String> = params
+            .text(SHADER_CHAIN_PARAM)
+            .split(',')
+            .map(str::trim)
+            .filter(|shader| !shader.is_empty())
+            .map(String::from)
+            .collect();
+
+        for part in parts.iter() {
+            if let Ok(mut chain) = cameras.get_mut(part)
+                && chain.shaders != shaders
+            {
+                chain.shaders = shaders.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_layers_are_reused() {
+        let mut counter = ModuleLayerCounter::default();
+        let layers: Vec<usize> = (0..300).map(|_| counter.acquire()).collect();
+        assert_eq!(layers, (1..301).collect::<Vec<_>>());
+
+        counter.release(7);
+        counter.release(7);
+        counter.release(200);
+        assert_eq!(counter.acquire(), 200);
+        assert_eq!(counter.acquire(), 7);
+        assert_eq!(counter.acquire(), 301);
+    }
+
+    #[test]
+    fn free_names_are_kept() {
+        assert_eq!(unique_name("Noise", &[]), "Noise");
+        assert_eq!(unique_name("Noise", &["Noise 1", "Pong"]), "Noise");
+    }
+
+    #[test]
+    fn taken_names_get_the_next_number() {
+        assert_eq!(unique_name("Noise", &["Noise"]), "Noise 2");
+        assert_eq!(unique_name("Noise", &["Noise", "Noise 2"]), "Noise 3");
+        assert_eq!(unique_name("Noise 1", &["Noise 1"]), "Noise 2");
+    }
+
+    #[test]
+    fn a_number_ending_the_name_is_replaced() {
+        assert_eq!(unique_name("Noise 7", &["Noise 7"]), "Noise 2");
+        assert_eq!(unique_name("Noise 2", &["Noise 2"]), "Noise 3");
+        // only a whole number counts
+        assert_eq!(unique_name("Noise 2b", &["Noise 2b"]), "Noise 2b 2");
+        assert_eq!(unique_name("Noise2", &["Noise2"]), "Noise2 2");
+    }
+
+    #[test]
+    fn gaps_are_filled_first() {
+        assert_eq!(
+            unique_name("Noise 1", &["Noise 1", "Noise 3", "Noise 4"]),
+            "Noise 2"
+        );
+        assert_eq!(
+            unique_name("Noise", &["Noise", "Noise 2", "Noise 4"]),
+            "Noise 3"
+        );
+    }
+}
@@ -1,46 +1,31 @@
-use bevy::asset::uuid::Uuid;
 use bevy::camera::RenderTarget;
 use bevy::prelude::*;
+use bevy::render::render_resource::ShaderType;
 
 use crate::module::*;
+use crate::pipeline::RenderTargetPool;
+use crate::params::{GradientStop, LiveParams, ModuleParams, Param, sorted_stops};
+use crate::playback::Playback;
 use crate::rendering::*;
 
 use bevy::sprite_render::Material2dPlugin;
 use bevy::{reflect::TypePath, render::render_resource::AsBindGroup};
 
 use bevy::{shader::ShaderRef, sprite_render::Material2d};
-use bevy_egui::{EguiContexts, EguiPrimaryContextPass, egui};
 
 pub struct NoiseModule;
 
 impl Plugin for NoiseModule {
     fn build(&self, app: &mut App) {
-        app.add_plugins((
-            Material2dPlugin::<NoiseMaterial>::default(),
-            ShaderChainPlugin,
-        ))
-        .add_systems(OnEnter(AppState::Startup), setup)
-        .add_systems(EguiPrimaryContextPass, ui_noise);
+        app.add_plugins(Material2dPlugin::<NoiseMaterial>::default())
+            .add_systems(OnEnter(AppState::Startup), setup)
+            .add_systems(
+                Update,
+                update_noise_material.run_if(in_state(AppState::Running)),
+            );
     }
 }
 
-fn ui_noise(
-    mut contexts: EguiContexts,
-    windows: Query<&mut Window>,
-    mut materials: ResMut<Assets<NoiseMaterial>>,
-) -> Result {
-    for (shaderid, shader) in materials.iter_mut() {
-        if let Ok(_win) = windows.single() {
-            egui::Window::new(format!("Noise params"))
-                .id(egui::Id::new(shaderid))
-                .show(contexts.ctx_mut()?, |ui| {
-                    ui.add(egui::Slider::new(&mut shader.speed, 0.0..=10.0).suffix("°"));
-                });
-        }
-    }
-    Ok(())
-}
-
 fn setup(mut commands: Commands, mut spawnerconfig: ResMut<ModuleSpawnerConfig>) {
     let eid = commands
         .spawn((ModuleSpawner {
@@ -55,21 +40,35 @@ fn setup(mut commands: Commands, mut spawnerconfig: ResMut<ModuleSpawnerConfig>)
         .insert(ModuleClass::Noise, vec![eid]);
 }
 
-// fn resize_rect(
+/// Maximum number of colour ramp stops the shader reads
+const MAX_STOPS: usize = 8;
+const MAX_OCTAVES: i32 = 8;
 
-// )
+/// Mirrors `NoiseSettings` in noise.wgsl
+#[derive(ShaderType, Debug, Clone, Default)]
+pub struct NoiseSettings {
+    pub time: f32,
+    pub width: f32,
+    pub height: f32,
+    pub speed: f32,
+    pub offset: Vec2,
+    pub drift: Vec2,
+    pub scale: f32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub seed: f32,
+    pub octaves: u32,
+    pub stop_count: u32,
+    /// Stop positions packed four per vector
+    pub stop_positions: [Vec4; MAX_STOPS / 4],
+    pub stop_colors: [Vec4; MAX_STOPS],
+}
 
 // This is the struct that will be passed to your shader
 #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
 pub struct NoiseMaterial {
     #[uniform(0)]
-    pub color: LinearRgba,
-    #[uniform(1)]
-    pub width: f32,
-    #[uniform(2)]
-    pub height: f32,
-    #[uniform(3)]
-    pub speed: f32,
+    pub settings: NoiseSettings,
 }
 
 /// This example uses a shader source file from the assets subdirectory
@@ -83,99 +82,158 @@ impl Material2d for NoiseMaterial {
     }
 }
 
+fn default_params() -> ModuleParams {
+    ModuleParams(vec![
+        Param::float("speed", 1.0, 0.0..=10.0),
+        Param::int("octaves", 4, 1..=MAX_OCTAVES),
+        Param::float("lacunarity", 2.0, 1.0..=4.0),
+        Param::float("gain", 0.5, 0.0..=1.0),
+        Param::float("scale", 10.0, 0.1..=100.0),
+        Param::vec2("offset", Vec2::ZERO, -1000.0..=1000.0),
+        Param::vec2("drift", Vec2::ZERO, -10.0..=10.0),
+        Param::int("seed", 0, 0..=1000),
+        Param::gradient(
+            "color ramp",
+            vec![
+                GradientStop {
+                    position: 0.0,
+                    color: LinearRgba::BLACK,
+                },
+                GradientStop {
+                    position: 1.0,
+                    color: LinearRgba::WHITE,
+                },
+            ],
+        ),
+        Param::text(
+            SHADER_CHAIN_PARAM,
+            "shaders/post_processing_2.wgsl, shaders/post_processing.wgsl",
+        ),
+    ])
+}
+
 pub fn spawn_noise_module(
     spawn: On<SpawnModuleInternalEvent>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut shadermaterials: ResMut<Assets<NoiseMaterial>>,
     mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
 ) {
-    let image = Image::new_target_texture(512, 512, TextureFormat::bevy_default(), None);
-    let image_handle = images.add(image);
-
-    let drawlayer = RenderLayers::layer(1);
-
     if spawn.moduleclass != ModuleClass::Noise {
         return;
     };
     // Spawn the noise module entities here
-    println!("Spawning Noise Module");
+    debug!("spawning noise module");
+
+    let image_handle = pool.acquire(
+        &mut images,
+        spawn.root_id,
+        UVec2::new(BOXWIDTH as u32, BOXHEIGHT as u32),
+        TextureFormat::bevy_default(),
+    );
 
+    // a module restored from a snapshot already has its parameters
+    commands
+        .entity(spawn.root_id)
+        .insert_if_new(default_params());
 
+    // settings are filled in by update_noise_material
     let shader = shadermaterials.add(NoiseMaterial {
-        color: LinearRgba::GREEN,
-        width: BOXWIDTH,
-        height: BOXHEIGHT,
-        speed: 1.0,
+        settings: NoiseSettings::default(),
     });
 
-    let shadersurface: Entity = commands
-        .spawn((
-            Mesh2d(meshes.add(Rectangle::new(1., 1.))),
-            //MeshMaterial2d(colormaterials.add(Color::srgb(0.0, 1.0, 0.0))),
-            MeshMaterial2d(shader.clone()),
-            Transform::default().with_scale(Vec3::new(BOXWIDTH, BOXHEIGHT, 1.0)),
-            FirstPassEntity {
-                module_id: spawn.root_id,
-            },
-            ModulePart(spawn.root_id),
-            drawlayer.clone(),
-        ))
-        .id();
+    commands.spawn((
+        Mesh2d(meshes.add(Rectangle::new(1., 1.))),
+        MeshMaterial2d(shader),
+        Transform::default().with_scale(Vec3::new(BOXWIDTH, BOXHEIGHT, 1.0)),
+        FirstPassEntity {
+            module_id: spawn.root_id,
+        },
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
+    ));
 
     commands.spawn((
-        Camera2d::default(),
+        Camera2d,
         RenderTarget::Image(image_handle.clone().into()),
         Camera {
-            clear_color: Color::hsla(0.0, 0.0, 0.0, 0.0).into(),
+            clear_color: Color::NONE.into(),
             ..default()
         },
         Transform::from_translation(Vec3::new(0.0, 0.0, 15.0)).looking_at(Vec3::ZERO, Vec3::Y),
-        ShaderChainCamera {
-            shaders: vec![
-                "shaders/post_processing_2.wgsl".to_string(),
-                "shaders/post_processing.wgsl".to_string(),
-                ],
-            iid: 1,
-        },
-        drawlayer,
-        
+        ShaderChainCamera::default(),
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
     ));
 
     //Sprite to display the rendered texture
-    let sprite = commands.spawn(
-        (
-            ModulePart(spawn.root_id),
-            Sprite::from_image(image_handle.clone()),
-        )
-    ).id();
+    let sprite = commands
+        .spawn((ModulePart(spawn.root_id), Sprite::from_image(image_handle)))
+        .id();
 
     commands.entity(spawn.root_id).add_child(sprite);
 }
 
 fn resize_surface(
     resize: On<ResizeModuleInternal>,
-    mut materials: ResMut<Assets<NoiseMaterial>>,
-    mut surfaces: Query<(&mut Transform, &MeshMaterial2d<NoiseMaterial>), With<Mesh2d>>,
+    mut surfaces: Query<&mut Transform, With<MeshMaterial2d<NoiseMaterial>>>,
     roots: Query<&ModuleWithParts, With<ModuleWin>>,
-    sprites: Query<&Sprite>,
-    mut images: ResMut<Assets<Image>>
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
 ) {
     if let Ok(rootchildren) = roots.get(resize.moduleroot) {
         for child in rootchildren.iter() {
-            if let Ok((mut transform, materialref)) = surfaces.get_mut(child) {
-                let newscale = Vec3::new(resize.width, resize.height, 1.0);
-                transform.scale = newscale;
-                if let Some(shader) = materials.get_mut(materialref.id()) {
-                    shader.width = resize.width;
-                    shader.height = resize.height;
-                }
-            }
-            else if let Ok(sprite) = sprites.get(child) {
-                if let Some(image) = images.get_mut(sprite.image.id()){
-                    image.resize(Extent3d { width: resize.width as u32, height:resize.height as u32, depth_or_array_layers: 1 });
-                }
+            if let Ok(mut transform) = surfaces.get_mut(child) {
+                transform.scale = Vec3::new(resize.width, resize.height, 1.0);
             }
         }
     }
+    pool.resize(
+        &mut images,
+        resize.moduleroot,
+        UVec2::new(resize.width as u32, resize.height as u32),
+    );
+}
+
+/// Writes the module parameters and the playback time into the noise material
+fn update_noise_material(
+    playback: Res<Playback>,
+    mut materials: ResMut<Assets<NoiseMaterial>>,
+    surfaces: Query<(&MeshMaterial2d<NoiseMaterial>, &ModulePart)>,
+    roots: Query<(&LiveParams, &ModuleWin)>,
+) {
+    for (materialref, part) in surfaces.iter() {
+        let Ok((LiveParams(params), mw)) = roots.get(part.0) else {
+            continue;
+        };
+        let Some(material) = materials.get_mut(materialref.id()) else {
+            continue;
+        };
+
+        let stops = sorted_stops(params.gradient("color ramp"));
+        let mut stop_positions = [Vec4::ZERO; MAX_STOPS / 4];
+        let mut stop_colors = [Vec4::ZERO; MAX_STOPS];
+        for (i, stop) in stops.iter().take(MAX_STOPS).enumerate() {
+            stop_positions[i / 4][i % 4] = stop.position;
+            stop_colors[i] = stop.color.to_vec4();
+        }
+
+        material.settings = NoiseSettings {
+            time: playback.time,
+            width: mw.width,
+            height: mw.height,
+            speed: params.float("speed"),
+            offset: params.vec2("offset"),
+            drift: params.vec2("drift"),
+            scale: params.float("scale"),
+            lacunarity: params.float("lacunarity"),
+            gain: params.float("gain"),
+            seed: params.int("seed") as f32,
+            octaves: params.int("octaves").clamp(1, MAX_OCTAVES) as u32,
+            stop_count: stops.len().min(MAX_STOPS) as u32,
+            stop_positions,
+            stop_colors,
+        };
+    }
 }
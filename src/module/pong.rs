@@ -33,7 +33,7 @@ fn spawn_module(
 ) {
     // if spawn.moduleclass != ModuleClass::Pong { return };
     // Spawn the noise module entities here
-    println!("Spawning Pong Module");
+    debug!("spawning pong module");
 
     //first pass circle mesh
     let ball = commands.spawn((
@@ -0,0 +1,617 @@
+use std::mem::discriminant;
+
+use bevy::camera::RenderTarget;
+use bevy::mesh::MeshVertexBufferLayoutRef;
+use bevy::prelude::*;
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, ShaderType, SpecializedMeshPipelineError,
+};
+use bevy::shader::Source;
+use bevy::sprite_render::{AlphaMode2d, Material2d, Material2dKey, Material2dPlugin};
+
+use crate::audio::{AudioAnalysis, AudioSpectrum};
+use crate::module::*;
+use crate::pipeline::RenderTargetPool;
+use crate::params::{LiveParams, ModuleParams, Param, ParamValue};
+use crate::playback::Playback;
+
+/// A module that renders any fragment shader from `assets/shaders`.
+/// The uniforms the shader declares in `@group(2)` become the module's parameters,
+/// binding 0 is reserved for [`ShaderToyInputs`] and bindings 9 and 10 for the audio spectrum
+/// (see `shaders/include/shadertoy.wgsl`).
+pub struct ShaderToyModule;
+
+impl Plugin for ShaderToyModule {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(Material2dPlugin::<ShaderToyMaterial>::default())
+            .add_systems(Startup, load_include)
+            .add_systems(OnEnter(AppState::Startup), setup)
+            .add_systems(
+                Update,
+                (
+                    load_shadertoy_shader,
+                    invalidate_modified_shaders,
+                    parse_shadertoy_uniforms,
+                    update_shadertoy_material,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+/// Keeps the `bumper::shadertoy` import module loaded so user shaders can import it
+#[derive(Resource)]
+struct ShaderToyInclude {
+    _shader: Handle<Shader>,
+}
+
+fn load_include(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(ShaderToyInclude {
+        _shader: asset_server.load("shaders/include/shadertoy.wgsl"),
+    });
+}
+
+fn setup(mut commands: Commands, mut spawnerconfig: ResMut<ModuleSpawnerConfig>) {
+    let eid = commands
+        .spawn((ModuleSpawner {
+            class: ModuleClass::Shader,
+        },))
+        .observe(spawn_shadertoy_module)
+        .observe(resize_shadertoy)
+        .id();
+
+    spawnerconfig
+        .observers
+        .insert(ModuleClass::Shader, vec![eid]);
+}
+
+/// Number of uniform bindings after the inputs that shaders can use for their own parameters
+const UNIFORM_SLOTS: u32 = 8;
+
+#[derive(ShaderType, Debug, Clone, Default)]
+pub struct ShaderToyInputs {
+    pub time: f32,
+    pub frame: u32,
+    pub resolution: Vec2,
+    pub mouse: Vec4,
+    /// Rms, onset and beat of the audio track, and the seconds since the last beat
+    pub audio: Vec4,
+}
+
+#[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
+#[bind_group_data(ShaderToyKey)]
+pub struct ShaderToyMaterial {
+    #[uniform(0)]
+    pub inputs: ShaderToyInputs,
+    #[uniform(1)]
+    pub slot1: Vec4,
+    #[uniform(2)]
+    pub slot2: Vec4,
+    #[uniform(3)]
+    pub slot3: Vec4,
+    #[uniform(4)]
+    pub slot4: Vec4,
+    #[uniform(5)]
+    pub slot5: Vec4,
+    #[uniform(6)]
+    pub slot6: Vec4,
+    #[uniform(7)]
+    pub slot7: Vec4,
+    #[uniform(8)]
+    pub slot8: Vec4,
+    #[texture(9)]
+    #[sampler(10)]
+    pub spectrum: Option<Handle<Image>>,
+    pub shader: Handle<Shader>,
+}
+
+impl ShaderToyMaterial {
+    fn new(shader: Handle<Shader>, spectrum: Handle<Image>) -> Self {
+        ShaderToyMaterial {
+            inputs: default(),
+            slot1: Vec4::ZERO,
+            slot2: Vec4::ZERO,
+            slot3: Vec4::ZERO,
+            slot4: Vec4::ZERO,
+            slot5: Vec4::ZERO,
+            slot6: Vec4::ZERO,
+            slot7: Vec4::ZERO,
+            slot8: Vec4::ZERO,
+            spectrum: Some(spectrum),
+            shader,
+        }
+    }
+
+    fn slot_mut(&mut self, binding: u32) -> Option<&mut Vec4> {
+        match binding {
+            1 => Some(&mut self.slot1),
+            2 => Some(&mut self.slot2),
+            3 => Some(&mut self.slot3),
+            4 => Some(&mut self.slot4),
+            5 => Some(&mut self.slot5),
+            6 => Some(&mut self.slot6),
+            7 => Some(&mut self.slot7),
+            8 => Some(&mut self.slot8),
+            _ => None,
+        }
+    }
+}
+
+/// Pipelines are specialized per fragment shader, since every module instance can pick its own
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ShaderToyKey {
+    shader: Handle<Shader>,
+}
+
+impl From<&ShaderToyMaterial> for ShaderToyKey {
+    fn from(material: &ShaderToyMaterial) -> Self {
+        ShaderToyKey {
+            shader: material.shader.clone(),
+        }
+    }
+}
+
+impl Material2d for ShaderToyMaterial {
+    fn alpha_mode(&self) -> AlphaMode2d {
+        AlphaMode2d::Blend
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayoutRef,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader = key.bind_group_data.shader;
+        }
+        Ok(())
+    }
+}
+
+/// A uniform declaration found in the shader source, mapped onto a parameter
+#[derive(Debug, Clone)]
+struct ShaderUniform {
+    binding: u32,
+    param: Param,
+}
+
+/// The mesh the shader is drawn on
+#[derive(Component, Default)]
+pub struct ShaderToySurface {
+    path: String,
+    shader: Handle<Shader>,
+    uniforms: Vec<ShaderUniform>,
+    parsed: bool,
+}
+
+const SHADER_PARAM: &str = "shader";
+const DEFAULT_SHADER: &str = "shadertoy_example.wgsl";
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_shadertoy_module(
+    spawn: On<SpawnModuleInternalEvent>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ShaderToyMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
+    spectrum: Res<AudioSpectrum>,
+    roots: Query<&ModuleParams>,
+) {
+    debug!("spawning shader module");
+
+    let image_handle = pool.acquire(
+        &mut images,
+        spawn.root_id,
+        UVec2::new(BOXWIDTH as u32, BOXHEIGHT as u32),
+        TextureFormat::bevy_default(),
+    );
+
+    let mut shaders = asset_files("shaders", &["wgsl"]);
+    if !shaders.iter().any(|s| s == DEFAULT_SHADER) {
+        shaders.insert(0, DEFAULT_SHADER.to_string());
+    }
+    // the uniform parameters get appended once the shader is loaded and parsed,
+    // a module restored from a snapshot already has its parameters
+    commands
+        .entity(spawn.root_id)
+        .insert_if_new(ModuleParams(vec![
+            Param::choice(SHADER_PARAM, DEFAULT_SHADER, shaders),
+            Param::text(SHADER_CHAIN_PARAM, ""),
+        ]));
+
+    // a restored module starts out with its own shader, so its uniforms are parsed with the values it had
+    let path = roots
+        .get(spawn.root_id)
+        .ok()
+        .map(|params| params.text(SHADER_PARAM))
+        .filter(|path| !path.is_empty())
+        .unwrap_or(DEFAULT_SHADER)
+        .to_string();
+    let shader: Handle<Shader> = asset_server.load(format!("shaders/{path}"));
+    commands.spawn((
+        Mesh2d(meshes.add(Rectangle::new(1., 1.))),
+        MeshMaterial2d(materials.add(ShaderToyMaterial::new(shader.clone(), spectrum.0.clone()))),
+        Transform::default().with_scale(Vec3::new(BOXWIDTH, BOXHEIGHT, 1.0)),
+        ShaderToySurface {
+            path,
+            shader,
+            ..default()
+        },
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
+    ));
+
+    commands.spawn((
+        Camera2d,
+        RenderTarget::Image(image_handle.clone().into()),
+        Camera {
+            clear_color: Color::NONE.into(),
+            ..default()
+        },
+        ShaderChainCamera::default(),
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
+    ));
+
+    //Sprite to display the rendered texture
+    let sprite = commands
+        .spawn((ModulePart(spawn.root_id), Sprite::from_image(image_handle)))
+        .id();
+
+    commands.entity(spawn.root_id).add_child(sprite);
+}
+
+fn resize_shadertoy(
+    resize: On<ResizeModuleInternal>,
+    roots: Query<&ModuleWithParts, With<ModuleWin>>,
+    mut surfaces: Query<&mut Transform, With<ShaderToySurface>>,
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
+) {
+    if let Ok(rootchildren) = roots.get(resize.moduleroot) {
+        for child in rootchildren.iter() {
+            if let Ok(mut transform) = surfaces.get_mut(child) {
+                transform.scale = Vec3::new(resize.width, resize.height, 1.0);
+            }
+        }
+    }
+    pool.resize(
+        &mut images,
+        resize.moduleroot,
+        UVec2::new(resize.width as u32, resize.height as u32),
+    );
+}
+
+/// Loads the shader file picked in the shader parameter
+fn load_shadertoy_shader(
+    asset_server: Res<AssetServer>,
+    roots: Query<&LiveParams, Changed<LiveParams>>,
+    mut surfaces: Query<(
+        &mut ShaderToySurface,
+        &MeshMaterial2d<ShaderToyMaterial>,
+        &ModulePart,
+    )>,
+    mut materials: ResMut<Assets<ShaderToyMaterial>>,
+) {
+    for (mut surface, material, part) in surfaces.iter_mut() {
+        let Ok(LiveParams(params)) = roots.get(part.0) else {
+            continue;
+        };
+        let path = params.text(SHADER_PARAM);
+        if path == surface.path {
+            continue;
+        }
+
+        surface.path = path.to_string();
+        surface.shader = asset_server.load(format!("shaders/{path}"));
+        surface.parsed = false;
+        if let Some(material) = materials.get_mut(material.id()) {
+            material.shader = surface.shader.clone();
+        }
+    }
+}
+
+/// Shaders edited on disk are reloaded by the file watcher, their uniforms need to be parsed again
+fn invalidate_modified_shaders(
+    mut events: MessageReader<AssetEvent<Shader>>,
+    mut surfaces: Query<&mut ShaderToySurface>,
+) {
+    for event in events.read() {
+        if let AssetEvent::Modified { id } = event {
+            for mut surface in surfaces.iter_mut() {
+                if surface.shader.id() == *id {
+                    surface.parsed = false;
+                }
+            }
+        }
+    }
+}
+
+/// Turns the shader's uniform declarations into module parameters,
+/// keeping the values of parameters that still exist with the same type
+fn parse_shadertoy_uniforms(
+    shaders: Res<Assets<Shader>>,
+    mut surfaces: Query<(&mut ShaderToySurface, &ModulePart)>,
+    mut roots: Query<&mut ModuleParams>,
+) {
+    for (mut surface, part) in surfaces.iter_mut() {
+        if surface.parsed {
+            continue;
+        }
+        let Some(shader) = shaders.get(&surface.shader) else {
+            continue;
+        };
+        let Ok(mut params) = roots.get_mut(part.0) else {
+            continue;
+        };
+
+        surface.parsed = true;
+        surface.uniforms = match &shader.source {
+            Source::Wgsl(source) => parse_uniforms(source),
+            _ => vec![],
+        };
+
+        let old = std::mem::take(&mut params.0);
+        let (mut fixed, old_uniforms): (Vec<Param>, Vec<Param>) = old
+            .into_iter()
+            .partition(|p| p.name == SHADER_PARAM || p.name == SHADER_CHAIN_PARAM);
+
+        for uniform in surface.uniforms.iter() {
+            let mut param = uniform.param.clone();
+            if let Some(previous) = old_uniforms.iter().find(|p| {
+                p.name == param.name && discriminant(&p.value) == discriminant(&param.value)
+            }) {
+                param.value = previous.value.clone();
+            }
+            fixed.push(param);
+        }
+        params.0 = fixed;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_shadertoy_material(
+    playback: Res<Playback>,
+    audio: Res<AudioAnalysis>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    window: Single<&Window>,
+    camera: Single<(&Camera, &GlobalTransform), With<Immortal>>,
+    roots: Query<(&LiveParams, &ModuleWin, &GlobalTransform, &ModuleAnchor)>,
+    surfaces: Query<(
+        &ShaderToySurface,
+        &MeshMaterial2d<ShaderToyMaterial>,
+        &ModulePart,
+    )>,
+    mut materials: ResMut<Assets<ShaderToyMaterial>>,
+) {
+    let (camera, camera_transform) = *camera;
+    let cursor = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok());
+
+    let audio = audio.levels(playback.frame());
+    for (surface, material, part) in surfaces.iter() {
+        let Ok((LiveParams(params), mw, root_transform, anchor)) = roots.get(part.0) else {
+            continue;
+        };
+        let Some(material) = materials.get_mut(material.id()) else {
+            continue;
+        };
+
+        let resolution = Vec2::new(mw.width, mw.height);
+        // cursor in pixels from the bottom left corner of the module
+        let mouse_position = cursor
+            .map(|cursor| {
+                let local = root_transform
+                    .affine()
+                    .inverse()
+                    .transform_point3(cursor.extend(0.0))
+                    .truncate();
+                local - anchor.local_rect(resolution).min
+            })
+            .unwrap_or(Vec2::ZERO);
+
+        material.inputs = ShaderToyInputs {
+            time: playback.time,
+            frame: playback.frame() as u32,
+            resolution,
+            mouse: mouse_position
+                .extend(if mouse.pressed(MouseButton::Left) {
+                    1.0
+                } else {
+                    0.0
+                })
+                .extend(0.0),
+            audio,
+        };
+
+        for uniform in surface.uniforms.iter() {
+            let value = match params.get(&uniform.param.name) {
+                Some(ParamValue::Float(v)) => Vec4::new(*v, 0.0, 0.0, 0.0),
+                Some(ParamValue::Vec2(v)) => v.extend(0.0).extend(0.0),
+                Some(ParamValue::Color(v)) => v.to_vec4(),
+                _ => continue,
+            };
+            if let Some(slot) = material.slot_mut(uniform.binding) {
+                *slot = value;
+            }
+        }
+    }
+}
+
+/// Finds `@group(2) @binding(n) var<uniform> name: type;` declarations in WGSL source.
+/// A trailing comment can hold `range(min, max)` and `default(...)` annotations.
+fn parse_uniforms(source: &str) -> Vec<ShaderUniform> {
+    let mut uniforms = vec![];
+
+    for line in source.lines() {
+        let (code, comment) = line.split_once("//").unwrap_or((line, ""));
+        let code = code.trim();
+        if !code.starts_with("@group(2)") {
+            continue;
+        }
+        let Some(binding) = annotation(code, "@binding").and_then(|b| b.first().copied()) else {
+            continue;
+        };
+        let binding = binding as u32;
+        if binding == 0 || binding > UNIFORM_SLOTS {
+            continue;
+        }
+        let Some((_, declaration)) = code.split_once("var<uniform>") else {
+            continue;
+        };
+        let Some((name, ty)) = declaration.trim_end_matches(';').split_once(':') else {
+            continue;
+        };
+        let name = name.trim();
+        let ty = ty.trim();
+
+        let range = annotation(comment, "range")
+            .filter(|r| r.len() == 2)
+            .map(|r| r[0]..=r[1])
+            .unwrap_or(0.0..=1.0);
+        let default = annotation(comment, "default").unwrap_or_default();
+        let component = |i: usize, fallback: f32| default.get(i).copied().unwrap_or(fallback);
+
+        let param = match ty {
+            "f32" => Param::float(name, component(0, *range.start()), range),
+            "vec2<f32>" | "vec2f" => {
+                Param::vec2(name, Vec2::new(component(0, 0.0), component(1, 0.0)), range)
+            }
+            "vec3<f32>" | "vec3f" | "vec4<f32>" | "vec4f" => Param::color(
+                name,
+                LinearRgba::new(
+                    component(0, 1.0),
+                    component(1, 1.0),
+                    component(2, 1.0),
+                    component(3, 1.0),
+                ),
+            ),
+            _ => {
+                warn!("unsupported uniform type {ty} for {name}");
+                continue;
+            }
+        };
+
+        uniforms.push(ShaderUniform { binding, param });
+    }
+
+    uniforms
+}
+
+/// Parses the numbers in `name(a, b, ...)` out of a piece of text
+fn annotation(text: &str, name: &str) -> Option<Vec<f32>> {
+    let start = text.find(&format!("{name}("))? + name.len() + 1;
+    let end = start + text[start..].find(')')?;
+    Some(
+        text[start..end]
+            .split(',')
+            .filter_map(|n| n.trim().parse().ok())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_supported_type() {
+        let uniforms = parse_uniforms(
+            "@group(2) @binding(1) var<uniform> speed: f32;
+            @group(2) @binding(2) var<uniform> offset: vec2<f32>;
+            @group(2) @binding(3) var<uniform> shift: vec2f;
+            @group(2) @binding(4) var<uniform> tint: vec3<f32>;
+            @group(2) @binding(5) var<uniform> glow: vec3f;
+            @group(2) @binding(6) var<uniform> fill: vec4<f32>;
+            @group(2) @binding(7) var<uniform> edge: vec4f;",
+        );
+        let found: Vec<(u32, &str)> = uniforms
+            .iter()
+            .map(|u| (u.binding, u.param.name.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (1, "speed"),
+                (2, "offset"),
+                (3, "shift"),
+                (4, "tint"),
+                (5, "glow"),
+                (6, "fill"),
+                (7, "edge")
+            ]
+        );
+        assert_eq!(uniforms[0].param.value, ParamValue::Float(0.0));
+        assert_eq!(uniforms[1].param.value, ParamValue::Vec2(Vec2::ZERO));
+        assert_eq!(uniforms[2].param.value, ParamValue::Vec2(Vec2::ZERO));
+        for uniform in &uniforms[3..] {
+            assert_eq!(uniform.param.value, ParamValue::Color(LinearRgba::WHITE));
+        }
+    }
+
+    #[test]
+    fn reads_ranges_and_defaults() {
+        let uniforms = parse_uniforms(
+            "@group(2) @binding(1) var<uniform> speed: f32; // range(-2.0, 4) default(1.5)
+            @group(2) @binding(2) var<uniform> low: f32; // range(2.0, 3.0)
+            @group(2) @binding(3) var<uniform> offset: vec2<f32>; // default(0.25, -1.0)
+            @group(2) @binding(4) var<uniform> tint: vec4<f32>; // default(0.2, 0.8, 1.0, 0.5)
+            @group(2) @binding(5) var<uniform> partial: vec3<f32>; // default(0.5)",
+        );
+        assert_eq!(uniforms[0].param.range, -2.0..=4.0);
+        assert_eq!(uniforms[0].param.value, ParamValue::Float(1.5));
+        // without a default a float starts at the bottom of its range
+        assert_eq!(uniforms[1].param.value, ParamValue::Float(2.0));
+        assert_eq!(
+            uniforms[2].param.value,
+            ParamValue::Vec2(Vec2::new(0.25, -1.0))
+        );
+        assert_eq!(
+            uniforms[3].param.value,
+            ParamValue::Color(LinearRgba::new(0.2, 0.8, 1.0, 0.5))
+        );
+        assert_eq!(
+            uniforms[4].param.value,
+            ParamValue::Color(LinearRgba::new(0.5, 1.0, 1.0, 1.0))
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_annotations() {
+        let uniforms = parse_uniforms(
+            "@group(2) @binding(1) var<uniform> a: f32; // range(1.0) default(x)
+            @group(2) @binding(2) var<uniform> b: f32; // range(0.0, 1.0",
+        );
+        assert_eq!(uniforms[0].param.range, 0.0..=1.0);
+        assert_eq!(uniforms[0].param.value, ParamValue::Float(0.0));
+        assert_eq!(uniforms[1].param.range, 0.0..=1.0);
+    }
+
+    #[test]
+    fn keeps_to_the_uniform_slots() {
+        let source: String = (0..=UNIFORM_SLOTS + 2)
+            .map(|binding| format!("@group(2) @binding({binding}) var<uniform> u{binding}: f32;\n"))
+            .collect();
+        let bindings: Vec<u32> = parse_uniforms(&source).iter().map(|u| u.binding).collect();
+        // binding 0 holds the inputs, the ones after the slots the spectrum
+        assert_eq!(bindings, (1..=UNIFORM_SLOTS).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn skips_other_declarations() {
+        let uniforms = parse_uniforms(
+            "@group(2) @binding(0) var<uniform> inputs: ShaderToyInputs;
+            @group(2) @binding(9) var spectrum: texture_2d<f32>;
+            @group(1) @binding(1) var<uniform> view: f32;
+            // @group(2) @binding(1) var<uniform> commented: f32;
+            @group(2) @binding(2) var<uniform> matrix: mat4x4<f32>;
+            @group(2) @binding(3) var<uniform> kept: f32;",
+        );
+        assert_eq!(uniforms.len(), 1);
+        assert_eq!(uniforms[0].param.name, "kept");
+    }
+}
@@ -0,0 +1,293 @@
+use bevy::camera::RenderTarget;
+use bevy::math::curve::{Curve, EaseFunction};
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use bevy::text::{LineHeight, TextBounds, TextLayoutInfo};
+
+use crate::module::*;
+use crate::pipeline::RenderTargetPool;
+use crate::params::{LiveParams, ModuleParams, Param};
+use crate::playback::Playback;
+use crate::rendering::ShaderChainCamera;
+
+pub struct TextModule;
+
+impl Plugin for TextModule {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Startup), setup)
+            .add_systems(
+                Update,
+                (apply_text_params, animate_glyphs)
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, mut spawnerconfig: ResMut<ModuleSpawnerConfig>) {
+    let eid = commands
+        .spawn((ModuleSpawner {
+            class: ModuleClass::Text,
+        },))
+        .observe(spawn_text_module)
+        .observe(resize_text)
+        .id();
+
+    spawnerconfig.observers.insert(ModuleClass::Text, vec![eid]);
+}
+
+/// The invisible Text2d that bevy lays out for us.
+/// Its glyph positions are copied onto one sprite per glyph so every character can be animated on its own.
+#[derive(Component, Default)]
+pub struct TextLayoutSource {
+    glyphs: Vec<Entity>,
+}
+
+#[derive(Component)]
+pub struct TextGlyph;
+
+const ALIGNMENTS: [&str; 3] = ["left", "center", "right"];
+const EASINGS: [&str; 5] = ["linear", "quad out", "cubic out", "back out", "elastic out"];
+
+/// Lists the fonts in `assets/fonts`, "default" is bevy's built-in font
+fn available_fonts() -> Vec<String> {
+    let mut fonts = vec!["default".to_string()];
+    fonts.extend(asset_files("fonts", &["ttf", "otf"]));
+    fonts
+}
+
+fn default_params() -> ModuleParams {
+    ModuleParams(vec![
+        Param::text("text", "Bumper"),
+        Param::choice("font", "default", available_fonts()),
+        Param::float("size", 64.0, 4.0..=256.0),
+        Param::color("color", LinearRgba::WHITE),
+        Param::choice(
+            "alignment",
+            "center",
+            ALIGNMENTS.iter().map(|a| a.to_string()).collect(),
+        ),
+        Param::float("line spacing", 1.2, 0.5..=3.0),
+        // per glyph animation, every glyph animates from these values to its resting state
+        Param::vec2("from offset", Vec2::new(0.0, -40.0), -500.0..=500.0),
+        Param::float("from scale", 0.0, 0.0..=4.0),
+        Param::float("from opacity", 0.0, 0.0..=1.0),
+        Param::float("duration", 0.6, 0.01..=5.0),
+        Param::float("stagger", 0.05, 0.0..=1.0),
+        Param::choice(
+            "easing",
+            "back out",
+            EASINGS.iter().map(|e| e.to_string()).collect(),
+        ),
+        Param::bool("loop", true),
+        Param::float("hold", 1.5, 0.0..=10.0),
+        Param::text(SHADER_CHAIN_PARAM, ""),
+    ])
+}
+
+fn spawn_text_module(
+    spawn: On<SpawnModuleInternalEvent>,
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
+) {
+    debug!("spawning text module");
+
+    let image_handle = pool.acquire(
+        &mut images,
+        spawn.root_id,
+        UVec2::new(BOXWIDTH as u32, BOXHEIGHT as u32),
+        TextureFormat::bevy_default(),
+    );
+
+    // a module restored from a snapshot already has its parameters
+    commands
+        .entity(spawn.root_id)
+        .insert_if_new(default_params());
+
+    // parameters are applied by apply_text_params on the first frame
+    commands.spawn((
+        Text2d::default(),
+        TextColor(Color::NONE),
+        TextBounds::new_horizontal(BOXWIDTH),
+        TextLayoutSource::default(),
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
+    ));
+
+    commands.spawn((
+        Camera2d,
+        RenderTarget::Image(image_handle.clone().into()),
+        Camera {
+            clear_color: Color::NONE.into(),
+            ..default()
+        },
+        ShaderChainCamera::default(),
+        ModulePart(spawn.root_id),
+        spawn.layer.clone(),
+    ));
+
+    //Sprite to display the rendered texture
+    let sprite = commands
+        .spawn((ModulePart(spawn.root_id), Sprite::from_image(image_handle)))
+        .id();
+
+    commands.entity(spawn.root_id).add_child(sprite);
+}
+
+fn resize_text(
+    resize: On<ResizeModuleInternal>,
+    roots: Query<&ModuleWithParts, With<ModuleWin>>,
+    mut sources: Query<&mut TextBounds, With<TextLayoutSource>>,
+    mut images: ResMut<Assets<Image>>,
+    mut pool: ResMut<RenderTargetPool>,
+) {
+    if let Ok(rootchildren) = roots.get(resize.moduleroot) {
+        for child in rootchildren.iter() {
+            if let Ok(mut bounds) = sources.get_mut(child) {
+                bounds.width = Some(resize.width);
+            }
+        }
+    }
+    pool.resize(
+        &mut images,
+        resize.moduleroot,
+        UVec2::new(resize.width as u32, resize.height as u32),
+    );
+}
+
+/// Copies the text, font and layout parameters onto the layout source
+fn apply_text_params(
+    asset_server: Res<AssetServer>,
+    roots: Query<(&LiveParams, &ModuleWithParts), Changed<LiveParams>>,
+    mut sources: Query<
+        (&mut Text2d, &mut TextFont, &mut TextLayout, &mut LineHeight),
+        With<TextLayoutSource>,
+    >,
+) {
+    for (LiveParams(params), parts) in roots.iter() {
+        for part in parts.iter() {
+            let Ok((mut text, mut font, mut layout, mut line_height)) = sources.get_mut(part)
+            else {
+                continue;
+            };
+
+            text.0 = params.text("text").to_string();
+            font.font_size = params.float("size");
+            font.font = match params.text("font") {
+                "default" => default(),
+                name => asset_server.load(format!("fonts/{name}")),
+            };
+            layout.justify = match params.text("alignment") {
+                "left" => Justify::Left,
+                "right" => Justify::Right,
+                _ => Justify::Center,
+            };
+            *line_height = LineHeight::RelativeToFont(params.float("line spacing"));
+        }
+    }
+}
+
+fn easing(name: &str) -> EaseFunction {
+    match name {
+        "quad out" => EaseFunction::QuadraticOut,
+        "cubic out" => EaseFunction::CubicOut,
+        "back out" => EaseFunction::BackOut,
+        "elastic out" => EaseFunction::ElasticOut,
+        _ => EaseFunction::Linear,
+    }
+}
+
+/// Keeps one sprite per laid out glyph and applies the staggered per glyph animation
+fn animate_glyphs(
+    mut commands: Commands,
+    playback: Res<Playback>,
+    mut images: ResMut<Assets<Image>>,
+    atlases: Res<Assets<TextureAtlasLayout>>,
+    mut sources: Query<(
+        &mut TextLayoutSource,
+        &TextLayoutInfo,
+        &TextBounds,
+        &Anchor,
+        &ModulePart,
+        &RenderLayers,
+    )>,
+    roots: Query<&LiveParams>,
+    mut glyphs: Query<(&mut Sprite, &mut Transform), With<TextGlyph>>,
+) {
+    for (mut source, layout, bounds, anchor, part, layer) in sources.iter_mut() {
+        let Ok(LiveParams(params)) = roots.get(part.0) else {
+            continue;
+        };
+
+        // spawn or despawn glyph sprites so there is exactly one per glyph
+        while source.glyphs.len() < layout.glyphs.len() {
+            let glyph = commands
+                .spawn((
+                    TextGlyph,
+                    Sprite::default(),
+                    Transform::default(),
+                    ModulePart(part.0),
+                    layer.clone(),
+                ))
+                .id();
+            source.glyphs.push(glyph);
+        }
+        for glyph in source.glyphs.drain(layout.glyphs.len()..) {
+            commands.entity(glyph).despawn();
+        }
+
+        let scale_factor = layout.scale_factor.max(f32::EPSILON);
+        let size = Vec2::new(
+            bounds.width.unwrap_or(layout.size.x / scale_factor),
+            bounds.height.unwrap_or(layout.size.y / scale_factor),
+        );
+        let top_left = (Anchor::TOP_LEFT.as_vec() - anchor.as_vec()) * size;
+
+        let color = params.color("color");
+        let from_offset = params.vec2("from offset");
+        let from_scale = params.float("from scale");
+        let from_opacity = params.float("from opacity");
+        let duration = params.float("duration").max(0.01);
+        let stagger = params.float("stagger");
+        let ease = easing(params.text("easing"));
+
+        // with looping enabled the whole text animates in, holds, and starts over
+        let total = duration + stagger * layout.glyphs.len() as f32 + params.float("hold");
+        let t = if params.bool("loop") {
+            playback.time % total
+        } else {
+            playback.time
+        };
+
+        for (i, positioned) in layout.glyphs.iter().enumerate() {
+            let Ok((mut sprite, mut transform)) = glyphs.get_mut(source.glyphs[i]) else {
+                // spawned this frame, picked up on the next one
+                continue;
+            };
+            let Some(atlas) = atlases.get(positioned.atlas_info.texture_atlas) else {
+                continue;
+            };
+            let rect = atlas.textures[positioned.atlas_info.location.glyph_index].as_rect();
+
+            if sprite.image.id() != positioned.atlas_info.texture
+                && let Some(handle) = images.get_strong_handle(positioned.atlas_info.texture)
+            {
+                sprite.image = handle;
+            }
+
+            let progress = ease.sample_clamped((t - i as f32 * stagger) / duration);
+
+            sprite.rect = Some(rect);
+            sprite.custom_size = Some(rect.size() / scale_factor);
+            sprite.color = Color::LinearRgba(
+                color.with_alpha(color.alpha * from_opacity.lerp(1.0, progress).clamp(0.0, 1.0)),
+            );
+
+            let rest =
+                top_left + Vec2::new(positioned.position.x, -positioned.position.y) / scale_factor;
+            transform.translation = (rest + from_offset * (1.0 - progress)).extend(0.0);
+            transform.scale = Vec3::splat(from_scale.lerp(1.0, progress));
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+use bevy::asset::io::file::FileAssetReader;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{AppState, ModuleWin};
+use crate::keymap::{Action, PressedActions};
+use crate::module::{DespawnModule, ModuleClass, ModuleId, SpawnModuleEvent};
+use crate::params::{ExternalValue, ModuleParams, Param, ParamValue, SetParam};
+use crate::playback::Playback;
+
+pub mod packet;
+
+use packet::OscArg;
+
+/// The config file with the OSC address and port, next to the assets directory
+pub const OSC_CONFIG_PATH: &str = "osc.ron";
+/// Received messages kept for the OSC window
+const RECENT_MESSAGES: usize = 50;
+
+/// An OSC address learned for a parameter, its values are scaled from 0..1 to the range of the parameter
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OscMapping {
+    pub address: String,
+    pub module: ModuleId,
+    pub param: String,
+}
+
+/// The learned addresses, saved with the project
+#[derive(Resource, Clone, Debug, Default, PartialEq)]
+pub struct OscMappings(pub Vec<OscMapping>);
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct OscConfig {
+    pub enabled: bool,
+    /// The interface the server listens on. The default only takes messages from this machine,
+    /// `0.0.0.0` lets anyone on the network control the composition
+    #[serde(default = "local_address")]
+    pub address: IpAddr,
+    /// The local UDP port the server listens on
+    pub port: u16,
+}
+
+fn local_address() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        OscConfig {
+            enabled: true,
+            address: local_address(),
+            port: 9000,
+        }
+    }
+}
+
+impl OscConfig {
+    fn load() -> Self {
+        let path = FileAssetReader::get_base_path().join(OSC_CONFIG_PATH);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => ron::from_str(&text).unwrap_or_else(|err| {
+                warn!("ignoring {}: {err}", path.display());
+                OscConfig::default()
+            }),
+            Err(_) => OscConfig::default(),
+        }
+    }
+
+    #[cfg(feature = "editor")]
+    pub fn save(&self) {
+        let path = FileAssetReader::get_base_path().join(OSC_CONFIG_PATH);
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(&path, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            error!("couldn't save {}: {err}", path.display());
+        }
+    }
+}
+
+/// The module and parameter the next incoming message is mapped to
+#[derive(Resource, Default)]
+pub struct OscLearn(pub Option<(ModuleId, String)>);
+
+#[derive(Resource, Default)]
+pub struct OscServer {
+    socket: Option<UdpSocket>,
+    /// The address and port the socket is bound to
+    pub address: Option<SocketAddr>,
+    pub error: Option<String>,
+    /// The last received messages, newest last
+    pub recent: VecDeque<String>,
+}
+
+/// Controls the composition from OSC controllers over UDP.
+///
+/// - `/module/<name>/<param> <value>` sets a parameter, names are matched ignoring case with `_` for spaces
+/// - `/spawn/<class>` spawns a module, `/delete/<name>` deletes one
+/// - `/transport/play`, `/transport/pause` and `/transport/seek <seconds>` control playback
+/// - `/action/<action>` is handled like the shortcut of the action was pressed
+///
+/// Learned addresses map to the parameter they were learned for.
+pub struct OscPlugin;
+
+impl Plugin for OscPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(OscConfig::load())
+            .init_resource::<OscMappings>()
+            .init_resource::<OscLearn>()
+            .init_resource::<OscServer>()
+            .add_systems(
+                Update,
+                (bind_server, receive_osc.run_if(in_state(AppState::Running))).chain(),
+            );
+    }
+}
+
+/// (Re)binds the socket when the server is enabled or its address changed
+fn bind_server(config: Res<OscConfig>, mut server: ResMut<OscServer>) {
+    let wanted = config
+        .enabled
+        .then_some(SocketAddr::new(config.address, config.port));
+    if server.address == wanted {
+        return;
+    }
+
+    server.socket = None;
+    server.address = wanted;
+    server.error = None;
+    let Some(address) = wanted else {
+        info!("OSC server stopped");
+        return;
+    };
+
+    match UdpSocket::bind(address).and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }) {
+        Ok(socket) => {
+            info!("OSC server listening on {address}");
+            server.socket = Some(socket);
+        }
+        Err(err) => {
+            error!("OSC server couldn't listen on {address}: {err}");
+            server.error = Some(err.to_string());
+        }
+    }
+}
+
+fn receive_osc(
+    mut commands: Commands,
+    mut server: ResMut<OscServer>,
+    mut mappings: ResMut<OscMappings>,
+    mut learn: ResMut<OscLearn>,
+    mut playback: ResMut<Playback>,
+    mut pressed: ResMut<PressedActions>,
+    modules: Query<(Entity, &ModuleId, &Name, &ModuleParams), With<ModuleWin>>,
+) {
+    let Some(socket) = server.socket.as_ref() else {
+        return;
+    };
+
+    let mut messages = vec![];
+    let mut buffer = [0; 65536];
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((len, from)) => match packet::decode(&buffer[..len]) {
+                Ok(decoded) => messages.extend(decoded),
+                Err(err) => warn!("bad OSC packet from {from}: {err}"),
+            },
+            Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+            Err(err) => {
+                warn!("OSC server: {err}");
+                break;
+            }
+        }
+    }
+
+    for message in messages {
+        server.recent.push_back(message.to_string());
+        if server.recent.len() > RECENT_MESSAGES {
+            server.recent.pop_front();
+        }
+
+        if !message.args.is_empty()
+            && let Some((module, param)) = learn.0.take()
+        {
+            info!(
+                "learned {} for {param} of module {}",
+                message.address, module.0
+            );
+            mappings
+                .0
+                .retain(|mapping| mapping.address != message.address);
+            mappings.0.push(OscMapping {
+                address: message.address.clone(),
+                module,
+                param,
+            });
+            continue;
+        }
+
+        let mut mapped = false;
+        for mapping in mappings.0.iter().filter(|m| m.address == message.address) {
+            mapped = true;
+            let target = modules
+                .iter()
+                .find(|(_, id, _, _)| **id == mapping.module)
+                .and_then(|(_, _, name, params)| {
+                    let param = params.0.iter().find(|p| p.name == mapping.param)?;
+                    Some((name, param))
+                });
+            if let Some((name, param)) = target
+                && let Some(value) = mapped_value(param, &message.args)
+            {
+                commands.trigger(SetParam {
+                    module: name.to_string(),
+                    param: param.name.clone(),
+                    value,
+                });
+            }
+        }
+        if mapped {
+            continue;
+        }
+
+        let segments: Vec<&str> = message.address.trim_start_matches('/').split('/').collect();
+        match segments.as_slice() {
+            ["module", module, param] => {
+                let target = modules
+                    .iter()
+                    .find(|(_, _, name, _)| matches(name, module))
+                    .and_then(|(_, _, name, params)| {
+                        let param = params.0.iter().find(|p| matches(&p.name, param))?;
+                        Some((name.to_string(), param.name.clone()))
+                    });
+                match (target, external_value(&message.args)) {
+                    (Some((module, param)), Some(value)) => {
+                        commands.trigger(SetParam {
+                            module,
+                            param,
+                            value,
+                        });
+                    }
+                    (None, _) => warn!("OSC: no parameter {param} on a module named {module}"),
+                    (_, None) => warn!("OSC: no value for {}", message.address),
+                }
+            }
+            ["spawn", class] => {
+                match ModuleClass::ALL
+                    .into_iter()
+                    .find(|c| matches(&format!("{c:?}"), class))
+                {
+                    Some(moduleclass) => commands.trigger(SpawnModuleEvent {
+                        moduleclass,
+                        snapshot: None,
+                    }),
+                    None => warn!("OSC: no module class {class}"),
+                }
+            }
+            ["delete", module] => {
+                match modules.iter().find(|(_, _, name, _)| matches(name, module)) {
+                    Some((entity, ..)) => commands.trigger(DespawnModule { entity }),
+                    None => warn!("OSC: no module named {module}"),
+                }
+            }
+            ["transport", "play"] => playback.playing = true,
+            ["transport", "pause"] => playback.playing = false,
+            ["transport", "seek"] => {
+                if let Some(time) = message.args.first().and_then(OscArg::number) {
+                    playback.time = time.max(0.0) as f32;
+                }
+            }
+            ["action", action] => {
+                match Action::ALL
+                    .into_iter()
+                    .find(|a| matches(&format!("{a:?}"), action))
+                {
+                    Some(action) => pressed.press(action),
+                    None => warn!("OSC: no action {action}"),
+                }
+            }
+            _ => debug!("unhandled OSC message {message}"),
+        }
+    }
+}
+
+/// Compares a name with an address segment, ignoring case and with `_` standing in for spaces
+fn matches(name: &str, segment: &str) -> bool {
+    name.to_lowercase().replace(' ', "_") == segment.to_lowercase()
+}
+
+/// The arguments of a message as they are
+fn external_value(args: &[OscArg]) -> Option<ExternalValue> {
+    match args {
+        [OscArg::Bool(v)] => Some(ExternalValue::Bool(*v)),
+        [OscArg::Text(v)] => Some(ExternalValue::Text(v.clone())),
+        [arg] => arg.number().map(ExternalValue::Number),
+        [] => None,
+        args => args
+            .iter()
+            .map(OscArg::number)
+            .collect::<Option<_>>()
+            .map(ExternalValue::List),
+    }
+}
+
+/// The arguments of a learned message, numbers from 0 to 1 are scaled to the parameter like a fader would
+fn mapped_value(param: &Param, args: &[OscArg]) -> Option<ExternalValue> {
+    let start = *param.range.start() as f64;
+    let end = *param.range.end() as f64;
+    let scale = |n: f64| start + n.clamp(0.0, 1.0) * (end - start);
+    let first = args.first().and_then(OscArg::number);
+    match (&param.value, first) {
+        (ParamValue::Float(_) | ParamValue::Int(_), Some(n)) => {
+            Some(ExternalValue::Number(scale(n)))
+        }
+        (ParamValue::Bool(_), Some(n)) => Some(ExternalValue::Bool(n > 0.5)),
+        (ParamValue::Choice(_), Some(n)) if !param.options.is_empty() => {
+            let last = param.options.len() - 1;
+            let index = (n.clamp(0.0, 1.0) * last as f64).round() as usize;
+            Some(ExternalValue::Text(param.options[index].clone()))
+        }
+        (ParamValue::Vec2(_), _) => match external_value(args)? {
+            ExternalValue::List(list) => {
+                Some(ExternalValue::List(list.into_iter().map(scale).collect()))
+            }
+            other => Some(other),
+        },
+        _ => external_value(args),
+    }
+}
@@ -0,0 +1,328 @@
+//! Just enough of OSC 1.0 to read what controllers send: messages, bundles and the common argument types
+
+/// Bundles nested deeper than this are rejected instead of recursing on
+const MAX_BUNDLE_DEPTH: usize = 8;
+
+/// An argument of an OSC message
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Text(String),
+    Bool(bool),
+    Nil,
+}
+
+impl OscArg {
+    pub fn number(&self) -> Option<f64> {
+        match self {
+            OscArg::Int(v) => Some(*v as f64),
+            OscArg::Long(v) => Some(*v as f64),
+            OscArg::Float(v) => Some(*v as f64),
+            OscArg::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl std::fmt::Display for OscMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address)?;
+        for arg in self.args.iter() {
+            match arg {
+                OscArg::Int(v) => write!(f, " {v}")?,
+                OscArg::Long(v) => write!(f, " {v}")?,
+                OscArg::Float(v) => write!(f, " {v}")?,
+                OscArg::Double(v) => write!(f, " {v}")?,
+                OscArg::Text(v) => write!(f, " \"{v}\"")?,
+                OscArg::Bool(v) => write!(f, " {v}")?,
+                OscArg::Nil => write!(f, " nil")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the messages of a packet, the messages of a bundle are returned in order and its time tag is ignored
+pub fn decode(packet: &[u8]) -> Result<Vec<OscMessage>, String> {
+    let mut messages = vec![];
+    decode_into(packet, &mut messages, 0)?;
+    Ok(messages)
+}
+
+fn decode_into(packet: &[u8], messages: &mut Vec<OscMessage>, depth: usize) -> Result<(), String> {
+    let mut reader = Reader {
+        bytes: packet,
+        pos: 0,
+    };
+    let address = reader.string()?;
+
+    if address == "#bundle" {
+        if depth >= MAX_BUNDLE_DEPTH {
+            return Err(format!(
+                "OSC bundles nested more than {MAX_BUNDLE_DEPTH} deep"
+            ));
+        }
+        // the time tag
+        reader.take(8)?;
+        while reader.pos < packet.len() {
+            let size = reader.int()?;
+            let size = usize::try_from(size)
+                .map_err(|_| format!("negative OSC bundle element size {size}"))?;
+            decode_into(reader.take(size)?, messages, depth + 1)?;
+        }
+        return Ok(());
+    }
+    if !address.starts_with('/') {
+        return Err(format!("not an OSC address: {address}"));
+    }
+
+    // a message without a type tag string has no arguments
+    let tags = if reader.pos < packet.len() {
+        reader.string()?
+    } else {
+        String::new()
+    };
+    let mut args = vec![];
+    for tag in tags.chars().skip_while(|c| *c == ',') {
+        args.push(match tag {
+            'i' => OscArg::Int(reader.int()?),
+            'h' => OscArg::Long(i64::from_be_bytes(reader.array()?)),
+            'f' => OscArg::Float(f32::from_be_bytes(reader.array()?)),
+            'd' => OscArg::Double(f64::from_be_bytes(reader.array()?)),
+            's' | 'S' => OscArg::Text(reader.string()?),
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'N' | 'I' => OscArg::Nil,
+            other => return Err(format!("unsupported OSC type tag {other}")),
+        });
+    }
+    messages.push(OscMessage { address, args });
+    Ok(())
+}
+
+/// Writes a message, used to test the server from the editor
+pub fn encode(message: &OscMessage) -> Vec<u8> {
+    let mut packet = vec![];
+    write_string(&mut packet, &message.address);
+
+    let mut tags = ",".to_string();
+    let mut data = vec![];
+    for arg in message.args.iter() {
+        match arg {
+            OscArg::Int(v) => {
+                tags.push('i');
+                data.extend(v.to_be_bytes());
+            }
+            OscArg::Long(v) => {
+                tags.push('h');
+                data.extend(v.to_be_bytes());
+            }
+            OscArg::Float(v) => {
+                tags.push('f');
+                data.extend(v.to_be_bytes());
+            }
+            OscArg::Double(v) => {
+                tags.push('d');
+                data.extend(v.to_be_bytes());
+            }
+            OscArg::Text(v) => {
+                tags.push('s');
+                write_string(&mut data, v);
+            }
+            OscArg::Bool(v) => tags.push(if *v { 'T' } else { 'F' }),
+            OscArg::Nil => tags.push('N'),
+        }
+    }
+    write_string(&mut packet, &tags);
+    packet.extend(data);
+    packet
+}
+
+/// Strings are null terminated and padded to a multiple of four bytes
+fn write_string(packet: &mut Vec<u8>, text: &str) {
+    packet.extend(text.as_bytes());
+    packet.extend(std::iter::repeat_n(0, 4 - text.len() % 4));
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.saturating_add(len);
+        let bytes = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or("OSC packet ends early")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn int(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.bytes[self.pos..];
+        let len = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or("unterminated OSC string")?;
+        let text = String::from_utf8_lossy(&rest[..len]).to_string();
+        self.take((len / 4 + 1) * 4)?;
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(address: &str, args: Vec<OscArg>) -> OscMessage {
+        OscMessage {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    /// A bundle holding the given packets, with an immediate time tag
+    fn bundle(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut packet = vec![];
+        write_string(&mut packet, "#bundle");
+        packet.extend(1u64.to_be_bytes());
+        for element in elements {
+            packet.extend((element.len() as i32).to_be_bytes());
+            packet.extend(element);
+        }
+        packet
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            message("/transport/play", vec![]),
+            message("/abc", vec![OscArg::Int(-7)]),
+            message(
+                "/module/noise_1/scale",
+                vec![
+                    OscArg::Int(42),
+                    OscArg::Long(-1 << 40),
+                    OscArg::Float(0.25),
+                    OscArg::Double(1e-9),
+                    OscArg::Text("four".to_string()),
+                    OscArg::Text("odd".to_string()),
+                    OscArg::Text(String::new()),
+                    OscArg::Bool(true),
+                    OscArg::Bool(false),
+                    OscArg::Nil,
+                ],
+            ),
+        ];
+        for original in messages {
+            let packet = encode(&original);
+            assert_eq!(packet.len() % 4, 0);
+            assert_eq!(decode(&packet), Ok(vec![original]));
+        }
+    }
+
+    #[test]
+    fn message_without_type_tags_has_no_arguments() {
+        let mut packet = vec![];
+        write_string(&mut packet, "/transport/pause");
+        assert_eq!(
+            decode(&packet),
+            Ok(vec![message("/transport/pause", vec![])])
+        );
+    }
+
+    #[test]
+    fn bundles_return_their_messages_in_order() {
+        let first = message("/a", vec![OscArg::Float(1.0)]);
+        let second = message("/b", vec![OscArg::Int(2)]);
+        let third = message("/c", vec![]);
+        let packet = bundle(&[encode(&first), bundle(&[encode(&second), encode(&third)])]);
+        assert_eq!(decode(&packet), Ok(vec![first, second, third]));
+    }
+
+    #[test]
+    fn rejects_bundles_nested_too_deep() {
+        let mut packet = encode(&message("/deep", vec![]));
+        for _ in 0..MAX_BUNDLE_DEPTH {
+            packet = bundle(&[packet]);
+        }
+        assert_eq!(decode(&packet).map(|m| m.len()), Ok(1));
+        assert!(decode(&bundle(&[packet])).is_err());
+    }
+
+    #[test]
+    fn rejects_negative_bundle_sizes() {
+        let mut packet = bundle(&[]);
+        packet.extend((-4i32).to_be_bytes());
+        packet.extend(encode(&message("/a", vec![])));
+        assert!(decode(&packet).is_err());
+    }
+
+    #[test]
+    fn rejects_bundle_elements_past_the_end() {
+        let mut packet = bundle(&[encode(&message("/a", vec![]))]);
+        packet.truncate(packet.len() - 4);
+        assert!(decode(&packet).is_err());
+
+        let mut packet = bundle(&[]);
+        packet.extend(1000i32.to_be_bytes());
+        assert!(decode(&packet).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_strings() {
+        // no terminator at all
+        assert!(decode(b"/abc").is_err());
+        // terminated but missing the padding
+        assert!(decode(b"/abcde\0").is_err());
+        let packet = encode(&message("/a", vec![OscArg::Text("hello".to_string())]));
+        assert!(decode(&packet[..packet.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_arguments() {
+        for arg in [
+            OscArg::Int(1),
+            OscArg::Long(1),
+            OscArg::Float(1.0),
+            OscArg::Double(1.0),
+        ] {
+            let packet = encode(&message("/a", vec![arg]));
+            assert!(decode(&packet[..packet.len() - 1]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_type_tags_and_addresses() {
+        let mut packet = vec![];
+        write_string(&mut packet, "/a");
+        write_string(&mut packet, ",b");
+        packet.extend(4i32.to_be_bytes());
+        packet.extend([0; 4]);
+        assert!(decode(&packet).is_err());
+
+        let mut packet = vec![];
+        write_string(&mut packet, "no/slash");
+        assert!(decode(&packet).is_err());
+        assert!(decode(&[]).is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::common::ModuleWin;
+
+/// The value of a single module parameter
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ParamValue {
+    Float(f32),
+    Int(i32),
+    Bool(bool),
+    Color(LinearRgba),
+    Vec2(Vec2),
+    Text(String),
+    /// One of the strings in [`Param::options`]
+    Choice(String),
+    /// Colour ramp, stops may be in any order
+    Gradient(Vec<GradientStop>),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GradientStop {
+    pub position: f32,
+    pub color: LinearRgba,
+}
+
+/// Returns the stops ordered by position
+pub fn sorted_stops(stops: &[GradientStop]) -> Vec<GradientStop> {
+    let mut sorted = stops.to_vec();
+    sorted.sort_by(|a, b| a.position.total_cmp(&b.position));
+    sorted
+}
+
+/// Samples a colour ramp at `t`, clamping to the first and last stop
+pub fn sample_gradient(stops: &[GradientStop], t: f32) -> LinearRgba {
+    let sorted = sorted_stops(stops);
+    let (Some(first), Some(last)) = (sorted.first(), sorted.last()) else {
+        return LinearRgba::BLACK;
+    };
+    if t <= first.position {
+        return first.color;
+    }
+    for pair in sorted.windows(2) {
+        if t <= pair[1].position {
+            let span = (pair[1].position - pair[0].position).max(f32::EPSILON);
+            return pair[0]
+                .color
+                .mix(&pair[1].color, (t - pair[0].position) / span);
+        }
+    }
+    last.color
+}
+
+/// A named, editable parameter exposed by a module instance.
+/// `range` is used by numeric values, `options` by [`ParamValue::Choice`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Param {
+    pub name: String,
+    pub value: ParamValue,
+    pub range: RangeInclusive<f32>,
+    pub options: Vec<String>,
+}
+
+impl Param {
+    fn new(name: &str, value: ParamValue) -> Self {
+        Param {
+            name: name.to_string(),
+            value,
+            range: 0.0..=1.0,
+            options: vec![],
+        }
+    }
+
+    pub fn float(name: &str, value: f32, range: RangeInclusive<f32>) -> Self {
+        Param {
+            range,
+            ..Param::new(name, ParamValue::Float(value))
+        }
+    }
+
+    pub fn int(name: &str, value: i32, range: RangeInclusive<i32>) -> Self {
+        Param {
+            range: *range.start() as f32..=*range.end() as f32,
+            ..Param::new(name, ParamValue::Int(value))
+        }
+    }
+
+    pub fn bool(name: &str, value: bool) -> Self {
+        Param::new(name, ParamValue::Bool(value))
+    }
+
+    pub fn color(name: &str, value: LinearRgba) -> Self {
+        Param::new(name, ParamValue::Color(value))
+    }
+
+    pub fn vec2(name: &str, value: Vec2, range: RangeInclusive<f32>) -> Self {
+        Param {
+            range,
+            ..Param::new(name, ParamValue::Vec2(value))
+        }
+    }
+
+    pub fn text(name: &str, value: &str) -> Self {
+        Param::new(name, ParamValue::Text(value.to_string()))
+    }
+
+    pub fn choice(name: &str, value: &str, options: Vec<String>) -> Self {
+        Param {
+            options,
+            ..Param::new(name, ParamValue::Choice(value.to_string()))
+        }
+    }
+
+    pub fn gradient(name: &str, stops: Vec<GradientStop>) -> Self {
+        Param::new(name, ParamValue::Gradient(stops))
+    }
+}
+
+/// The parameters of a module instance, stored on the module root next to [`crate::common::ModuleWin`].
+/// Modules read them back by name in their own systems, the UI edits them generically.
+#[derive(Component, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModuleParams(pub Vec<Param>);
+
+impl ModuleParams {
+    pub fn get(&self, name: &str) -> Option<&ParamValue> {
+        self.0.iter().find(|p| p.name == name).map(|p| &p.value)
+    }
+
+    pub fn float(&self, name: &str) -> f32 {
+        match self.get(name) {
+            Some(ParamValue::Float(v)) => *v,
+            _ => 0.0,
+        }
+    }
+
+    pub fn int(&self, name: &str) -> i32 {
+        match self.get(name) {
+            Some(ParamValue::Int(v)) => *v,
+            _ => 0,
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(ParamValue::Bool(true)))
+    }
+
+    pub fn color(&self, name: &str) -> LinearRgba {
+        match self.get(name) {
+            Some(ParamValue::Color(v)) => *v,
+            _ => LinearRgba::WHITE,
+        }
+    }
+
+    pub fn vec2(&self, name: &str) -> Vec2 {
+        match self.get(name) {
+            Some(ParamValue::Vec2(v)) => *v,
+            _ => Vec2::ZERO,
+        }
+    }
+
+    /// Returns the string of a [`ParamValue::Text`] or [`ParamValue::Choice`]
+    pub fn text(&self, name: &str) -> &str {
+        match self.get(name) {
+            Some(ParamValue::Text(v)) | Some(ParamValue::Choice(v)) => v,
+            _ => "",
+        }
+    }
+
+    pub fn gradient(&self, name: &str) -> &[GradientStop] {
+        match self.get(name) {
+            Some(ParamValue::Gradient(v)) => v,
+            _ => &[],
+        }
+    }
+}
+
+/// The parameters a module renders with: its [`ModuleParams`] with the audio bindings applied over them.
+/// Modules read these, while the UI, the history and the project keep working on the values the user set.
+#[derive(Component, Clone, Debug, Default, PartialEq)]
+pub struct LiveParams(pub ModuleParams);
+
+/// A parameter value from outside the editor, like OSC or a web page, converted to and from the type of the parameter
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExternalValue {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    /// A vector or an RGBA colour
+    List(Vec<f64>),
+}
+
+impl ExternalValue {
+    /// Writes the value into `param` if it fits its type, returns false otherwise.
+    /// Numbers are kept in the range of the parameter and choices to its options, like the editor's widgets do.
+    pub fn apply(&self, param: &mut Param) -> bool {
+        let range = param.range.clone();
+        let clamp = |n: f64| (n as f32).clamp(*range.start(), *range.end());
+        match (&mut param.value, self) {
+            (ParamValue::Float(v), ExternalValue::Number(n)) => *v = clamp(*n),
+            (ParamValue::Int(v), ExternalValue::Number(n)) => *v = clamp(n.round()) as i32,
+            (ParamValue::Bool(v), ExternalValue::Bool(b)) => *v = *b,
+            (ParamValue::Text(v), ExternalValue::Text(t)) => *v = t.clone(),
+            (ParamValue::Choice(v), ExternalValue::Text(t)) if param.options.contains(t) => {
+                *v = t.clone();
+            }
+            (ParamValue::Vec2(v), ExternalValue::List(l)) if l.len() == 2 => {
+                *v = Vec2::new(clamp(l[0]), clamp(l[1]));
+            }
+            (ParamValue::Color(v), ExternalValue::List(l)) if l.len() == 3 || l.len() == 4 => {
+                *v = LinearRgba::new(
+                    l[0] as f32,
+                    l[1] as f32,
+                    l[2] as f32,
+                    l.get(3).copied().unwrap_or(1.0) as f32,
+                );
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// The value a control like a fader at `t` from 0 to 1 sets `param` to, `min` and `max` being what 0 and 1 map to.
+    /// None for parameters a single control can't drive.
+    pub fn from_control(param: &Param, min: f32, max: f32, t: f32) -> Option<Self> {
+        let t = t.clamp(0.0, 1.0);
+        match &param.value {
+            ParamValue::Float(_) | ParamValue::Int(_) => {
+                Some(ExternalValue::Number(min.lerp(max, t) as f64))
+            }
+            ParamValue::Bool(_) => Some(ExternalValue::Bool(t > 0.5)),
+            ParamValue::Choice(_) if !param.options.is_empty() => {
+                let index = (t * (param.options.len() - 1) as f32).round() as usize;
+                Some(ExternalValue::Text(param.options[index].clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Sets a parameter of the module with this name, from outside the editor's params window.
+/// The params are flagged as changed like an edit in the window, so modules pick it up the same way.
+/// It isn't an edit of the user's, so it doesn't end up in the undo history.
+#[derive(Event, Clone, Debug)]
+pub struct SetParam {
+    pub module: String,
+    pub param: String,
+    pub value: ExternalValue,
+}
+
+pub struct ParamsPlugin;
+
+impl Plugin for ParamsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(set_param);
+    }
+}
+
+fn set_param(set: On<SetParam>, mut modules: Query<(&Name, &mut ModuleParams), With<ModuleWin>>) {
+    let Some((_, mut params)) = modules
+        .iter_mut()
+        .find(|(name, _)| name.as_str() == set.module)
+    else {
+        warn!("no module named {}", set.module);
+        return;
+    };
+    let Some(target) = params
+        .bypass_change_detection()
+        .0
+        .iter_mut()
+        .find(|p| p.name == set.param)
+    else {
+        warn!("{} has no parameter {}", set.module, set.param);
+        return;
+    };
+    if set.value.apply(target) {
+        params.set_changed();
+    } else {
+        warn!(
+            "{:?} doesn't fit parameter {} of {}",
+            set.value, set.param, set.module
+        );
+    }
+}
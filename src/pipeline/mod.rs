@@ -1,30 +1,216 @@
-use std::any::Any;
-
-use bevy::render::renderer::{RenderContext, RenderDevice};
-use bevy::{prelude::*, render};
-use bevy::render::render_resource::*;
-use std::any::{TypeId};
-
-pub fn create_render_target(
-    // render_device: &RenderDevice,
-    render_device: Res<RenderDevice>,
-) {
-    let tid = render_device.wgpu_device().type_id();
-    println!("I AM IN THE FUNCTION");
-    println!("{tid:?}");
-    // let mut texture = render_device.create_texture(&TextureDescriptor {
-    //     label: Some("test"),
-    //     size: Extent3d { width: 1920, height: 1080, depth_or_array_layers: 1 },
-    //     mip_level_count: 1,
-    //     sample_count: 1,
-    //     dimension: TextureDimension::D2,
-    //     format: TextureFormat::Rgba8UnormSrgb,
-    //     usage: TextureUsages::RENDER_ATTACHMENT
-    //         | TextureUsages::TEXTURE_BINDING
-    //         | TextureUsages::COPY_SRC,
-    //     view_formats: todo!(),
-        
-    //  });
-
-    // return texture
+use bevy::image::TextureFormatPixelInfo;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureFormat, TextureUsages};
+
+use crate::common::ModuleWin;
+
+/// Released targets kept around for reuse, the oldest ones beyond this are freed
+const MAX_FREE_TARGETS: usize = 8;
+
+/// Hands out the textures modules render into.
+///
+/// Targets are leased to a module root and go back to the pool when the root is despawned,
+/// so a restart or deleting and spawning a module reuses the textures instead of allocating new ones.
+#[derive(Resource, Default)]
+pub struct RenderTargetPool {
+    leased: HashMap<Entity, Vec<Handle<Image>>>,
+    free: Vec<Handle<Image>>,
+}
+
+/// GPU memory taken by the render targets, in bytes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolMemory {
+    pub leased_targets: usize,
+    pub leased_bytes: usize,
+    pub free_targets: usize,
+    pub free_bytes: usize,
+}
+
+fn extent(size: UVec2) -> Extent3d {
+    Extent3d {
+        width: size.x.max(1),
+        height: size.y.max(1),
+        depth_or_array_layers: 1,
+    }
+}
+
+fn target_bytes(image: &Image) -> usize {
+    let descriptor = &image.texture_descriptor;
+    let size = descriptor.size;
+    descriptor.format.pixel_size().unwrap_or(4)
+        * (size.width * size.height * size.depth_or_array_layers) as usize
+}
+
+impl RenderTargetPool {
+    /// A target of the given size and format for the module `owner`, recycled if a free one matches
+    pub fn acquire(
+        &mut self,
+        images: &mut Assets<Image>,
+        owner: Entity,
+        size: UVec2,
+        format: TextureFormat,
+    ) -> Handle<Image> {
+        let extent = extent(size);
+        let recycled = self.free.iter().position(|handle| {
+            images.get(handle).is_some_and(|image| {
+                image.texture_descriptor.size == extent && image.texture_descriptor.format == format
+            })
+        });
+
+        let handle = match recycled {
+            Some(i) => self.free.remove(i),
+            None => {
+                debug!(?extent, ?format, "allocating render target");
+                let mut image =
+                    Image::new_target_texture(extent.width, extent.height, format, None);
+                // lets a frame of the module be read back and saved
+                image.texture_descriptor.usage |= TextureUsages::COPY_SRC;
+                images.add(image)
+            }
+        };
+        self.leased.entry(owner).or_default().push(handle.clone());
+        handle
+    }
+
+    /// Resizes the targets of a module in place, the cameras and sprites using them keep their handles
+    pub fn resize(&mut self, images: &mut Assets<Image>, owner: Entity, size: UVec2) {
+        for target in self.leased.get(&owner).into_iter().flatten() {
+            if let Some(image) = images.get_mut(target)
+                && image.texture_descriptor.size != extent(size)
+            {
+                image.resize(extent(size));
+            }
+        }
+    }
+
+    /// Puts the targets of a module back into the pool
+    pub fn release(&mut self, owner: Entity) {
+        let Some(targets) = self.leased.remove(&owner) else {
+            return;
+        };
+        self.free.extend(targets);
+        if self.free.len() > MAX_FREE_TARGETS {
+            let excess = self.free.len() - MAX_FREE_TARGETS;
+            self.free.drain(..excess);
+        }
+    }
+
+    pub fn memory(&self, images: &Assets<Image>) -> PoolMemory {
+        let bytes = |handles: &mut dyn Iterator<Item = &Handle<Image>>| -> usize {
+            handles
+                .filter_map(|handle| images.get(handle))
+                .map(target_bytes)
+                .sum()
+        };
+        PoolMemory {
+            leased_targets: self.leased.values().map(Vec::len).sum(),
+            leased_bytes: bytes(&mut self.leased.values().flatten()),
+            free_targets: self.free.len(),
+            free_bytes: bytes(&mut self.free.iter()),
+        }
+    }
+}
+
+pub struct RenderTargetPoolPlugin;
+
+impl Plugin for RenderTargetPoolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderTargetPool>()
+            .add_observer(release_module_targets);
+    }
+}
+
+/// Module roots are despawned on delete and on restart, either way their targets can be reused
+fn release_module_targets(remove: On<Remove, ModuleWin>, mut pool: ResMut<RenderTargetPool>) {
+    pool.release(remove.entity);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+    fn owners(count: usize) -> Vec<Entity> {
+        let mut world = World::new();
+        (0..count).map(|_| world.spawn_empty().id()).collect()
+    }
+
+    #[test]
+    fn reuses_released_targets_of_the_same_size_and_format() {
+        let mut images = Assets::<Image>::default();
+        let mut pool = RenderTargetPool::default();
+        let owners = owners(4);
+        let size = UVec2::new(64, 32);
+
+        let first = pool.acquire(&mut images, owners[0], size, FORMAT);
+        pool.release(owners[0]);
+        assert_eq!(pool.acquire(&mut images, owners[1], size, FORMAT), first);
+        assert_eq!(pool.free.len(), 0);
+
+        pool.release(owners[1]);
+        let other_size = pool.acquire(&mut images, owners[2], UVec2::new(32, 64), FORMAT);
+        let other_format = pool.acquire(&mut images, owners[3], size, TextureFormat::Rgba16Float);
+        assert_ne!(other_size, first);
+        assert_ne!(other_format, first);
+        assert_eq!(pool.free, [first]);
+        assert_eq!(images.len(), 3);
+    }
+
+    #[test]
+    fn releasing_twice_or_an_unknown_owner_does_nothing() {
+        let mut images = Assets::<Image>::default();
+        let mut pool = RenderTargetPool::default();
+        let owners = owners(2);
+        pool.acquire(&mut images, owners[0], UVec2::new(8, 8), FORMAT);
+        pool.release(owners[0]);
+        pool.release(owners[0]);
+        pool.release(owners[1]);
+        assert_eq!(pool.free.len(), 1);
+    }
+
+    #[test]
+    fn frees_the_oldest_targets_beyond_the_limit() {
+        let mut images = Assets::<Image>::default();
+        let mut pool = RenderTargetPool::default();
+        let owners = owners(MAX_FREE_TARGETS + 2);
+        let handles: Vec<_> = owners
+            .iter()
+            .enumerate()
+            .map(|(i, owner)| {
+                pool.acquire(&mut images, *owner, UVec2::new(8, i as u32 + 1), FORMAT)
+            })
+            .collect();
+        for owner in &owners {
+            pool.release(*owner);
+        }
+        assert_eq!(pool.free, handles[2..]);
+    }
+
+    #[test]
+    fn counts_the_memory_of_leased_and_free_targets() {
+        let mut images = Assets::<Image>::default();
+        let mut pool = RenderTargetPool::default();
+        let owners = owners(2);
+        pool.acquire(&mut images, owners[0], UVec2::new(64, 32), FORMAT);
+        pool.acquire(
+            &mut images,
+            owners[0],
+            UVec2::new(64, 32),
+            TextureFormat::Rgba16Float,
+        );
+        pool.acquire(&mut images, owners[1], UVec2::new(10, 10), FORMAT);
+        pool.release(owners[1]);
+
+        let memory = pool.memory(&images);
+        assert_eq!(memory.leased_targets, 2);
+        assert_eq!(memory.leased_bytes, 64 * 32 * 4 + 64 * 32 * 8);
+        assert_eq!(memory.free_targets, 1);
+        assert_eq!(memory.free_bytes, 10 * 10 * 4);
+
+        // resizing in place changes what the targets take
+        pool.resize(&mut images, owners[0], UVec2::new(32, 32));
+        assert_eq!(pool.memory(&images).leased_bytes, 32 * 32 * 4 + 32 * 32 * 8);
+    }
 }
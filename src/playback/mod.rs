@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+
+use crate::keymap::{Action, action_just_pressed};
+
+/// The composition clock. Modules should animate from this instead of [`Time`]
+/// so they can be paused, scrubbed and stepped frame by frame.
+#[derive(Resource)]
+pub struct Playback {
+    /// Current position in seconds
+    pub time: f32,
+    pub playing: bool,
+    pub frame_rate: f32,
+    /// Time jumps back to the start after this many seconds
+    pub loop_duration: Option<f32>,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback {
+            time: 0.0,
+            playing: true,
+            frame_rate: 30.0,
+            loop_duration: None,
+        }
+    }
+}
+
+impl Playback {
+    /// The composition frame the current time falls in
+    pub fn frame(&self) -> u64 {
+        (self.time * self.frame_rate).floor() as u64
+    }
+}
+
+pub struct PlaybackPlugin;
+
+impl Plugin for PlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Playback>()
+            .add_systems(First, advance_playback)
+            .add_systems(
+                Update,
+                toggle_playback.run_if(action_just_pressed(Action::PlayPause)),
+            );
+    }
+}
+
+pub fn advance_playback(time: Res<Time>, mut playback: ResMut<Playback>) {
+    if playback.playing {
+        playback.time += time.delta_secs();
+        if let Some(duration) = playback.loop_duration
+            && duration > 0.0
+        {
+            playback.time %= duration;
+        }
+    }
+}
+
+fn toggle_playback(mut playback: ResMut<Playback>) {
+    playback.playing = !playback.playing;
+}
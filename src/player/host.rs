@@ -0,0 +1,141 @@
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+
+use bevy::prelude::*;
+
+use crate::common::ModuleWin;
+use crate::keymap::{Action, PressedActions};
+use crate::params::{ExternalValue, ModuleParams, ParamValue, SetParam};
+use crate::playback::Playback;
+
+/// Something a host, like the page embedding a web player, asks the player to do
+#[derive(Clone, Debug)]
+pub enum HostCommand {
+    Play,
+    Pause,
+    Seek(f32),
+    SetLoop(Option<f32>),
+    SetParam(SetParam),
+    /// Handled like the shortcut of the action was pressed
+    Trigger(Action),
+}
+
+/// A parameter of a module as published to the host
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostParam {
+    pub module: String,
+    pub name: String,
+    pub value: Option<ExternalValue>,
+    pub range: RangeInclusive<f32>,
+    pub options: Vec<String>,
+}
+
+/// Changes kept for a host that doesn't take them, the oldest ones beyond this are dropped
+const MAX_CHANGED: usize = 1024;
+
+/// The host calls in between frames, so it talks to the ECS through these
+static COMMANDS: Mutex<Vec<HostCommand>> = Mutex::new(Vec::new());
+static PARAMS: Mutex<Vec<HostParam>> = Mutex::new(Vec::new());
+static CHANGED: Mutex<Vec<HostParam>> = Mutex::new(Vec::new());
+
+pub fn queue_command(command: HostCommand) {
+    if let Ok(mut commands) = COMMANDS.lock() {
+        commands.push(command);
+    }
+}
+
+/// Every parameter of every module, as of the end of the last frame
+pub fn params() -> Vec<HostParam> {
+    PARAMS
+        .lock()
+        .map(|params| params.clone())
+        .unwrap_or_default()
+}
+
+/// The parameters whose value changed since the last call, whatever changed them
+pub fn take_changed_params() -> Vec<HostParam> {
+    CHANGED
+        .lock()
+        .map(|mut changed| std::mem::take(&mut *changed))
+        .unwrap_or_default()
+}
+
+pub(super) fn apply_host_commands(
+    mut commands: Commands,
+    mut playback: ResMut<Playback>,
+    mut pressed: ResMut<PressedActions>,
+) {
+    let queued = match COMMANDS.lock() {
+        Ok(mut queue) => std::mem::take(&mut *queue),
+        Err(_) => return,
+    };
+
+    for command in queued {
+        match command {
+            HostCommand::Play => playback.playing = true,
+            HostCommand::Pause => playback.playing = false,
+            HostCommand::Seek(time) => playback.time = time.max(0.0),
+            HostCommand::SetLoop(duration) => playback.loop_duration = duration,
+            HostCommand::Trigger(action) => pressed.press(action),
+            HostCommand::SetParam(set) => commands.trigger(set),
+        }
+    }
+}
+
+/// Publishes the parameters to the host after they changed, and which of their values did
+pub(super) fn publish_params(
+    modules: Query<(&Name, &ModuleParams), With<ModuleWin>>,
+    changed: Query<(), (Changed<ModuleParams>, With<ModuleWin>)>,
+    mut removed: RemovedComponents<ModuleParams>,
+) {
+    let removed = removed.read().count() > 0;
+    if changed.is_empty() && !removed {
+        return;
+    }
+
+    let current: Vec<HostParam> = modules
+        .iter()
+        .flat_map(|(name, params)| {
+            params.0.iter().map(|param| HostParam {
+                module: name.to_string(),
+                name: param.name.clone(),
+                value: host_value(&param.value),
+                range: param.range.clone(),
+                options: param.options.clone(),
+            })
+        })
+        .collect();
+
+    let (Ok(mut published), Ok(mut changed)) = (PARAMS.lock(), CHANGED.lock()) else {
+        return;
+    };
+    changed.extend(
+        current
+            .iter()
+            .filter(|param| {
+                published
+                    .iter()
+                    .find(|old| old.module == param.module && old.name == param.name)
+                    .is_none_or(|old| old.value != param.value)
+            })
+            .cloned(),
+    );
+    if changed.len() > MAX_CHANGED {
+        let excess = changed.len() - MAX_CHANGED;
+        changed.drain(..excess);
+    }
+    *published = current;
+}
+
+/// A parameter value as the host sees it, gradients have no external representation
+fn host_value(value: &ParamValue) -> Option<ExternalValue> {
+    Some(match value {
+        ParamValue::Float(v) => ExternalValue::Number(*v as f64),
+        ParamValue::Int(v) => ExternalValue::Number(*v as f64),
+        ParamValue::Bool(v) => ExternalValue::Bool(*v),
+        ParamValue::Text(v) | ParamValue::Choice(v) => ExternalValue::Text(v.clone()),
+        ParamValue::Vec2(v) => ExternalValue::List(vec![v.x as f64, v.y as f64]),
+        ParamValue::Color(v) => ExternalValue::List(v.to_f32_array().map(f64::from).to_vec()),
+        ParamValue::Gradient(_) => return None,
+    })
+}
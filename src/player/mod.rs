@@ -0,0 +1,117 @@
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+
+use crate::common::AppState;
+use crate::keymap;
+use crate::project::{PLAYER_PROJECT_ASSET, Project};
+
+/// The API a host embedding the player drives it with
+pub mod host;
+
+/// A project loaded through the asset server, which is the only way to read files on the web
+#[derive(Asset, TypePath, Debug)]
+pub struct ProjectAsset(pub Project);
+
+#[derive(Default, TypePath)]
+pub struct ProjectLoader;
+
+impl AssetLoader for ProjectLoader {
+    type Asset = ProjectAsset;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<ProjectAsset, BevyError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ProjectAsset(ron::de::from_bytes(&bytes)?))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["project.ron"]
+    }
+}
+
+/// The project the player is playing, spawned once it has loaded
+#[derive(Resource)]
+struct PlayerProject {
+    handle: Handle<ProjectAsset>,
+    spawned: bool,
+}
+
+/// Plays a project without the editor
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ProjectAsset>()
+            .init_asset_loader::<ProjectLoader>()
+            .add_systems(Startup, load_player_project)
+            .add_systems(
+                Update,
+                spawn_player_project.run_if(in_state(AppState::Running)),
+            )
+            // before Update, so triggered actions are seen by the systems handling them this frame
+            .add_systems(
+                PreUpdate,
+                host::apply_host_commands
+                    .after(keymap::read_actions)
+                    .run_if(in_state(AppState::Running)),
+            )
+            .add_systems(
+                PostUpdate,
+                host::publish_params.run_if(in_state(AppState::Running)),
+            );
+    }
+}
+
+fn load_player_project(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut projects: ResMut<Assets<ProjectAsset>>,
+) {
+    let handle = match project_from_args() {
+        Some(project) => projects.add(ProjectAsset(project)),
+        None => asset_server.load(PLAYER_PROJECT_ASSET),
+    };
+    commands.insert_resource(PlayerProject {
+        handle,
+        spawned: false,
+    });
+}
+
+/// The project file passed on the command line, without one the player plays the one in the assets like the web export
+fn project_from_args() -> Option<Project> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = std::env::args().nth(1) {
+        let project = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| ron::from_str::<Project>(&text).map_err(|err| err.to_string()));
+        match project {
+            Ok(project) => return Some(project),
+            Err(err) => error!("couldn't load {path}: {err}"),
+        }
+    }
+    None
+}
+
+fn spawn_player_project(
+    mut commands: Commands,
+    mut project: ResMut<PlayerProject>,
+    mut projects: ResMut<Assets<ProjectAsset>>,
+) {
+    if project.spawned {
+        return;
+    }
+    let Some(ProjectAsset(loaded)) = projects.remove(&project.handle) else {
+        return;
+    };
+    info!("playing {} modules", loaded.modules.len());
+    loaded.spawn(&mut commands);
+    project.spawned = true;
+}
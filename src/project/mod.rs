@@ -0,0 +1,133 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::common::AppState;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::keymap::{Action, action_just_pressed};
+use crate::audio::AudioTrack;
+use crate::midi::{MidiMapping, MidiMappings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::osc::{OscMapping, OscMappings};
+use crate::module::{ModuleSnapshot, SnapshotData, SpawnModuleEvent};
+
+/// The file the project is saved to, next to the assets directory
+pub const PROJECT_PATH: &str = "project.ron";
+/// The project a player plays, in its assets directory
+pub const PLAYER_PROJECT_ASSET: &str = "main.project.ron";
+
+/// Every module instance of a composition with its parameters
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Project {
+    pub modules: Vec<ModuleSnapshot>,
+    /// MIDI controls driving parameters of the modules
+    #[serde(default)]
+    pub midi: Vec<MidiMapping>,
+    /// The audio track and the parameters its features drive
+    #[serde(default)]
+    pub audio: AudioTrack,
+    /// OSC addresses driving parameters of the modules, the web player has no OSC and skips them
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(default)]
+    pub osc: Vec<OscMapping>,
+}
+
+/// What a project keeps besides its modules
+#[derive(SystemParam)]
+pub struct ProjectResources<'w> {
+    midi: Res<'w, MidiMappings>,
+    audio: Res<'w, AudioTrack>,
+    #[cfg(not(target_arch = "wasm32"))]
+    osc: Res<'w, OscMappings>,
+}
+
+impl Project {
+    pub fn take(modules: &Query<SnapshotData>, resources: &ProjectResources) -> Self {
+        let mut modules: Vec<ModuleSnapshot> = modules.iter().map(ModuleSnapshot::take).collect();
+        modules.sort_by_key(|module| module.id.0);
+        Project {
+            modules,
+            midi: resources.midi.0.clone(),
+            audio: resources.audio.clone(),
+            #[cfg(not(target_arch = "wasm32"))]
+            osc: resources.osc.0.clone(),
+        }
+    }
+
+    /// Spawns every module of the project and restores its MIDI and OSC mappings and audio track
+    pub fn spawn(self, commands: &mut Commands) {
+        commands.insert_resource(MidiMappings(self.midi));
+        #[cfg(not(target_arch = "wasm32"))]
+        commands.insert_resource(OscMappings(self.osc));
+        commands.insert_resource(self.audio);
+        for snapshot in self.modules {
+            commands.trigger(SpawnModuleEvent {
+                moduleclass: snapshot.class,
+                snapshot: Some(snapshot),
+            });
+        }
+    }
+}
+
+/// The project a restart tore down, serialized like a saved project.
+/// It is spawned again once the module setup systems registered their spawners.
+#[derive(Resource, Default)]
+struct RestartProject(Option<String>);
+
+pub struct ProjectPlugin;
+
+impl Plugin for ProjectPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RestartProject>()
+            .add_systems(OnExit(AppState::Running), stash_project)
+            .add_systems(OnEnter(AppState::Running), restore_project);
+
+        // there is no file system to save to on the web
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(
+            Update,
+            save_project
+                .run_if(action_just_pressed(Action::Save))
+                .run_if(in_state(AppState::Running)),
+        );
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_project(modules: Query<SnapshotData>, resources: ProjectResources) {
+    let path = bevy::asset::io::file::FileAssetReader::get_base_path().join(PROJECT_PATH);
+    let result = ron::ser::to_string_pretty(&Project::take(&modules, &resources), default())
+        .map_err(|err| err.to_string())
+        .and_then(|text| std::fs::write(&path, text).map_err(|err| err.to_string()));
+    match result {
+        Ok(()) => info!("saved project to {}", path.display()),
+        Err(err) => error!("couldn't save project to {}: {err}", path.display()),
+    }
+}
+
+fn stash_project(
+    modules: Query<SnapshotData>,
+    resources: ProjectResources,
+    mut stash: ResMut<RestartProject>,
+) {
+    match ron::to_string(&Project::take(&modules, &resources)) {
+        Ok(text) => stash.0 = Some(text),
+        Err(err) => error!("couldn't keep the project over the restart: {err}"),
+    }
+}
+
+fn restore_project(mut commands: Commands, mut stash: ResMut<RestartProject>) {
+    let Some(text) = stash.0.take() else {
+        return;
+    };
+    let project = match ron::from_str::<Project>(&text) {
+        Ok(project) => project,
+        Err(err) => {
+            error!("couldn't restore the project after the restart: {err}");
+            return;
+        }
+    };
+
+    info!("restoring {} modules", project.modules.len());
+    project.spawn(&mut commands);
+}
@@ -35,10 +35,16 @@ use bevy::{
 
 pub struct ShaderChainPlugin;
 
+/// Divides the alpha back out at the end of every chain, see [`ShaderChainCamera`]
+const UNPREMULTIPLY_SHADER: &str = "shaders/include/unpremultiply.wgsl";
+
+/// Cameras rendering a module into its target clear it to transparent,
+/// so what they render ends up with premultiplied alpha and the chain's shaders get it that way.
+/// The chain ends with a pass back to straight alpha, which the sprites showing the target expect
 #[derive(Component, Default, Clone, ExtractComponent)]
 pub struct ShaderChainCamera {
+    /// Post processing shaders applied in order, an empty chain only goes back to straight alpha
     pub shaders: Vec<String>,
-    pub iid: u32,
 }
 
 
@@ -127,13 +133,11 @@ fn find_chains(
     fullscreen_shader: Res<FullscreenShader>,
 ) {
     for (entity, mut chain) in query.iter() {
-        if post_process_pipeline.pipelines.contains_key(&chain.iid) {
+        // pipelines are shared between all cameras using the same chain
+        if post_process_pipeline.pipelines.contains_key(&chain.shaders) {
             continue;
         }else{
-            let shader: Handle<Shader> = asset_server.load(&chain.shaders[0]);
-            
-            
-            println!("Initializing post process pipeline");
+            debug!("initializing post process pipeline for {:?}", chain.shaders);
             // println!("Found shader chain camera with shaders: {:?}", chain.shaders);
             // We need to define the bind group layout used for our pipeline
 
@@ -159,7 +163,7 @@ fn find_chains(
             
             let mut pipeline_ids: Vec<CachedRenderPipelineId> = vec![];
             
-            for shader in chain.shaders.iter() {
+            for shader in chain.shaders.iter().cloned().chain([UNPREMULTIPLY_SHADER.to_string()]) {
                 let vertex_state = fullscreen_shader.to_vertex_state();
                 let shader: Handle<Shader> = asset_server.load(shader);
                 let descriptor = RenderPipelineDescriptor {
@@ -185,7 +189,7 @@ fn find_chains(
                 pipeline_ids.push(pipeline_id);
     
             }
-            post_process_pipeline.pipelines.insert(chain.iid, pipeline_ids);
+            post_process_pipeline.pipelines.insert(chain.shaders.clone(), pipeline_ids);
 
         }
     }
@@ -209,7 +213,7 @@ struct PostProcessPipeline {
     layout: BindGroupLayoutDescriptor,
     sampler: Sampler,
     pipeline_id: CachedRenderPipelineId,
-    pipelines: HashMap<u32, Vec<CachedRenderPipelineId>>,
+    pipelines: HashMap<Vec<String>, Vec<CachedRenderPipelineId>>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, SpecializerKey)]
@@ -248,7 +252,7 @@ fn init_post_process_pipeline(
     fullscreen_shader: Res<FullscreenShader>,
     pipeline_cache: Res<PipelineCache>,
 ) {
-    println!("Initializing post process pipeline");
+    debug!("initializing post process pipeline");
     // println!("Found shader chain camera with shaders: {:?}", chain.shaders);
     // We need to define the bind group layout used for our pipeline
     let layout = BindGroupLayoutDescriptor::new(
@@ -292,7 +296,7 @@ fn init_post_process_pipeline(
         // This will add the pipeline to the cache and queue its creation
         .queue_render_pipeline(descriptor.clone());
 
-    let hm: HashMap<u32, Vec<CachedRenderPipelineId>> = HashMap::new();
+    let hm: HashMap<Vec<String>, Vec<CachedRenderPipelineId>> = HashMap::new();
 
     commands.insert_resource(PostProcessPipeline {
         layout,
@@ -344,7 +348,7 @@ impl ViewNode for PostProcessNode {
         // It is required to avoid creating a new pipeline each frame,
         // which is expensive due to shader compilation.
         let pipeline_cache = world.resource::<PipelineCache>();
-        let Some(pipeline_ids) = post_process_pipeline.pipelines.get(&chain.iid) else {
+        let Some(pipeline_ids) = post_process_pipeline.pipelines.get(&chain.shaders) else {
             return Ok(());
         };
 
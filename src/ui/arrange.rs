@@ -0,0 +1,444 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::common::{Immortal, ModuleWin};
+use crate::history::{History, ModuleState, Placement};
+use crate::module::{ModuleAnchor, ModuleId, ModuleLayer, Selection};
+
+use super::canvas::world_bounds;
+
+/// Thickness of the rulers along the top and left of the canvas, in screen pixels
+const RULER_SIZE: f32 = 16.0;
+/// Distance in screen pixels within which a guide is picked up
+pub const GUIDE_PICK_DISTANCE: f32 = 4.0;
+
+/// What dragged modules snap to, edited in the arrange window
+#[derive(Resource)]
+pub struct SnapSettings {
+    pub enabled: bool,
+    pub to_modules: bool,
+    pub to_canvas_centre: bool,
+    pub to_guides: bool,
+    pub to_grid: bool,
+    pub grid_size: f32,
+    /// Distance in canvas pixels within which an edge or centre snaps
+    pub threshold: f32,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        SnapSettings {
+            enabled: true,
+            to_modules: true,
+            to_canvas_centre: true,
+            to_guides: true,
+            to_grid: false,
+            grid_size: 50.0,
+            threshold: 6.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Guide {
+    /// Index into [`Guides::vertical`]
+    Vertical(usize),
+    /// Index into [`Guides::horizontal`]
+    Horizontal(usize),
+}
+
+/// Ruler guides, dragged out of the rulers and dropped back on them to remove them
+#[derive(Resource, Default)]
+pub struct Guides {
+    /// Canvas x of every vertical guide
+    pub vertical: Vec<f32>,
+    /// Canvas y of every horizontal guide
+    pub horizontal: Vec<f32>,
+    pub dragging: Option<Guide>,
+    /// Screen area of the top and left ruler
+    rulers: [Rect; 2],
+}
+
+impl Guides {
+    /// The guide within `distance` of a canvas position
+    pub fn pick(&self, position: Vec2, distance: f32) -> Option<Guide> {
+        let vertical = self
+            .vertical
+            .iter()
+            .position(|x| (x - position.x).abs() <= distance)
+            .map(Guide::Vertical);
+        vertical.or_else(|| {
+            self.horizontal
+                .iter()
+                .position(|y| (y - position.y).abs() <= distance)
+                .map(Guide::Horizontal)
+        })
+    }
+}
+
+/// Lines a dragged module snaps to, per axis
+#[derive(Default, Clone)]
+pub struct SnapTargets {
+    x: Vec<f32>,
+    y: Vec<f32>,
+    grid: Option<f32>,
+    threshold: f32,
+}
+
+impl SnapTargets {
+    /// Collects the targets for a drag, `others` are the bounds of the modules that aren't being dragged
+    pub fn new(
+        settings: &SnapSettings,
+        guides: &Guides,
+        others: impl Iterator<Item = Rect>,
+    ) -> Self {
+        let mut targets = SnapTargets {
+            grid: settings.to_grid.then_some(settings.grid_size.max(1.0)),
+            threshold: settings.threshold,
+            ..default()
+        };
+        if !settings.enabled {
+            targets.threshold = -1.0;
+            return targets;
+        }
+        if settings.to_canvas_centre {
+            targets.x.push(0.0);
+            targets.y.push(0.0);
+        }
+        if settings.to_guides {
+            targets.x.extend(guides.vertical.iter().copied());
+            targets.y.extend(guides.horizontal.iter().copied());
+        }
+        if settings.to_modules {
+            for bounds in others {
+                targets
+                    .x
+                    .extend([bounds.min.x, bounds.center().x, bounds.max.x]);
+                targets
+                    .y
+                    .extend([bounds.min.y, bounds.center().y, bounds.max.y]);
+            }
+        }
+        targets
+    }
+
+    /// The offset that moves the closest of the edges and centres of `bounds` onto a target,
+    /// and the lines it snapped to
+    pub fn snap_rect(&self, bounds: Rect) -> (Vec2, SnapLines) {
+        let (dx, x) = self.snap_axis(&[bounds.min.x, bounds.center().x, bounds.max.x], &self.x);
+        let (dy, y) = self.snap_axis(&[bounds.min.y, bounds.center().y, bounds.max.y], &self.y);
+        (Vec2::new(dx, dy), SnapLines { x, y })
+    }
+
+    pub fn snap_point(&self, point: Vec2) -> (Vec2, SnapLines) {
+        self.snap_rect(Rect::from_center_size(point, Vec2::ZERO))
+    }
+
+    fn snap_axis(&self, features: &[f32], targets: &[f32]) -> (f32, Option<f32>) {
+        let mut best: Option<(f32, f32)> = None;
+        for feature in features {
+            let grid_line = self.grid.map(|grid| (feature / grid).round() * grid);
+            for target in targets.iter().chain(grid_line.iter()) {
+                let offset = target - feature;
+                if offset.abs() <= self.threshold
+                    && best.is_none_or(|(best, _)| offset.abs() < best.abs())
+                {
+                    best = Some((offset, *target));
+                }
+            }
+        }
+        best.map_or((0.0, None), |(offset, line)| (offset, Some(line)))
+    }
+}
+
+/// The lines the current drag snapped to, drawn while dragging
+#[derive(Resource, Default, Clone, Copy)]
+pub struct SnapLines {
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+}
+
+/// Rulers along the top and left of the canvas, dragging out of one creates a guide
+pub fn ui_rulers(
+    mut contexts: EguiContexts,
+    mut guides: ResMut<Guides>,
+    camera: Single<(&Camera, &GlobalTransform), With<Immortal>>,
+) -> Result {
+    let ctx = contexts.ctx_mut()?;
+    let canvas = ctx.available_rect();
+    let (camera, camera_transform) = *camera;
+    let to_screen = |world: Vec2| {
+        camera
+            .world_to_viewport(camera_transform, world.extend(0.0))
+            .unwrap_or(Vec2::ZERO)
+    };
+    let to_world = |screen: egui::Pos2| {
+        camera
+            .viewport_to_world_2d(camera_transform, Vec2::new(screen.x, screen.y))
+            .unwrap_or(Vec2::ZERO)
+    };
+
+    let top = egui::Rect::from_min_size(canvas.min, egui::vec2(canvas.width(), RULER_SIZE));
+    let left = egui::Rect::from_min_size(canvas.min, egui::vec2(RULER_SIZE, canvas.height()));
+    guides.rulers = [top, left].map(|r| Rect::new(r.min.x, r.min.y, r.max.x, r.max.y));
+
+    egui::Area::new(egui::Id::new("rulers"))
+        .fixed_pos(canvas.min)
+        .order(egui::Order::Background)
+        .show(ctx, |ui| {
+            let stroke = ui.visuals().widgets.noninteractive.fg_stroke;
+            for (rect, horizontal) in [(top, true), (left, false)] {
+                let response = ui.allocate_rect(rect, egui::Sense::drag());
+                let painter = ui.painter();
+                painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+                // a tick every 50 canvas pixels, a longer one every 100
+                let (start, end) = if horizontal {
+                    (to_world(top.left_top()).x, to_world(top.right_top()).x)
+                } else {
+                    (to_world(left.left_bottom()).y, to_world(left.left_top()).y)
+                };
+                let mut tick = (start / 50.0).ceil() * 50.0;
+                while tick <= end {
+                    let length = if tick % 100.0 == 0.0 {
+                        RULER_SIZE
+                    } else {
+                        RULER_SIZE / 2.0
+                    };
+                    let (a, b) = if horizontal {
+                        let x = to_screen(Vec2::new(tick, 0.0)).x;
+                        (
+                            egui::pos2(x, rect.max.y - length),
+                            egui::pos2(x, rect.max.y),
+                        )
+                    } else {
+                        let y = to_screen(Vec2::new(0.0, tick)).y;
+                        (
+                            egui::pos2(rect.max.x - length, y),
+                            egui::pos2(rect.max.x, y),
+                        )
+                    };
+                    painter.line_segment([a, b], stroke);
+                    tick += 50.0;
+                }
+
+                if response.drag_started()
+                    && let Some(pointer) = response.interact_pointer_pos()
+                {
+                    let position = to_world(pointer);
+                    guides.dragging = Some(if horizontal {
+                        guides.horizontal.push(position.y);
+                        Guide::Horizontal(guides.horizontal.len() - 1)
+                    } else {
+                        guides.vertical.push(position.x);
+                        Guide::Vertical(guides.vertical.len() - 1)
+                    });
+                }
+            }
+        });
+    Ok(())
+}
+
+/// Moves the dragged guide with the pointer, dropping it on a ruler removes it
+pub fn drag_guides(
+    mouse: Res<ButtonInput<MouseButton>>,
+    window: Single<&Window>,
+    camera: Single<(&Camera, &GlobalTransform), With<Immortal>>,
+    mut guides: ResMut<Guides>,
+) {
+    let Some(guide) = guides.dragging else {
+        return;
+    };
+    let Some(cursor) = window.cursor_position() else {
+        return;
+    };
+    let (camera, camera_transform) = *camera;
+    let Ok(position) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+
+    if mouse.pressed(MouseButton::Left) {
+        match guide {
+            Guide::Vertical(i) => guides.vertical[i] = position.x,
+            Guide::Horizontal(i) => guides.horizontal[i] = position.y,
+        }
+        return;
+    }
+
+    guides.dragging = None;
+    if guides.rulers.iter().any(|ruler| ruler.contains(cursor)) {
+        match guide {
+            Guide::Vertical(i) => guides.vertical.remove(i),
+            Guide::Horizontal(i) => guides.horizontal.remove(i),
+        };
+    }
+}
+
+pub fn draw_guides(mut gizmos: Gizmos, guides: Res<Guides>, lines: Res<SnapLines>) {
+    // far enough to cross any view of the canvas
+    const EXTENT: f32 = 100_000.0;
+    let guide_color = Color::srgba(0.2, 0.8, 1.0, 0.6);
+    let snap_color = Color::srgb(1.0, 0.2, 0.8);
+
+    for x in guides.vertical.iter() {
+        gizmos.line_2d(Vec2::new(*x, -EXTENT), Vec2::new(*x, EXTENT), guide_color);
+    }
+    for y in guides.horizontal.iter() {
+        gizmos.line_2d(Vec2::new(-EXTENT, *y), Vec2::new(EXTENT, *y), guide_color);
+    }
+    if let Some(x) = lines.x {
+        gizmos.line_2d(Vec2::new(x, -EXTENT), Vec2::new(x, EXTENT), snap_color);
+    }
+    if let Some(y) = lines.y {
+        gizmos.line_2d(Vec2::new(-EXTENT, y), Vec2::new(EXTENT, y), snap_color);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Align {
+    Left,
+    CentreX,
+    Right,
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Snap settings and align and distribute commands for the selected modules
+pub fn ui_arrange(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<SnapSettings>,
+    selection: Res<Selection>,
+    mut history: ResMut<History>,
+    mut modules: Query<(
+        &mut Transform,
+        &ModuleWin,
+        &ModuleAnchor,
+        &ModuleLayer,
+        &ModuleId,
+    )>,
+) -> Result {
+    let mut align = None;
+    let mut distribute = None;
+
+    egui::Window::new("Arrange")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.checkbox(&mut settings.enabled, "Snap (hold Alt to bypass)");
+            ui.add_enabled_ui(settings.enabled, |ui| {
+                ui.checkbox(&mut settings.to_modules, "to modules");
+                ui.checkbox(&mut settings.to_canvas_centre, "to canvas centre");
+                ui.checkbox(&mut settings.to_guides, "to guides");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut settings.to_grid, "to grid");
+                    ui.add(
+                        egui::DragValue::new(&mut settings.grid_size)
+                            .range(1.0..=1000.0)
+                            .suffix(" px"),
+                    );
+                });
+                ui.add(egui::Slider::new(&mut settings.threshold, 1.0..=32.0).text("distance"));
+            });
+
+            ui.separator();
+            let count = selection.0.len();
+            ui.add_enabled_ui(count >= 2, |ui| {
+                ui.horizontal(|ui| {
+                    for (label, value) in [
+                        ("⇤ Left", Align::Left),
+                        ("Centre", Align::CentreX),
+                        ("Right ⇥", Align::Right),
+                    ] {
+                        if ui.button(label).clicked() {
+                            align = Some(value);
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    for (label, value) in [
+                        ("⤒ Top", Align::Top),
+                        ("Middle", Align::Middle),
+                        ("Bottom ⤓", Align::Bottom),
+                    ] {
+                        if ui.button(label).clicked() {
+                            align = Some(value);
+                        }
+                    }
+                });
+            });
+            ui.add_enabled_ui(count >= 3, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Distribute horizontally").clicked() {
+                        distribute = Some(0);
+                    }
+                    if ui.button("Distribute vertically").clicked() {
+                        distribute = Some(1);
+                    }
+                });
+            });
+        });
+
+    if align.is_none() && distribute.is_none() {
+        return Ok(());
+    }
+
+    // locked modules stay where they are
+    let mut items: Vec<(Entity, Rect)> = selection
+        .0
+        .iter()
+        .filter_map(|entity| {
+            let (transform, mw, anchor, layer, _) = modules.get(*entity).ok()?;
+            (!layer.locked).then(|| {
+                (
+                    *entity,
+                    world_bounds(transform, Vec2::new(mw.width, mw.height), anchor),
+                )
+            })
+        })
+        .collect();
+    if items.len() < 2 {
+        return Ok(());
+    }
+    let all = items
+        .iter()
+        .fold(items[0].1, |all, (_, bounds)| all.union(*bounds));
+
+    let mut offsets: Vec<(Entity, Vec2)> = vec![];
+    if let Some(align) = align {
+        for (entity, bounds) in items.iter() {
+            let offset = match align {
+                Align::Left => Vec2::new(all.min.x - bounds.min.x, 0.0),
+                Align::CentreX => Vec2::new(all.center().x - bounds.center().x, 0.0),
+                Align::Right => Vec2::new(all.max.x - bounds.max.x, 0.0),
+                Align::Top => Vec2::new(0.0, all.max.y - bounds.max.y),
+                Align::Middle => Vec2::new(0.0, all.center().y - bounds.center().y),
+                Align::Bottom => Vec2::new(0.0, all.min.y - bounds.min.y),
+            };
+            offsets.push((*entity, offset));
+        }
+    }
+    if let Some(axis) = distribute {
+        // equal gaps between the modules, the outermost ones stay in place
+        items.sort_by(|a, b| a.1.center()[axis].total_cmp(&b.1.center()[axis]));
+        let total: f32 = items.iter().map(|(_, bounds)| bounds.size()[axis]).sum();
+        let gap = (all.size()[axis] - total) / (items.len() - 1) as f32;
+        let mut position = all.min[axis];
+        for (entity, bounds) in items.iter() {
+            let mut offset = Vec2::ZERO;
+            offset[axis] = position - bounds.min[axis];
+            offsets.push((*entity, offset));
+            position += bounds.size()[axis] + gap;
+        }
+    }
+
+    for (entity, offset) in offsets {
+        if let Ok((mut transform, mw, anchor, _, id)) = modules.get_mut(entity) {
+            history.touch(
+                *id,
+                ModuleState::Placement(Placement::new(&transform, anchor, mw)),
+            );
+            transform.translation += offset.extend(0.0);
+        }
+    }
+    Ok(())
+}
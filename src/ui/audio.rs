@@ -0,0 +1,219 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::audio::{AUDIO_DIR, AudioAnalysis, AudioBinding, AudioFeature, AudioTrack, BANDS};
+use crate::common::ModuleWin;
+use crate::module::{ModuleId, Selection, asset_files};
+use crate::params::ModuleParams;
+use crate::playback::Playback;
+
+/// The binding being set up for the selected module
+pub struct NewBinding {
+    param: String,
+    feature: AudioFeature,
+}
+
+impl Default for NewBinding {
+    fn default() -> Self {
+        NewBinding {
+            param: String::new(),
+            feature: AudioFeature::Rms,
+        }
+    }
+}
+
+/// Picking the audio track, its features at the current frame and binding them to parameters
+#[allow(clippy::too_many_arguments)]
+pub fn ui_audio(
+    mut contexts: EguiContexts,
+    mut track: ResMut<AudioTrack>,
+    analysis: Res<AudioAnalysis>,
+    playback: Res<Playback>,
+    selection: Res<Selection>,
+    modules: Query<(&ModuleId, &Name, &ModuleParams), With<ModuleWin>>,
+    mut files: Local<Option<Vec<String>>>,
+    mut new: Local<NewBinding>,
+) -> Result {
+    egui::Window::new("Audio")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            let files = files.get_or_insert_with(|| asset_files(AUDIO_DIR, &["wav", "ogg"]));
+            ui.horizontal(|ui| {
+                let mut selected = track.file.clone();
+                egui::ComboBox::from_label("track")
+                    .selected_text(selected.as_deref().unwrap_or("none"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut selected, None, "none");
+                        for file in files.iter() {
+                            ui.selectable_value(&mut selected, Some(file.clone()), file);
+                        }
+                    });
+                if selected != track.file {
+                    track.file = selected;
+                }
+                if ui
+                    .small_button("⟳")
+                    .on_hover_text("look for files again")
+                    .clicked()
+                {
+                    *files = asset_files(AUDIO_DIR, &["wav", "ogg"]);
+                }
+            });
+            if files.is_empty() {
+                ui.weak(format!("Put WAV or OGG files in assets/{AUDIO_DIR}"));
+            }
+
+            match (&analysis.error, &analysis.features) {
+                (Some(err), _) => {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+                (None, Some(features)) => {
+                    let beats = features.frames.iter().filter(|f| f.beat).count();
+                    ui.label(format!(
+                        "{:.1} s, {} frames at {} fps, {beats} beats",
+                        analysis.duration,
+                        features.frames.len(),
+                        features.frame_rate
+                    ));
+                }
+                (None, None) if analysis.is_analysing() => {
+                    ui.label("analysing...");
+                }
+                (None, None) if track.file.is_some() => {
+                    ui.label("loading...");
+                }
+                (None, None) => {}
+            }
+
+            if let Some(features) = &analysis.features {
+                let frame = playback.frame();
+                let levels = analysis.levels(frame);
+                for (label, level) in [("rms", levels.x), ("onset", levels.y), ("beat", levels.z)] {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(egui::ProgressBar::new(level).desired_height(8.0));
+                    });
+                }
+                let bands = features
+                    .frame(frame)
+                    .map(|f| f.bands)
+                    .unwrap_or([0.0; BANDS]);
+                spectrum_ui(ui, &bands);
+            }
+
+            ui.separator();
+            let selected = selection
+                .active()
+                .and_then(|entity| modules.get(entity).ok());
+            match selected {
+                Some((id, name, params)) => {
+                    ui.label(format!("Bind to {name}"));
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_id_salt("audio bind param")
+                            .selected_text(new.param.as_str())
+                            .show_ui(ui, |ui| {
+                                for param in params.0.iter() {
+                                    ui.selectable_value(
+                                        &mut new.param,
+                                        param.name.clone(),
+                                        &param.name,
+                                    );
+                                }
+                            });
+                        feature_ui(ui, "audio bind feature", &mut new.feature);
+                        let param = params.0.iter().find(|p| p.name == new.param);
+                        if ui
+                            .add_enabled(param.is_some(), egui::Button::new("Bind"))
+                            .clicked()
+                            && let Some(param) = param
+                        {
+                            track.bindings.push(AudioBinding {
+                                feature: new.feature,
+                                module: *id,
+                                param: param.name.clone(),
+                                min: *param.range.start(),
+                                max: *param.range.end(),
+                            });
+                        }
+                    });
+                }
+                None => {
+                    ui.label("Select a module to bind its parameters");
+                }
+            }
+
+            let mut removed = None;
+            egui::CollapsingHeader::new(format!("Bindings ({})", track.bindings.len()))
+                .id_salt("audio bindings")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("audio_bindings_grid")
+                        .num_columns(4)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (i, binding) in track.bindings.iter_mut().enumerate() {
+                                let module = modules
+                                    .iter()
+                                    .find(|(id, _, _)| **id == binding.module)
+                                    .map(|(_, name, _)| name.to_string())
+                                    .unwrap_or_else(|| "missing module".to_string());
+                                feature_ui(ui, ("audio feature", i), &mut binding.feature);
+                                ui.label(format!("→ {module} {}", binding.param));
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::DragValue::new(&mut binding.min).speed(0.01));
+                                    ui.label("to");
+                                    ui.add(egui::DragValue::new(&mut binding.max).speed(0.01));
+                                });
+                                if ui.small_button("x").clicked() {
+                                    removed = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+            if let Some(i) = removed {
+                track.bindings.remove(i);
+            }
+        });
+    Ok(())
+}
+
+fn feature_ui(ui: &mut egui::Ui, id: impl std::hash::Hash, feature: &mut AudioFeature) {
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_salt(id)
+            .selected_text(match *feature {
+                AudioFeature::Band(_) => "band".to_string(),
+                other => other.to_string(),
+            })
+            .show_ui(ui, |ui| {
+                for option in [AudioFeature::Rms, AudioFeature::Onset, AudioFeature::Beat] {
+                    ui.selectable_value(&mut *feature, option, option.to_string());
+                }
+                if ui
+                    .selectable_label(matches!(feature, AudioFeature::Band(_)), "band")
+                    .clicked()
+                {
+                    *feature = AudioFeature::Band(0);
+                }
+            });
+        if let AudioFeature::Band(band) = feature {
+            ui.add(egui::DragValue::new(band).range(0..=BANDS as u8 - 1));
+        }
+    });
+}
+
+fn spectrum_ui(ui: &mut egui::Ui, bands: &[f32; BANDS]) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(192.0, 48.0), egui::Sense::hover());
+    ui.painter()
+        .rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+    let width = rect.width() / BANDS as f32;
+    for (i, level) in bands.iter().enumerate() {
+        let x = rect.left() + i as f32 * width;
+        let top = rect.bottom() - level * rect.height();
+        ui.painter().rect_filled(
+            egui::Rect::from_x_y_ranges(x + 1.0..=x + width - 1.0, top..=rect.bottom()),
+            0.0,
+            ui.visuals().selection.bg_fill,
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use crate::common::{Immortal, ModuleWin};
+use crate::history::{History, ModuleState, Placement};
+use crate::module::{ModuleAnchor, ModuleId, ModuleLayer, ResizeModule, Selection};
+
+use super::ParamsWindowOpen;
+use super::arrange::{GUIDE_PICK_DISTANCE, Guides, SnapLines, SnapSettings, SnapTargets};
+
+pub const MIN_MODULE_SIZE: f32 = 16.0;
+
+/// Radius around a handle that picks it, in screen pixels so handles keep their size at any zoom
+const HANDLE_RADIUS: f32 = 8.0;
+/// Distance of the rotation handle above the top edge, in screen pixels
+const ROTATE_HANDLE_OFFSET: f32 = 28.0;
+/// Rotation snaps to this many degrees while shift is held
+const ROTATE_SNAP_DEGREES: f32 = 15.0;
+const DOUBLE_CLICK_SECONDS: f64 = 0.3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum CanvasHandle {
+    Move,
+    /// Resizes from the corner in the direction of the sign vector, scales while shift is held
+    Corner(Vec2),
+    Rotate,
+    Anchor,
+}
+
+/// The module being dragged and its state when the drag started
+pub struct CanvasDrag {
+    entity: Entity,
+    handle: CanvasHandle,
+    start_pointer: Vec2,
+    start_transform: Transform,
+    start_size: Vec2,
+    start_anchor: ModuleAnchor,
+    start_bounds: Rect,
+    /// Every unlocked selected module and where it was, moved along with the dragged one
+    group: Vec<(Entity, Vec3)>,
+    targets: SnapTargets,
+}
+
+/// Where the handles of a module are on the canvas
+struct ModuleHandles {
+    corners: [(Vec2, Vec2); 4],
+    top: Vec2,
+    rotate: Vec2,
+    anchor: Vec2,
+    /// [`HANDLE_RADIUS`] in canvas pixels
+    radius: f32,
+}
+
+impl ModuleHandles {
+    /// `screen_pixel` is the size of a screen pixel on the canvas
+    fn new(transform: &Transform, size: Vec2, anchor: &ModuleAnchor, screen_pixel: f32) -> Self {
+        let rect = anchor.local_rect(size);
+        let world = |local: Vec2| transform.transform_point(local.extend(0.0)).truncate();
+        let corner = |sign: Vec2| (sign, world(rect.center() + sign * rect.half_size()));
+        let top = world(Vec2::new(rect.center().x, rect.max.y));
+        let up = (transform.rotation * Vec3::Y).truncate();
+
+        ModuleHandles {
+            corners: [
+                corner(Vec2::new(-1.0, -1.0)),
+                corner(Vec2::new(1.0, -1.0)),
+                corner(Vec2::new(1.0, 1.0)),
+                corner(Vec2::new(-1.0, 1.0)),
+            ],
+            top,
+            rotate: top + up * ROTATE_HANDLE_OFFSET * screen_pixel,
+            anchor: transform.translation.truncate(),
+            radius: HANDLE_RADIUS * screen_pixel,
+        }
+    }
+
+    fn pick(&self, pointer: Vec2) -> Option<CanvasHandle> {
+        let near = |p: Vec2| p.distance(pointer) <= self.radius;
+        if near(self.anchor) {
+            Some(CanvasHandle::Anchor)
+        } else if near(self.rotate) {
+            Some(CanvasHandle::Rotate)
+        } else {
+            self.corners
+                .iter()
+                .find(|(_, p)| near(*p))
+                .map(|(sign, _)| CanvasHandle::Corner(*sign))
+        }
+    }
+}
+
+fn contains(transform: &Transform, size: Vec2, anchor: &ModuleAnchor, pointer: Vec2) -> bool {
+    let local = transform
+        .compute_affine()
+        .inverse()
+        .transform_point3(pointer.extend(0.0))
+        .truncate();
+    anchor.local_rect(size).contains(local)
+}
+
+/// The axis aligned box around a module on the canvas
+pub fn world_bounds(transform: &Transform, size: Vec2, anchor: &ModuleAnchor) -> Rect {
+    let corners = ModuleHandles::new(transform, size, anchor, 1.0).corners;
+    corners.iter().fold(
+        Rect::from_center_size(corners[0].1, Vec2::ZERO),
+        |bounds, (_, p)| bounds.union_point(*p),
+    )
+}
+
+/// Selects, moves, resizes, scales and rotates modules by dragging on the canvas.
+/// Shift clicking adds to the selection and dragging one of the selected modules moves all of them.
+/// Moves and resizes snap to the targets in [`SnapSettings`] unless alt is held.
+/// Clicking empty canvas deselects, double clicking a module opens its parameters.
+#[allow(clippy::too_many_arguments)]
+pub fn canvas_input(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    window: Single<&Window>,
+    camera: Single<(&Camera, &GlobalTransform), With<Immortal>>,
+    mut selection: ResMut<Selection>,
+    mut history: ResMut<History>,
+    (settings, mut guides, mut snap_lines): (Res<SnapSettings>, ResMut<Guides>, ResMut<SnapLines>),
+    mut modules: Query<(
+        Entity,
+        &mut Transform,
+        &mut ModuleWin,
+        &mut ModuleAnchor,
+        &ModuleLayer,
+        &ModuleId,
+    )>,
+    mut drag: Local<Option<CanvasDrag>>,
+    mut last_click: Local<Option<(Entity, f64)>>,
+) -> Result {
+    if mouse.just_released(MouseButton::Left) {
+        *drag = None;
+        *snap_lines = SnapLines::default();
+    }
+    // the whole drag is one step of the history
+    if drag.is_some() {
+        history.hold();
+    }
+
+    let (camera, camera_transform) = *camera;
+    let Some((cursor, pointer)) = window.cursor_position().and_then(|cursor| {
+        let pointer = camera.viewport_to_world_2d(camera_transform, cursor).ok()?;
+        Some((cursor, pointer))
+    }) else {
+        return Ok(());
+    };
+    // canvas pixels per screen pixel at the current zoom
+    let screen_pixel = camera
+        .viewport_to_world_2d(camera_transform, cursor + Vec2::X)
+        .map_or(1.0, |next| next.distance(pointer));
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let alt = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+
+    if mouse.just_pressed(MouseButton::Left) && !contexts.ctx_mut()?.is_pointer_over_area() {
+        let any_solo = modules.iter().any(|(.., layer, _)| layer.solo);
+
+        // the handles of the active module go before guides and the modules underneath them
+        let handle = selection.active().and_then(|entity| {
+            let (_, transform, mw, anchor, layer, _) = modules.get(entity).ok()?;
+            if layer.locked || !layer.is_shown(any_solo) {
+                return None;
+            }
+            let size = Vec2::new(mw.width, mw.height);
+            let handle = ModuleHandles::new(transform, size, anchor, screen_pixel).pick(pointer)?;
+            Some((entity, handle))
+        });
+        if handle.is_none()
+            && let Some(guide) = guides.pick(pointer, GUIDE_PICK_DISTANCE * screen_pixel)
+        {
+            guides.dragging = Some(guide);
+            return Ok(());
+        }
+
+        let picked = handle.or_else(|| {
+            let mut hits: Vec<_> = modules
+                .iter()
+                .filter(|(_, transform, mw, anchor, layer, _)| {
+                    layer.is_shown(any_solo)
+                        && contains(transform, Vec2::new(mw.width, mw.height), anchor, pointer)
+                })
+                .map(|(entity, .., layer, _)| (entity, layer.order))
+                .collect();
+            hits.sort_by_key(|(_, order)| std::cmp::Reverse(*order));
+            hits.first()
+                .map(|(entity, _)| (*entity, CanvasHandle::Move))
+        });
+
+        match picked {
+            Some((entity, _)) if shift => selection.toggle(entity),
+            Some((entity, handle)) => {
+                // clicking a selected module keeps the others selected so they can be dragged along
+                if selection.contains(entity) {
+                    selection.activate(entity);
+                } else {
+                    selection.select(entity);
+                }
+
+                let now = time.elapsed_secs_f64();
+                if let Some((clicked, at)) = *last_click
+                    && clicked == entity
+                    && now - at < DOUBLE_CLICK_SECONDS
+                {
+                    commands.entity(entity).insert(ParamsWindowOpen);
+                }
+                *last_click = Some((entity, now));
+
+                let group: Vec<(Entity, Vec3)> = match handle {
+                    CanvasHandle::Move => selection
+                        .0
+                        .iter()
+                        .filter_map(|e| {
+                            let (_, transform, .., layer, _) = modules.get(*e).ok()?;
+                            (!layer.locked).then_some((*e, transform.translation))
+                        })
+                        .collect(),
+                    _ => vec![(entity, Vec3::ZERO)],
+                };
+                let others = modules
+                    .iter()
+                    .filter(|(e, .., layer, _)| {
+                        layer.is_shown(any_solo) && !group.iter().any(|(g, _)| g == e)
+                    })
+                    .map(|(_, transform, mw, anchor, ..)| {
+                        world_bounds(transform, Vec2::new(mw.width, mw.height), anchor)
+                    });
+                let targets = SnapTargets::new(&settings, &guides, others);
+
+                for (e, _) in group.iter() {
+                    if let Ok((_, transform, mw, anchor, _, id)) = modules.get(*e) {
+                        let before = Placement::new(transform, anchor, mw);
+                        history.touch(*id, ModuleState::Placement(before));
+                    }
+                }
+
+                let (_, transform, mw, anchor, layer, _) = modules.get(entity)?;
+                if !layer.locked {
+                    history.hold();
+                    let size = Vec2::new(mw.width, mw.height);
+                    *drag = Some(CanvasDrag {
+                        entity,
+                        handle,
+                        start_pointer: pointer,
+                        start_transform: *transform,
+                        start_size: size,
+                        start_anchor: *anchor,
+                        start_bounds: world_bounds(transform, size, anchor),
+                        group,
+                        targets,
+                    });
+                }
+            }
+            None => selection.clear(),
+        }
+    }
+
+    let Some(drag) = drag.as_ref() else {
+        return Ok(());
+    };
+
+    if drag.handle == CanvasHandle::Move {
+        let mut offset = pointer - drag.start_pointer;
+        if !alt {
+            let bounds = Rect::from_center_size(
+                drag.start_bounds.center() + offset,
+                drag.start_bounds.size(),
+            );
+            let (snap, lines) = drag.targets.snap_rect(bounds);
+            offset += snap;
+            *snap_lines = lines;
+        }
+        for (entity, start) in drag.group.iter() {
+            if let Ok((_, mut transform, ..)) = modules.get_mut(*entity) {
+                transform.translation = (start.truncate() + offset).extend(start.z);
+            }
+        }
+        return Ok(());
+    }
+
+    // resized corners and the anchor snap to the lines the pointer is close to
+    let mut pointer = pointer;
+    if !alt && !shift && matches!(drag.handle, CanvasHandle::Corner(_)) {
+        let (snap, lines) = drag.targets.snap_point(pointer);
+        pointer += snap;
+        *snap_lines = lines;
+    }
+
+    let Ok((entity, mut transform, mut mw, mut anchor, ..)) = modules.get_mut(drag.entity) else {
+        return Ok(());
+    };
+
+    let start = &drag.start_transform;
+    let to_local = |world: Vec2| {
+        start
+            .compute_affine()
+            .inverse()
+            .transform_point3(world.extend(0.0))
+            .truncate()
+    };
+    let to_world = |local: Vec2| start.transform_point(local.extend(0.0)).truncate();
+    let z = transform.translation.z;
+
+    match drag.handle {
+        CanvasHandle::Move => {}
+        CanvasHandle::Corner(_) if shift => {
+            // uniform scale around the anchor
+            let factor =
+                to_local(pointer).length() / to_local(drag.start_pointer).length().max(1.0);
+            transform.scale = (start.scale.truncate() * factor.max(0.01)).extend(1.0);
+        }
+        CanvasHandle::Corner(sign) => {
+            // the opposite corner stays in place, the anchor keeps its relative position
+            let rect = drag.start_anchor.local_rect(drag.start_size);
+            let opposite = rect.center() - sign * rect.half_size();
+            let size = ((to_local(pointer) - opposite) * sign)
+                .max(Vec2::splat(MIN_MODULE_SIZE))
+                .round();
+            let min = opposite.min(opposite + sign * size);
+            let origin = min + (drag.start_anchor.0 + 0.5) * size;
+            transform.translation = to_world(origin).extend(z);
+
+            if size != Vec2::new(mw.width, mw.height) {
+                mw.width = size.x;
+                mw.height = size.y;
+                commands.trigger(ResizeModule {
+                    entity,
+                    width: size.x,
+                    height: size.y,
+                });
+            }
+        }
+        CanvasHandle::Rotate => {
+            let origin = start.translation.truncate();
+            let delta = (pointer - origin).to_angle() - (drag.start_pointer - origin).to_angle();
+            let mut angle = start.rotation.to_euler(EulerRot::XYZ).2 + delta;
+            if shift {
+                let snap = ROTATE_SNAP_DEGREES.to_radians();
+                angle = (angle / snap).round() * snap;
+            }
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+        CanvasHandle::Anchor => {
+            // the module stays where it is, only the point it transforms around moves
+            let rect = drag.start_anchor.local_rect(drag.start_size);
+            let local = to_local(pointer).clamp(rect.min, rect.max);
+            anchor.0 = (local - rect.min) / drag.start_size - 0.5;
+            transform.translation = to_world(local).extend(z);
+        }
+    }
+    Ok(())
+}
+
+/// Outlines every visible module and draws the handles of the active one
+pub fn draw_module_gizmos(
+    mut gizmos: Gizmos,
+    selection: Res<Selection>,
+    camera: Single<(&Camera, &GlobalTransform), With<Immortal>>,
+    modules: Query<(Entity, &Transform, &ModuleWin, &ModuleAnchor, &ModuleLayer)>,
+) {
+    let any_solo = modules.iter().any(|(.., layer)| layer.solo);
+    let (camera, camera_transform) = *camera;
+    // canvas pixels per screen pixel at the current zoom
+    let screen_pixel = match (
+        camera.viewport_to_world_2d(camera_transform, Vec2::ZERO),
+        camera.viewport_to_world_2d(camera_transform, Vec2::X),
+    ) {
+        (Ok(origin), Ok(next)) => next.distance(origin),
+        _ => 1.0,
+    };
+
+    for (entity, transform, mw, anchor, layer) in modules.iter() {
+        if !layer.is_shown(any_solo) {
+            continue;
+        }
+        let size = Vec2::new(mw.width, mw.height);
+        let handles = ModuleHandles::new(transform, size, anchor, screen_pixel);
+        let is_selected = selection.contains(entity);
+
+        let color = match (is_selected, layer.locked) {
+            (true, true) => Color::srgb(1.0, 0.4, 0.3),
+            (true, false) => Color::srgb(1.0, 0.8, 0.2),
+            (false, _) => Color::srgba(1.0, 1.0, 1.0, 0.25),
+        };
+        let corners = handles.corners.map(|(_, p)| p);
+        gizmos.linestrip_2d(
+            [corners[0], corners[1], corners[2], corners[3], corners[0]],
+            color,
+        );
+
+        if selection.active() != Some(entity) || layer.locked {
+            continue;
+        }
+        for corner in corners {
+            gizmos.rect_2d(
+                Isometry2d::new(corner, transform.rotation.to_euler(EulerRot::XYZ).2.into()),
+                Vec2::splat(handles.radius),
+                color,
+            );
+        }
+        gizmos.line_2d(handles.top, handles.rotate, color);
+        gizmos.circle_2d(handles.rotate, handles.radius / 2.0, color);
+        gizmos.circle_2d(handles.anchor, handles.radius / 2.0, color);
+        gizmos.cross_2d(handles.anchor, handles.radius, color);
+    }
+}
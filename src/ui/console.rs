@@ -0,0 +1,122 @@
+use bevy::log::Level;
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::console::{LogBuffer, LogLine};
+
+const LEVELS: [Level; 5] = [
+    Level::ERROR,
+    Level::WARN,
+    Level::INFO,
+    Level::DEBUG,
+    Level::TRACE,
+];
+
+/// What the console shows, kept between frames
+pub struct ConsoleFilter {
+    /// The most verbose level shown
+    level: Level,
+    search: String,
+}
+
+impl Default for ConsoleFilter {
+    fn default() -> Self {
+        ConsoleFilter {
+            level: Level::DEBUG,
+            search: String::new(),
+        }
+    }
+}
+
+impl ConsoleFilter {
+    fn shows(&self, line: &LogLine) -> bool {
+        let search = self.search.to_lowercase();
+        line.level <= self.level
+            && (search.is_empty()
+                || line.message.to_lowercase().contains(&search)
+                || line.target.to_lowercase().contains(&search))
+    }
+}
+
+fn level_color(level: Level) -> egui::Color32 {
+    match level {
+        Level::ERROR => egui::Color32::from_rgb(255, 90, 80),
+        Level::WARN => egui::Color32::from_rgb(255, 200, 60),
+        Level::INFO => egui::Color32::from_rgb(140, 200, 255),
+        _ => egui::Color32::GRAY,
+    }
+}
+
+/// The log lines with level filtering and search, copyable as text
+pub fn ui_console(
+    mut contexts: EguiContexts,
+    buffer: Res<LogBuffer>,
+    mut filter: Local<ConsoleFilter>,
+) -> Result {
+    egui::Window::new("Log")
+        .default_open(false)
+        .default_size([640.0, 260.0])
+        .show(contexts.ctx_mut()?, |ui| {
+            let mut copy = false;
+            let mut clear = false;
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_salt("log_level")
+                    .selected_text(filter.level.as_str())
+                    .show_ui(ui, |ui| {
+                        for level in LEVELS {
+                            ui.selectable_value(&mut filter.level, level, level.as_str());
+                        }
+                    });
+                ui.add(egui::TextEdit::singleline(&mut filter.search).hint_text("search"));
+                copy = ui.button("Copy").clicked();
+                clear = ui.button("Clear").clicked();
+            });
+            ui.separator();
+
+            // the lock is only held briefly, anything logged while drawing would wait on it
+            let shown: Vec<LogLine> = match buffer.0.lock() {
+                Ok(mut lines) if clear => {
+                    lines.clear();
+                    vec![]
+                }
+                Ok(lines) => lines
+                    .iter()
+                    .filter(|line| filter.shows(line))
+                    .cloned()
+                    .collect(),
+                Err(_) => vec![],
+            };
+            if copy {
+                let text: Vec<String> = shown
+                    .iter()
+                    .map(|line| {
+                        format!(
+                            "{:>8.3} {:5} {}: {}",
+                            line.time, line.level, line.target, line.message
+                        )
+                    })
+                    .collect();
+                ui.ctx().copy_text(text.join("\n"));
+            }
+
+            let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+            egui::ScrollArea::both()
+                .auto_shrink(false)
+                .stick_to_bottom(true)
+                .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                    for line in &shown[rows] {
+                        ui.horizontal(|ui| {
+                            ui.monospace(format!("{:>8.3}", line.time));
+                            ui.label(
+                                egui::RichText::new(format!("{:5}", line.level.as_str()))
+                                    .monospace()
+                                    .color(level_color(line.level)),
+                            );
+                            ui.label(egui::RichText::new(&line.target).monospace().weak());
+                            ui.monospace(&line.message);
+                        });
+                    }
+                });
+        });
+    Ok(())
+}
@@ -0,0 +1,346 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::common::ModuleWin;
+use crate::export::{
+    CaptureFrame, CaptureModuleFrame, CaptureRange, ExportVideo, ExportWeb, FrameCapture,
+    GifDither, GifPalette, Snapshots, VideoCodec,
+};
+use crate::module::Selection;
+use crate::playback::Playback;
+
+pub struct WebExportSettings {
+    dir: String,
+    autoplay: bool,
+    loop_duration: Option<f32>,
+}
+
+impl Default for WebExportSettings {
+    fn default() -> Self {
+        WebExportSettings {
+            dir: "export/web".to_string(),
+            autoplay: true,
+            loop_duration: None,
+        }
+    }
+}
+
+/// Publishes the composition as a standalone web player
+pub fn ui_export(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut settings: Local<WebExportSettings>,
+) -> Result {
+    egui::Window::new("Export for web")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("directory");
+                ui.text_edit_singleline(&mut settings.dir);
+            });
+            ui.checkbox(&mut settings.autoplay, "autoplay");
+            ui.horizontal(|ui| {
+                let mut looping = settings.loop_duration.is_some();
+                if ui.checkbox(&mut looping, "loop").changed() {
+                    settings.loop_duration = looping.then_some(10.0);
+                }
+                if let Some(duration) = settings.loop_duration.as_mut() {
+                    ui.add(
+                        egui::DragValue::new(duration)
+                            .range(0.1..=3600.0)
+                            .speed(0.05)
+                            .suffix(" s"),
+                    );
+                }
+            });
+            if ui.button("Export").clicked() {
+                commands.trigger(ExportWeb {
+                    dir: settings.dir.clone().into(),
+                    autoplay: settings.autoplay,
+                    loop_duration: settings.loop_duration,
+                });
+            }
+        });
+    Ok(())
+}
+
+pub struct VideoExportSettings {
+    path: String,
+    codec: VideoCodec,
+    bitrate: u32,
+    palette: GifPalette,
+    quality: u8,
+    range: CaptureRange,
+}
+
+impl Default for VideoExportSettings {
+    fn default() -> Self {
+        VideoExportSettings {
+            path: "export/video.mp4".to_string(),
+            codec: VideoCodec::default(),
+            bitrate: 8000,
+            palette: GifPalette::default(),
+            quality: 80,
+            range: CaptureRange {
+                width: 1920,
+                height: 1080,
+                frame_rate: 30.0,
+                start: 0.0,
+                end: 10.0,
+                transparent: false,
+            },
+        }
+    }
+}
+
+/// Renders a range of the composition into a video file, with the progress of a running export
+pub fn ui_export_video(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut capture: ResMut<FrameCapture>,
+    playback: Res<Playback>,
+    mut settings: Local<VideoExportSettings>,
+) -> Result {
+    egui::Window::new("Export video")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            if let Some((done, total)) = capture.progress() {
+                ui.add(
+                    egui::ProgressBar::new(done as f32 / total as f32)
+                        .text(format!("frame {done} of {total}")),
+                );
+                if ui.button("Cancel").clicked() {
+                    capture.cancel();
+                }
+                return;
+            }
+            if capture.is_finishing() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("finishing the file");
+                });
+                if ui.button("Cancel").clicked() {
+                    capture.cancel();
+                }
+                return;
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("file");
+                ui.text_edit_singleline(&mut settings.path);
+            });
+            egui::Grid::new("video_export_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("codec");
+                    let mut codec = settings.codec;
+                    egui::ComboBox::from_id_salt("video codec")
+                        .selected_text(codec.to_string())
+                        .show_ui(ui, |ui| {
+                            for option in VideoCodec::ALL {
+                                ui.selectable_value(&mut codec, option, option.to_string());
+                            }
+                        });
+                    if codec != settings.codec {
+                        settings.codec = codec;
+                        let path =
+                            std::path::Path::new(&settings.path).with_extension(codec.extension());
+                        settings.path = path.to_string_lossy().to_string();
+                    }
+                    ui.end_row();
+
+                    if settings.codec.has_bitrate() {
+                        ui.label("bitrate");
+                        ui.add(
+                            egui::DragValue::new(&mut settings.bitrate)
+                                .range(100..=200_000)
+                                .suffix(" kbit/s"),
+                        );
+                        ui.end_row();
+                    }
+                    if settings.codec == VideoCodec::Gif {
+                        ui.label("colours");
+                        ui.add(egui::DragValue::new(&mut settings.palette.colors).range(2..=256));
+                        ui.end_row();
+
+                        ui.label("dither");
+                        egui::ComboBox::from_id_salt("gif dither")
+                            .selected_text(format!("{:?}", settings.palette.dither))
+                            .show_ui(ui, |ui| {
+                                for option in GifDither::ALL {
+                                    ui.selectable_value(
+                                        &mut settings.palette.dither,
+                                        option,
+                                        format!("{option:?}"),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
+                    if settings.codec == VideoCodec::WebP {
+                        ui.label("quality");
+                        ui.add(egui::Slider::new(&mut settings.quality, 0..=100));
+                        ui.end_row();
+                    }
+
+                    ui.label("background");
+                    ui.add_enabled(
+                        settings.codec.keeps_alpha(),
+                        egui::Checkbox::new(&mut settings.range.transparent, "transparent"),
+                    )
+                    .on_disabled_hover_text("needs VP9, ProRes, WebP, GIF or a PNG sequence");
+                    ui.end_row();
+
+                    let range = &mut settings.range;
+                    ui.label("size");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut range.width).range(16..=7680));
+                        ui.add(egui::DragValue::new(&mut range.height).range(16..=4320));
+                    });
+                    ui.end_row();
+
+                    ui.label("frame rate");
+                    ui.add(
+                        egui::DragValue::new(&mut range.frame_rate)
+                            .range(1.0..=240.0)
+                            .suffix(" fps"),
+                    );
+                    ui.end_row();
+
+                    ui.label("range");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut range.start)
+                                .range(0.0..=range.end)
+                                .speed(0.05)
+                                .suffix(" s"),
+                        );
+                        ui.label("to");
+                        ui.add(
+                            egui::DragValue::new(&mut range.end)
+                                .range(range.start..=36000.0)
+                                .speed(0.05)
+                                .suffix(" s"),
+                        );
+                        if let Some(duration) = playback.loop_duration
+                            && ui
+                                .small_button("loop")
+                                .on_hover_text("the whole playback loop")
+                                .clicked()
+                        {
+                            range.start = 0.0;
+                            range.end = duration;
+                        }
+                    });
+                    ui.end_row();
+                });
+            ui.label(format!("{} frames", settings.range.frames()));
+
+            if ui.button("Export").clicked() {
+                let mut range = settings.range.clone();
+                range.transparent &= settings.codec.keeps_alpha();
+                commands.trigger(ExportVideo {
+                    path: settings.path.clone().into(),
+                    codec: settings.codec,
+                    bitrate: settings.bitrate,
+                    palette: settings.palette.clone(),
+                    quality: settings.quality,
+                    range,
+                });
+            }
+            match &capture.result {
+                Some(Ok(done)) => {
+                    ui.label(format!("exported {done}"));
+                }
+                Some(Err(err)) => {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+                None => {}
+            }
+            if let Some(seam) = capture.seam
+                && settings.codec.loops()
+                && capture.result.as_ref().is_some_and(|result| result.is_ok())
+            {
+                if seam.is_perfect() {
+                    ui.label("loops seamlessly");
+                } else {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!(
+                            "not a perfect loop: the last frame jumps {:.1}% back to the first, frames change {:.1}% on average",
+                            seam.jump * 100.0,
+                            seam.step * 100.0
+                        ),
+                    );
+                }
+            }
+        });
+    Ok(())
+}
+
+/// Saves the current frame of the composition or of the selected module as a PNG
+pub fn ui_capture_frame(
+    mut contexts: EguiContexts,
+    mut commands: Commands,
+    mut snapshots: ResMut<Snapshots>,
+    selection: Res<Selection>,
+    modules: Query<&Name, With<ModuleWin>>,
+) -> Result {
+    egui::Window::new("Capture frame")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            egui::Grid::new("capture_frame_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("directory");
+                    let mut dir = snapshots.dir.to_string_lossy().to_string();
+                    if ui.text_edit_singleline(&mut dir).changed() {
+                        snapshots.dir = dir.into();
+                    }
+                    ui.end_row();
+
+                    ui.label("size");
+                    ui.horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut snapshots.size.x).range(16..=7680));
+                        ui.add(egui::DragValue::new(&mut snapshots.size.y).range(16..=4320));
+                    });
+                    ui.end_row();
+
+                    ui.label("background");
+                    ui.checkbox(&mut snapshots.transparent, "transparent");
+                    ui.end_row();
+                });
+
+            ui.horizontal(|ui| {
+                ui.label("capture");
+                if ui.button("composition").clicked() {
+                    commands.trigger(CaptureFrame);
+                }
+                let selected = selection
+                    .active()
+                    .and_then(|entity| modules.get(entity).ok().map(|name| (entity, name)));
+                if let Some((module, name)) = selected
+                    && ui
+                        .button(format!("{name}"))
+                        .on_hover_text("the module's own render target, at its resolution")
+                        .clicked()
+                {
+                    commands.trigger(CaptureModuleFrame { module });
+                }
+            });
+            match &snapshots.result {
+                Some(Ok(done)) => {
+                    ui.label(format!("captured {done}"));
+                }
+                Some(Err(err)) => {
+                    ui.colored_label(egui::Color32::RED, err);
+                }
+                None => {}
+            }
+            ui.weak("The project thumbnail is captured at this size on every save");
+            if let Some(Err(err)) = &snapshots.thumbnail {
+                ui.colored_label(egui::Color32::RED, format!("thumbnail: {err}"));
+            }
+        });
+    Ok(())
+}
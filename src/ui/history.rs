@@ -0,0 +1,53 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::history::History;
+
+/// Lists the undo stack followed by the undone entries, clicking an entry goes back or forward to it
+pub fn ui_history(mut contexts: EguiContexts, mut history: ResMut<History>) -> Result {
+    let mut steps = 0;
+    egui::Window::new("History")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!history.undo.is_empty(), egui::Button::new("⟲ Undo"))
+                    .clicked()
+                {
+                    steps = -1;
+                }
+                if ui
+                    .add_enabled(!history.redo.is_empty(), egui::Button::new("⟳ Redo"))
+                    .clicked()
+                {
+                    steps = 1;
+                }
+            });
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let undo_count = history.undo.len() as isize;
+                if ui.selectable_label(undo_count == 0, "Start").clicked() {
+                    steps = -undo_count;
+                }
+                for (i, entry) in history.undo.iter().enumerate() {
+                    let current = i as isize == undo_count - 1;
+                    if ui.selectable_label(current, &entry.label).clicked() {
+                        steps = i as isize + 1 - undo_count;
+                    }
+                }
+                // the redo stack is popped from the back, the next redo is its last entry
+                for (i, entry) in history.redo.iter().rev().enumerate() {
+                    let label = egui::RichText::new(&entry.label).weak();
+                    if ui.selectable_label(false, label).clicked() {
+                        steps = i as isize + 1;
+                    }
+                }
+            });
+        });
+
+    if steps != 0 {
+        history.requested_steps += steps;
+    }
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use bevy::camera::RenderTarget;
+use bevy::camera::visibility::RenderLayers;
+use bevy::ecs::observer::Observer;
+use bevy::prelude::*;
+use bevy::window::Monitor;
+use bevy_egui::{EguiContext, PrimaryEguiContext, egui};
+
+use crate::common::ModuleWin;
+use crate::module::{ModuleLayer, ModulePart, ModuleWithParts};
+use crate::pipeline::RenderTargetPool;
+
+/// Developer panel listing the entities of every module with their components and the render target memory.
+/// Reflected components show their values, parts also show the render layers and camera they use.
+pub fn ui_inspector(world: &mut World) -> Result {
+    let ctx = world
+        .query_filtered::<&mut EguiContext, With<PrimaryEguiContext>>()
+        .single_mut(world)?
+        .get_mut()
+        .clone();
+
+    let mut modules: Vec<(Entity, u32, String)> = world
+        .query_filtered::<(Entity, &ModuleLayer, &Name), With<ModuleWin>>()
+        .iter(world)
+        .map(|(entity, layer, name)| (entity, layer.order, name.to_string()))
+        .collect();
+    modules.sort_by_key(|(_, order, _)| std::cmp::Reverse(*order));
+
+    // everything that isn't a module, a part of one or engine bookkeeping
+    let mut others: Vec<Entity> = world
+        .query_filtered::<Entity, (
+            Without<ModuleWin>,
+            Without<ChildOf>,
+            Without<ModulePart>,
+            Without<Observer>,
+            Without<Monitor>,
+        )>()
+        .iter(world)
+        .collect();
+    others.sort();
+
+    let memory = world
+        .resource::<RenderTargetPool>()
+        .memory(world.resource::<Assets<Image>>());
+    let megabytes = |bytes: usize| bytes as f32 / (1024.0 * 1024.0);
+
+    let world = &*world;
+    egui::Window::new("Inspector")
+        .default_open(false)
+        .default_size([420.0, 480.0])
+        .show(&ctx, |ui| {
+            ui.label(format!(
+                "Render targets: {} in use ({:.1} MB), {} free ({:.1} MB)",
+                memory.leased_targets,
+                megabytes(memory.leased_bytes),
+                memory.free_targets,
+                megabytes(memory.free_bytes),
+            ));
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (root, _, name) in modules.iter() {
+                    egui::CollapsingHeader::new(format!("{name} ({root})"))
+                        .id_salt(root)
+                        .show(ui, |ui| {
+                            entity_ui(ui, world, *root, "root");
+                            for part in parts(world, *root) {
+                                entity_ui(ui, world, part, "part");
+                            }
+                        });
+                }
+                ui.separator();
+                egui::CollapsingHeader::new(format!("Other entities ({})", others.len()))
+                    .id_salt("other entities")
+                    .show(ui, |ui| {
+                        for entity in others.iter() {
+                            entity_ui(ui, world, *entity, "entity");
+                        }
+                    });
+            });
+        });
+    Ok(())
+}
+
+/// The parts of a module and the children of its root, like the sprite showing its render target
+fn parts(world: &World, root: Entity) -> Vec<Entity> {
+    let entity = world.entity(root);
+    let mut parts: Vec<Entity> = entity
+        .get::<ModuleWithParts>()
+        .map(|parts| parts.iter().collect())
+        .unwrap_or_default();
+    if let Some(children) = entity.get::<Children>() {
+        parts.extend(children.iter());
+    }
+    parts
+}
+
+fn entity_ui(ui: &mut egui::Ui, world: &World, entity: Entity, kind: &str) {
+    let Ok(entity_ref) = world.get_entity(entity) else {
+        return;
+    };
+    let title = match entity_ref.get::<Name>() {
+        Some(name) => format!("{kind} {entity} \"{name}\""),
+        None => format!("{kind} {entity}"),
+    };
+
+    egui::CollapsingHeader::new(title)
+        .id_salt(("inspect", entity))
+        .show(ui, |ui| {
+            if let Some(layers) = entity_ref.get::<RenderLayers>() {
+                let layers: Vec<String> = layers.iter().map(|layer| layer.to_string()).collect();
+                ui.label(format!("render layers: {}", layers.join(", ")));
+            }
+            if let Some(camera) = entity_ref.get::<Camera>() {
+                let target = match entity_ref.get::<RenderTarget>() {
+                    Some(RenderTarget::Image(image)) => format!("image {:?}", image.handle.id()),
+                    Some(target) => format!("{target:?}"),
+                    None => "window".to_string(),
+                };
+                ui.label(format!(
+                    "camera: order {}, active {}, target {target}",
+                    camera.order, camera.is_active
+                ));
+            }
+
+            let registry = world.resource::<AppTypeRegistry>().read();
+            let Ok(components) = world.inspect_entity(entity) else {
+                return;
+            };
+            for info in components {
+                let registration = info.type_id().and_then(|id| registry.get(id));
+                let name = match registration {
+                    Some(registration) => registration
+                        .type_info()
+                        .type_path_table()
+                        .short_path()
+                        .to_string(),
+                    None => info.name().shortname().to_string(),
+                };
+                let value = registration
+                    .and_then(|registration| registration.data::<ReflectComponent>())
+                    .and_then(|reflect| reflect.reflect(entity_ref));
+
+                match value {
+                    Some(value) => {
+                        egui::CollapsingHeader::new(name)
+                            .id_salt(("component", entity, info.id()))
+                            .show(ui, |ui| {
+                                ui.monospace(format!("{:#?}", value.as_partial_reflect()));
+                            });
+                    }
+                    None => {
+                        ui.label(egui::RichText::new(format!("{name} (not reflected)")).weak());
+                    }
+                }
+            }
+        });
+}
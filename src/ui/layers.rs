@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::common::ModuleWin;
+use crate::history::{History, ModuleState};
+use crate::module::{DespawnModule, ModuleId, ModuleLayer, RenameModule, Selection};
+
+use super::ParamsWindowOpen;
+
+/// Lists the modules from top to bottom with visibility, lock and solo toggles.
+/// Rows are dragged by their handle to change the draw order. Clicking a name selects the module, shift clicking adds it
+/// to the selection and double clicking renames it. Right clicking it opens the module's parameters or deletes it.
+pub fn ui_layers(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    // the module being renamed and the name typed so far
+    mut renaming: Local<Option<(Entity, String)>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut selection: ResMut<Selection>,
+    mut history: ResMut<History>,
+    mut modules: Query<(Entity, &ModuleId, &Name, &mut ModuleLayer), With<ModuleWin>>,
+) -> Result {
+    let mut rows: Vec<_> = modules.iter_mut().collect();
+    rows.sort_by_key(|(.., layer)| std::cmp::Reverse(layer.order));
+
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let mut moved = None;
+    egui::SidePanel::left("Layers").show(contexts.ctx_mut()?, |ui| {
+        ui.heading("Layers");
+        for (row, (entity, id, name, layer)) in rows.iter_mut().enumerate() {
+            let before = ModuleLayer::clone(layer);
+            let response = ui
+                .horizontal(|ui| {
+                    ui.dnd_drag_source(egui::Id::new(("layer", *entity)), *entity, |ui| {
+                        ui.label("☰");
+                    });
+                    ui.toggle_value(&mut layer.visible, "👁")
+                        .on_hover_text("Visible");
+                    ui.toggle_value(&mut layer.locked, "🔒")
+                        .on_hover_text("Locked");
+                    ui.toggle_value(&mut layer.solo, "S").on_hover_text("Solo");
+                    match renaming.as_mut() {
+                        Some((renamed, text)) if renamed == entity => {
+                            let edit = ui.text_edit_singleline(text);
+                            if edit.lost_focus() {
+                                history.touch(**id, ModuleState::Name(name.to_string()));
+                                commands.trigger(RenameModule {
+                                    entity: *entity,
+                                    name: text.clone(),
+                                });
+                                *renaming = None;
+                            } else {
+                                edit.request_focus();
+                            }
+                        }
+                        _ => {
+                            let label =
+                                ui.selectable_label(selection.contains(*entity), name.as_str());
+                            if label.clicked() && shift {
+                                selection.toggle(*entity);
+                            } else if label.clicked() {
+                                selection.select(*entity);
+                            }
+                            if label.double_clicked() {
+                                *renaming = Some((*entity, name.to_string()));
+                            }
+                            label.context_menu(|ui| {
+                                if ui.button("Parameters").clicked() {
+                                    selection.select(*entity);
+                                    commands.entity(*entity).insert(ParamsWindowOpen);
+                                    ui.close();
+                                }
+                                if ui.button("Rename").clicked() {
+                                    *renaming = Some((*entity, name.to_string()));
+                                    ui.close();
+                                }
+                                if ui.button("Delete").clicked() {
+                                    history.record_spawns();
+                                    commands.trigger(DespawnModule { entity: *entity });
+                                    ui.close();
+                                }
+                            });
+                        }
+                    }
+                })
+                .response;
+            if **layer != before {
+                history.touch(**id, ModuleState::Layer(before));
+            }
+
+            if response.dnd_hover_payload::<Entity>().is_some() {
+                ui.painter().hline(
+                    response.rect.x_range(),
+                    response.rect.top(),
+                    ui.visuals().selection.stroke,
+                );
+            }
+            if let Some(dragged) = response.dnd_release_payload::<Entity>() {
+                moved = Some((*dragged, row));
+            }
+        }
+    });
+
+    // the dropped row takes the place of the row it was dropped on, then the order is renumbered.
+    // The dragged module may have been deleted during the drag.
+    if let Some((dragged, to)) = moved
+        && let Some(from) = rows.iter().position(|(entity, ..)| *entity == dragged)
+    {
+        let row = rows.remove(from);
+        rows.insert(to, row);
+        let count = rows.len() as u32;
+        for (i, (_, id, _, layer)) in rows.iter_mut().enumerate() {
+            history.touch(**id, ModuleState::Layer(ModuleLayer::clone(layer)));
+            layer.order = count - i as u32;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::common::ModuleWin;
+use crate::midi::{MidiCurve, MidiInput, MidiLearn, MidiMappings, list_ports};
+use crate::module::ModuleId;
+
+/// A control change sent from the MIDI window, like a controller would
+pub struct TestControl {
+    channel: u8,
+    controller: u8,
+    value: u8,
+}
+
+impl Default for TestControl {
+    fn default() -> Self {
+        TestControl {
+            channel: 1,
+            controller: 1,
+            value: 64,
+        }
+    }
+}
+
+/// The MIDI port, learning controls for parameters and editing the mappings of the project
+pub fn ui_midi(
+    mut contexts: EguiContexts,
+    mut input: ResMut<MidiInput>,
+    mut learn: ResMut<MidiLearn>,
+    mut mappings: ResMut<MidiMappings>,
+    modules: Query<(&ModuleId, &Name), With<ModuleWin>>,
+    mut ports: Local<Option<Vec<String>>>,
+    mut test: Local<TestControl>,
+) -> Result {
+    egui::Window::new("MIDI")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            let ports = ports.get_or_insert_with(list_ports);
+            ui.horizontal(|ui| {
+                let mut selected = input.port_name.clone();
+                egui::ComboBox::from_label("port")
+                    .selected_text(selected.as_deref().unwrap_or("none"))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut selected, None, "none");
+                        for port in ports.iter() {
+                            ui.selectable_value(&mut selected, Some(port.clone()), port);
+                        }
+                    });
+                if selected != input.port_name {
+                    input.select(selected);
+                }
+                if ui
+                    .small_button("⟳")
+                    .on_hover_text("look for ports again")
+                    .clicked()
+                {
+                    *ports = list_ports();
+                }
+            });
+            if !cfg!(target_os = "linux") {
+                ui.weak(
+                    "MIDI devices are only found on Linux for now, the test sender below works everywhere",
+                );
+            } else if ports.is_empty() {
+                ui.weak("No MIDI ports, `modprobe snd-virmidi` adds virtual ones to test with");
+            }
+            if let Some(err) = &input.error {
+                ui.colored_label(egui::Color32::RED, err);
+            }
+
+            ui.separator();
+            if ui
+                .checkbox(&mut learn.enabled, "MIDI learn")
+                .on_hover_text("click a parameter name in a params window, then move a control")
+                .changed()
+            {
+                learn.target = None;
+            }
+            if let Some((module, param)) = &learn.target {
+                let module = modules
+                    .iter()
+                    .find(|(id, _)| *id == module)
+                    .map(|(_, name)| name.to_string())
+                    .unwrap_or_default();
+                ui.label(format!("move a control for {param} of {module}"));
+            }
+
+            ui.separator();
+            let mut removed = None;
+            egui::CollapsingHeader::new(format!("Mappings ({})", mappings.0.len()))
+                .id_salt("midi mappings")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("midi_mappings_grid")
+                        .num_columns(5)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (i, mapping) in mappings.0.iter_mut().enumerate() {
+                                let module = modules
+                                    .iter()
+                                    .find(|(id, _)| **id == mapping.module)
+                                    .map(|(_, name)| name.to_string())
+                                    .unwrap_or_else(|| "missing module".to_string());
+                                ui.monospace(mapping.source.to_string());
+                                ui.label(format!("→ {module} {}", mapping.param));
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::DragValue::new(&mut mapping.min).speed(0.01));
+                                    ui.label("to");
+                                    ui.add(egui::DragValue::new(&mut mapping.max).speed(0.01));
+                                    if ui.small_button("⇄").on_hover_text("invert").clicked() {
+                                        std::mem::swap(&mut mapping.min, &mut mapping.max);
+                                    }
+                                });
+                                egui::ComboBox::from_id_salt(("midi curve", i))
+                                    .selected_text(format!("{:?}", mapping.curve))
+                                    .show_ui(ui, |ui| {
+                                        for curve in MidiCurve::ALL {
+                                            ui.selectable_value(
+                                                &mut mapping.curve,
+                                                curve,
+                                                format!("{curve:?}"),
+                                            );
+                                        }
+                                    });
+                                if ui.small_button("x").clicked() {
+                                    removed = Some(i);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                });
+            if let Some(i) = removed {
+                mappings.0.remove(i);
+            }
+
+            egui::CollapsingHeader::new("Received")
+                .id_salt("midi received")
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(160.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for line in input.recent.iter() {
+                                ui.monospace(line);
+                            }
+                        });
+                });
+
+            egui::CollapsingHeader::new("Send a test control")
+                .id_salt("midi test")
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut test.channel)
+                                .range(1..=16)
+                                .prefix("ch"),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut test.controller)
+                                .range(0..=127)
+                                .prefix("CC "),
+                        );
+                    });
+                    // sliding sends a message for every value, like turning a knob
+                    if ui
+                        .add(egui::Slider::new(&mut test.value, 0..=127))
+                        .changed()
+                        | ui.button("Send").clicked()
+                    {
+                        let bytes = vec![0xb0 | (test.channel - 1), test.controller, test.value];
+                        let _ = input.injector().send(bytes);
+                    }
+                });
+        });
+    Ok(())
+}
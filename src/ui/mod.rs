@@ -1,8 +1,24 @@
-use crate::{common::{ModuleWin, BOXHEIGHT, BOXWIDTH}, module::ResizeModule};
+use crate::{common::{AppState, ModuleWin}, module::ResizeModule, params::{ModuleParams, Param}, playback::Playback};
+use crate::midi::{MidiLearn, MidiMappings};
 use bevy::prelude::*;
 use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
 
-use crate::module::{ModuleClass, SpawnModuleEvent};
+use crate::history::{History, ModuleState, Placement};
+use crate::module::{ModuleAnchor, ModuleClass, ModuleId, Selection, SpawnModuleEvent};
+use canvas::MIN_MODULE_SIZE;
+
+mod arrange;
+mod audio;
+mod canvas;
+mod console;
+mod export;
+mod history;
+mod inspector;
+mod layers;
+mod midi;
+mod osc;
+mod params;
+mod shortcuts;
 
 // use bevy_simple_subsecond_system::prelude::*;
 
@@ -12,84 +28,271 @@ pub struct BumpUiPlugin;
 impl Plugin for BumpUiPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(EguiPlugin::default())
-            .add_systems(EguiPrimaryContextPass, ui_example_system);
+            .init_resource::<arrange::SnapSettings>()
+            .init_resource::<arrange::Guides>()
+            .init_resource::<arrange::SnapLines>()
+            .add_systems(
+                EguiPrimaryContextPass,
+                (
+                    ui_transport,
+                    layers::ui_layers,
+                    ui_example_system,
+                    arrange::ui_rulers,
+                    arrange::ui_arrange,
+                    ui_module_params,
+                    history::ui_history,
+                    shortcuts::ui_shortcuts,
+                    console::ui_console,
+                    inspector::ui_inspector,
+                    export::ui_export,
+                    export::ui_export_video,
+                    export::ui_capture_frame,
+                    osc::ui_osc,
+                    midi::ui_midi,
+                    audio::ui_audio,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    canvas::canvas_input,
+                    arrange::drag_guides,
+                    canvas::draw_module_gizmos,
+                    arrange::draw_guides,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Running)),
+            );
     }
 }
 
 
+/// Play/pause and scrubbing for the composition clock
+fn ui_transport(mut contexts: EguiContexts, mut playback: ResMut<Playback>) -> Result {
+    egui::TopBottomPanel::bottom("Transport").show(contexts.ctx_mut()?, |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("⏮").clicked() {
+                playback.time = 0.0;
+            }
+            let label = if playback.playing { "⏸" } else { "▶" };
+            if ui.button(label).clicked() {
+                playback.playing = !playback.playing;
+            }
+            ui.add(
+                egui::DragValue::new(&mut playback.time)
+                    .range(0.0..=f32::MAX)
+                    .speed(0.05)
+                    .suffix(" s"),
+            );
+            ui.label(format!("frame {}", playback.frame()));
+            ui.add(
+                egui::DragValue::new(&mut playback.frame_rate)
+                    .range(1.0..=240.0)
+                    .suffix(" fps"),
+            );
+            let mut looping = playback.loop_duration.is_some();
+            if ui.checkbox(&mut looping, "loop").changed() {
+                playback.loop_duration = looping.then_some(10.0);
+            }
+            if let Some(duration) = playback.loop_duration.as_mut() {
+                ui.add(
+                    egui::DragValue::new(duration)
+                        .range(0.1..=3600.0)
+                        .speed(0.05)
+                        .suffix(" s"),
+                );
+            }
+        });
+    });
+    Ok(())
+}
+
+/// Marks a module whose parameter window was opened, it is shown while the module is the active one
+#[derive(Component)]
+pub(super) struct ParamsWindowOpen;
+
+#[allow(clippy::too_many_arguments)]
 fn ui_example_system(
     mut commands : Commands,
     mut contexts: EguiContexts,
-    query: Query<(Entity, &mut Transform, &mut ModuleWin)>,
-    windows: Query<&mut Window>,
+    mut history: ResMut<History>,
+    mut query: Query<(&Name, &ModuleId, &mut Transform, &mut ModuleAnchor, &mut ModuleWin)>,
+    selection: Res<Selection>,
+    mut transform_panel: Local<bool>,
+    mut editing: Local<bool>,
 ) -> Result {
-    if let Ok(win) = windows.single() {
-        // new window with our spawn button
-        egui::SidePanel::right("Module Spawner").show(contexts.ctx_mut()?, |ui| {
-            if ui.button("Spawn pong Module").clicked() {
+    // new window with our spawn button
+    egui::SidePanel::right("Module Spawner").show(contexts.ctx_mut()?, |ui| {
+        for (label, moduleclass) in [
+            ("Spawn pong Module", ModuleClass::Pong),
+            ("Spawn noise Module", ModuleClass::Noise),
+            ("Spawn text Module", ModuleClass::Text),
+            ("Spawn image Module", ModuleClass::Image),
+            ("Spawn shader Module", ModuleClass::Shader),
+        ] {
+            if ui.button(label).clicked() {
+                history.record_spawns();
                 commands.trigger(SpawnModuleEvent {
-                    moduleclass: ModuleClass::Pong,
+                    moduleclass,
+                    snapshot: None,
                 });
             }
-            if ui.button("Spawn noise Module").clicked() {
-                commands.trigger(SpawnModuleEvent {
-                    moduleclass: ModuleClass::Noise,
+        }
+        ui.separator();
+        ui.checkbox(&mut transform_panel, "Transform panel");
+    });
+
+    // numeric editing of what the canvas handles do
+    let Some(entity) = selection.active() else {
+        return Ok(());
+    };
+    let Ok((name, id, mut tf, mut anchor, mut mw)) = query.get_mut(entity) else {
+        return Ok(());
+    };
+    let before = Placement::new(&tf, &anchor, &mw);
+    egui::Window::new(format!("{name} transform"))
+        .id(egui::Id::new("transform panel"))
+        .open(&mut transform_panel)
+        .show(contexts.ctx_mut()?, |ui| {
+            egui::Grid::new("transform_grid").num_columns(2).show(ui, |ui| {
+                ui.label("position");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut tf.translation.x));
+                    ui.add(egui::DragValue::new(&mut tf.translation.y));
                 });
-            }
-        });
+                ui.end_row();
 
-        for (entity, mut tf, mut mw) in query {
-            let title = format!("{:?} module", mw.class);
-            let window = egui::Window::new(title)
-                .id(egui::Id::new(entity.index()))
-                .pivot(egui::Align2::LEFT_TOP)
-                .min_width(20.0)
-                .min_height(20.0)
-                .default_size([BOXWIDTH, BOXHEIGHT])
-                .constrain(false)
-                .title_bar(true)
-                .frame(
-                    egui::Frame::default()
-                        // .fill(egui::Color32::TRANSPARENT)
-                        // .stroke(egui::Stroke::new(4.0, egui::Color32::BLACK)),
-                )
-                .show(contexts.ctx_mut()?, |ui| {
-                    ui.allocate_space(ui.available_size());
+                ui.label("rotation");
+                let mut degrees = tf.rotation.to_euler(EulerRot::XYZ).2.to_degrees();
+                if ui
+                    .add(egui::DragValue::new(&mut degrees).suffix("°"))
+                    .changed()
+                {
+                    tf.rotation = Quat::from_rotation_z(degrees.to_radians());
+                }
+                ui.end_row();
+
+                ui.label("scale");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut tf.scale.x).speed(0.01));
+                    ui.add(egui::DragValue::new(&mut tf.scale.y).speed(0.01));
                 });
+                ui.end_row();
 
-            // Get the current position after the window has been shown and potentially moved
-            let response = window.and_then(|r| Some(r.response)).unwrap();
-            let newsize = (
-                (response.rect.size().x) as u32,
-                (response.rect.size().y) as u32,
-            );
+                ui.label("anchor");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut anchor.0.x).range(-0.5..=0.5).speed(0.01));
+                    ui.add(egui::DragValue::new(&mut anchor.0.y).range(-0.5..=0.5).speed(0.01));
+                });
+                ui.end_row();
+
+                ui.label("size");
+                let mut size = Vec2::new(mw.width, mw.height);
+                let resized = ui
+                    .horizontal(|ui| {
+                        ui.add(egui::DragValue::new(&mut size.x).range(MIN_MODULE_SIZE..=4096.0))
+                            .changed()
+                            | ui.add(egui::DragValue::new(&mut size.y).range(MIN_MODULE_SIZE..=4096.0))
+                                .changed()
+                    })
+                    .inner;
+                if resized {
+                    let size = size.round();
+                    mw.width = size.x;
+                    mw.height = size.y;
+                    commands.trigger(ResizeModule {
+                        entity,
+                        width: size.x,
+                        height: size.y,
+                    });
+                }
+                ui.end_row();
+            });
+        });
+
+    if Placement::new(&tf, &anchor, &mw) != before {
+        history.touch(*id, ModuleState::Placement(before));
+        *editing = true;
+    }
+    history.hold_widget_edit(contexts.ctx_mut()?, &mut editing);
+    Ok(())
+}
 
-            // set sprite custom size to window size if updated
-            if (
-                mw.width as u32,
-                mw.height as u32,
-            ) != newsize
-            {
-                // sprite.custom_size = Some(Vec2 {
-                //     x: newsize.0 as f32,
-                //     y: newsize.1 as f32,
-                // });
-
-                mw.height = newsize.1 as f32;
-                mw.width = newsize.0 as f32;
-
-                commands.trigger(ResizeModule {
-                    entity: entity,
-                    width: newsize.0 as f32,
-                    height: newsize.1 as f32,
-                })
+/// The parameter window of the active module, if it was opened from its module window.
+/// While MIDI learn is on, clicking a parameter name picks it for the next control that moves.
+#[allow(clippy::too_many_arguments)]
+fn ui_module_params(
+    mut commands: Commands,
+    mut contexts: EguiContexts,
+    mut history: ResMut<History>,
+    mut editing: Local<bool>,
+    selection: Res<Selection>,
+    mut learn: ResMut<MidiLearn>,
+    mut mappings: ResMut<MidiMappings>,
+    mut query: Query<(&Name, &ModuleId, &mut ModuleParams), With<ParamsWindowOpen>>,
+) -> Result {
+    let Some(entity) = selection.active() else {
+        return Ok(());
+    };
+    let Ok((name, id, mut moduleparams)) = query.get_mut(entity) else {
+        return Ok(());
+    };
 
+    let mut open = true;
+    egui::Window::new(format!("{name} params"))
+        .id(egui::Id::new(("params", entity)))
+        .open(&mut open)
+        .show(contexts.ctx_mut()?, |ui| {
+            let label = |ui: &mut egui::Ui, param: &Param| {
+                let controls: Vec<String> = mappings
+                    .0
+                    .iter()
+                    .filter(|m| m.module == *id && m.param == param.name)
+                    .map(|m| m.source.to_string())
+                    .collect();
+                if !learn.enabled {
+                    let response = ui.label(&param.name);
+                    if !controls.is_empty() {
+                        response.on_hover_text(format!("MIDI: {}", controls.join(", ")));
+                    }
+                    return;
+                }
+
+                let target = (*id, param.name.clone());
+                let learning = learn.target.as_ref() == Some(&target);
+                let text = if learning {
+                    format!("{} (move a control)", param.name)
+                } else if controls.is_empty() {
+                    param.name.clone()
+                } else {
+                    format!("{} [{}]", param.name, controls.join(", "))
+                };
+                let response = ui.selectable_label(learning, text);
+                if response.clicked() {
+                    learn.target = (!learning).then_some(target);
+                }
+                response.context_menu(|ui| {
+                    if ui.button("Forget MIDI controls").clicked() {
+                        mappings
+                            .0
+                            .retain(|m| !(m.module == *id && m.param == param.name));
+                        ui.close();
+                    }
+                });
+            };
+            // only flag the params as changed when a widget was actually edited
+            let before = moduleparams.clone();
+            if params::params_ui(ui, moduleparams.bypass_change_detection(), label) {
+                moduleparams.set_changed();
+                history.touch(*id, ModuleState::Params(before));
+                *editing = true;
             }
+        });
+    history.hold_widget_edit(contexts.ctx_mut()?, &mut editing);
 
-            // set sprite position to window position
-            tf.translation.x = response.rect.center().x - win.resolution.width() / 2.0;
-            tf.translation.y = -response.rect.center().y + win.resolution.height() / 2.0;
-        }
+    if !open {
+        commands.entity(entity).remove::<ParamsWindowOpen>();
     }
     Ok(())
 }
@@ -0,0 +1,200 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::common::ModuleWin;
+use crate::module::{ModuleId, Selection};
+use crate::osc::packet::{self, OscArg, OscMessage};
+use crate::osc::{OscConfig, OscLearn, OscMappings, OscServer};
+use crate::params::ModuleParams;
+
+/// A message typed into the OSC window to test the server with
+pub struct TestMessage {
+    address: String,
+    value: String,
+}
+
+impl Default for TestMessage {
+    fn default() -> Self {
+        TestMessage {
+            address: "/transport/play".to_string(),
+            value: String::new(),
+        }
+    }
+}
+
+/// The OSC server settings, learning addresses for the parameters of the selected module and the received messages
+#[allow(clippy::too_many_arguments)]
+pub fn ui_osc(
+    mut contexts: EguiContexts,
+    mut config: ResMut<OscConfig>,
+    mut mappings: ResMut<OscMappings>,
+    mut learn: ResMut<OscLearn>,
+    server: Res<OscServer>,
+    selection: Res<Selection>,
+    modules: Query<(&ModuleId, &Name, &ModuleParams), With<ModuleWin>>,
+    mut test: Local<TestMessage>,
+    mut address: Local<Option<String>>,
+) -> Result {
+    egui::Window::new("OSC")
+        .default_open(false)
+        .show(contexts.ctx_mut()?, |ui| {
+            let mut settings_changed = false;
+            ui.horizontal(|ui| {
+                settings_changed |= ui.checkbox(&mut config.enabled, "listen on").changed();
+                let text = address.get_or_insert_with(|| config.address.to_string());
+                let edit = ui
+                    .add(egui::TextEdit::singleline(text).desired_width(110.0))
+                    .on_hover_text("127.0.0.1 only takes messages from this machine, 0.0.0.0 from the whole network");
+                match text.trim().parse::<IpAddr>() {
+                    Ok(parsed) if edit.lost_focus() && parsed != config.address => {
+                        config.address = parsed;
+                        settings_changed = true;
+                    }
+                    Ok(_) => {}
+                    Err(_) => {
+                        ui.colored_label(egui::Color32::RED, "not an IP address");
+                    }
+                }
+                ui.label("port");
+                settings_changed |= ui
+                    .add(egui::DragValue::new(&mut config.port).range(1024..=65535))
+                    .changed();
+            });
+            if settings_changed {
+                config.save();
+            }
+            match (&server.error, server.address) {
+                (Some(err), _) => ui.colored_label(egui::Color32::RED, err),
+                (None, Some(address)) if address.ip().is_loopback() => {
+                    ui.label(format!("listening on UDP {address}, only for this machine"))
+                }
+                (None, Some(address)) => ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("listening on UDP {address}, anyone on the network can control the app"),
+                ),
+                (None, None) => ui.label("stopped"),
+            };
+
+            ui.separator();
+            let selected = selection
+                .active()
+                .and_then(|entity| modules.get(entity).ok());
+            match selected {
+                Some((id, name, params)) => {
+                    ui.label(format!("Learn for {name}"));
+                    egui::Grid::new("osc_learn_grid")
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for param in params.0.iter() {
+                                let target = (*id, param.name.clone());
+                                let learning = learn.0.as_ref() == Some(&target);
+                                ui.label(&param.name);
+                                let addresses: Vec<&str> = mappings
+                                    .0
+                                    .iter()
+                                    .filter(|m| m.module == target.0 && m.param == target.1)
+                                    .map(|m| m.address.as_str())
+                                    .collect();
+                                ui.monospace(addresses.join(" "));
+                                if ui
+                                    .selectable_label(
+                                        learning,
+                                        if learning { "waiting..." } else { "learn" },
+                                    )
+                                    .clicked()
+                                {
+                                    learn.0 = (!learning).then_some(target);
+                                }
+                                ui.end_row();
+                            }
+                        });
+                }
+                None => {
+                    ui.label("Select a module to learn addresses for its parameters");
+                }
+            }
+
+            ui.separator();
+            let mut removed = None;
+            egui::CollapsingHeader::new(format!("Mappings ({})", mappings.0.len()))
+                .id_salt("osc mappings")
+                .show(ui, |ui| {
+                    for (i, mapping) in mappings.0.iter().enumerate() {
+                        let module = modules
+                            .iter()
+                            .find(|(id, _, _)| **id == mapping.module)
+                            .map(|(_, name, _)| name.to_string())
+                            .unwrap_or_else(|| "missing module".to_string());
+                        ui.horizontal(|ui| {
+                            ui.monospace(&mapping.address);
+                            ui.label(format!("→ {module} {}", mapping.param));
+                            if ui.small_button("x").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                });
+            if let Some(i) = removed {
+                mappings.0.remove(i);
+            }
+
+            egui::CollapsingHeader::new("Received")
+                .id_salt("osc received")
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(160.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for line in server.recent.iter() {
+                                ui.monospace(line);
+                            }
+                        });
+                });
+
+            egui::CollapsingHeader::new("Send a test message")
+                .id_salt("osc test")
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut test.address);
+                        ui.add(egui::TextEdit::singleline(&mut test.value).hint_text("value"));
+                    });
+                    if ui.button("Send").clicked()
+                        && let Some(address) = server.address
+                    {
+                        send_test_message(&test, address);
+                    }
+                });
+        });
+    Ok(())
+}
+
+/// Sends a message to our own server, like an external controller would
+fn send_test_message(test: &TestMessage, mut server: SocketAddr) {
+    let value = test.value.trim();
+    let args = if value.is_empty() {
+        vec![]
+    } else if let Ok(number) = value.parse::<f32>() {
+        vec![OscArg::Float(number)]
+    } else if let Ok(boolean) = value.parse::<bool>() {
+        vec![OscArg::Bool(boolean)]
+    } else {
+        vec![OscArg::Text(value.to_string())]
+    };
+    let message = OscMessage {
+        address: test.address.trim().to_string(),
+        args,
+    };
+
+    if server.ip().is_unspecified() {
+        server.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    }
+    let local = SocketAddr::new(server.ip(), 0);
+    let sent =
+        UdpSocket::bind(local).and_then(|socket| socket.send_to(&packet::encode(&message), server));
+    if let Err(err) = sent {
+        warn!("couldn't send {message}: {err}");
+    }
+}
@@ -0,0 +1,130 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::params::{GradientStop, ModuleParams, Param, ParamValue, sample_gradient};
+
+/// Draws an editor widget for every parameter, `label` draws the cell next to it.
+/// Returns true if any of the values was changed by the user.
+pub fn params_ui(
+    ui: &mut egui::Ui,
+    params: &mut ModuleParams,
+    mut label: impl FnMut(&mut egui::Ui, &Param),
+) -> bool {
+    let mut changed = false;
+
+    egui::Grid::new("params_grid")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            for param in params.0.iter_mut() {
+                label(ui, param);
+                let range = param.range.clone();
+                changed |= match &mut param.value {
+                    ParamValue::Float(v) => ui.add(egui::Slider::new(v, range)).changed(),
+                    ParamValue::Int(v) => ui
+                        .add(egui::Slider::new(
+                            v,
+                            *range.start() as i32..=*range.end() as i32,
+                        ))
+                        .changed(),
+                    ParamValue::Bool(v) => ui.checkbox(v, "").changed(),
+                    ParamValue::Color(v) => color_ui(ui, v),
+                    ParamValue::Vec2(v) => {
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut v.x)
+                                    .range(range.clone())
+                                    .speed(0.5),
+                            )
+                            .changed()
+                                | ui.add(egui::DragValue::new(&mut v.y).range(range).speed(0.5))
+                                    .changed()
+                        })
+                        .inner
+                    }
+                    ParamValue::Text(v) => ui.text_edit_multiline(v).changed(),
+                    ParamValue::Choice(v) => {
+                        let mut selected = v.clone();
+                        egui::ComboBox::from_id_salt(&param.name)
+                            .selected_text(selected.as_str())
+                            .show_ui(ui, |ui| {
+                                for option in param.options.iter() {
+                                    ui.selectable_value(&mut selected, option.clone(), option);
+                                }
+                            });
+                        let picked = selected != *v;
+                        *v = selected;
+                        picked
+                    }
+                    ParamValue::Gradient(stops) => ui.vertical(|ui| gradient_ui(ui, stops)).inner,
+                };
+                ui.end_row();
+            }
+        });
+
+    changed
+}
+
+fn color_ui(ui: &mut egui::Ui, color: &mut LinearRgba) -> bool {
+    let mut rgba = color.to_f32_array();
+    let changed = ui.color_edit_button_rgba_unmultiplied(&mut rgba).changed();
+    if changed {
+        *color = LinearRgba::from_f32_array(rgba);
+    }
+    changed
+}
+
+fn to_color32(color: LinearRgba) -> egui::Color32 {
+    let [r, g, b, a] = Color::LinearRgba(color).to_srgba().to_u8_array();
+    egui::Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+/// A preview of the ramp with a row per stop, stops can be added and removed
+fn gradient_ui(ui: &mut egui::Ui, stops: &mut Vec<GradientStop>) -> bool {
+    let mut changed = false;
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(180.0, 14.0), egui::Sense::hover());
+    let steps = 48;
+    for i in 0..steps {
+        let t = (i as f32 + 0.5) / steps as f32;
+        let x0 = egui::lerp(rect.x_range(), i as f32 / steps as f32);
+        let x1 = egui::lerp(rect.x_range(), (i + 1) as f32 / steps as f32);
+        ui.painter().rect_filled(
+            egui::Rect::from_x_y_ranges(x0..=x1, rect.y_range()),
+            0.0,
+            to_color32(sample_gradient(stops, t)),
+        );
+    }
+
+    let removable = stops.len() > 1;
+    let mut remove = None;
+    for (i, stop) in stops.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(egui::Slider::new(&mut stop.position, 0.0..=1.0))
+                .changed();
+            changed |= color_ui(ui, &mut stop.color);
+            if removable && ui.small_button("✖").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(i) = remove {
+        stops.remove(i);
+        changed = true;
+    }
+
+    if ui.small_button("+ stop").clicked() {
+        let color = stops
+            .last()
+            .map(|stop| stop.color)
+            .unwrap_or(LinearRgba::WHITE);
+        stops.push(GradientStop {
+            position: 1.0,
+            color,
+        });
+        changed = true;
+    }
+
+    changed
+}
@@ -0,0 +1,41 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContexts, egui};
+
+use crate::keymap::{Action, KEYMAP_PATH, Keymap, PressedActions};
+
+/// Lists the keymap, toggled with the [`Action::Shortcuts`] shortcut
+pub fn ui_shortcuts(
+    mut contexts: EguiContexts,
+    keymap: Res<Keymap>,
+    pressed: Res<PressedActions>,
+    mut open: Local<bool>,
+) -> Result {
+    if pressed.contains(Action::Shortcuts) {
+        *open = !*open;
+    }
+
+    egui::Window::new("Shortcuts")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .show(contexts.ctx_mut()?, |ui| {
+            egui::Grid::new("shortcuts_grid")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (action, bindings) in keymap.0.iter() {
+                        ui.label(action.description());
+                        let keys: Vec<String> =
+                            bindings.iter().map(|binding| binding.to_string()).collect();
+                        ui.label(keys.join(", "));
+                        ui.end_row();
+                    }
+                });
+            ui.separator();
+            ui.weak(format!(
+                "Shortcuts are ignored while typing. Bindings are read from {KEYMAP_PATH}."
+            ));
+        });
+    Ok(())
+}
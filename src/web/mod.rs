@@ -0,0 +1,178 @@
+//! The JavaScript API of the web player, exported from the wasm module and exposed as `window.bumper`
+//! by the page the web export writes.
+
+use std::cell::RefCell;
+
+use bevy::prelude::*;
+use js_sys::{Array, Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::common::AppState;
+use crate::keymap::Action;
+use crate::params::{ExternalValue, SetParam};
+use crate::player::host::{self, HostCommand, HostParam, queue_command};
+
+thread_local! {
+    /// Callbacks passed to `subscribe`, JS values only live on the main thread
+    static SUBSCRIBERS: RefCell<Vec<(u32, Function)>> = const { RefCell::new(Vec::new()) };
+    static NEXT_SUBSCRIBER: RefCell<u32> = const { RefCell::new(0) };
+}
+
+/// Calls the subscribers about parameter changes
+pub struct WebPlugin;
+
+impl Plugin for WebPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Last, notify_subscribers.run_if(in_state(AppState::Running)));
+    }
+}
+
+fn notify_subscribers() {
+    let changed = host::take_changed_params();
+    if changed.is_empty() {
+        return;
+    }
+    SUBSCRIBERS.with_borrow(|subscribers| {
+        for param in changed.iter() {
+            let value = to_js(param.value.as_ref());
+            for (_, callback) in subscribers.iter() {
+                let args = Array::of3(
+                    &param.module.as_str().into(),
+                    &param.name.as_str().into(),
+                    &value,
+                );
+                if let Err(err) = callback.apply(&JsValue::NULL, &args) {
+                    warn!("parameter subscriber threw {err:?}");
+                }
+            }
+        }
+    });
+}
+
+fn to_js(value: Option<&ExternalValue>) -> JsValue {
+    match value {
+        Some(ExternalValue::Number(number)) => (*number).into(),
+        Some(ExternalValue::Bool(boolean)) => (*boolean).into(),
+        Some(ExternalValue::Text(text)) => text.as_str().into(),
+        Some(ExternalValue::List(list)) => list
+            .iter()
+            .map(|n| JsValue::from(*n))
+            .collect::<Array>()
+            .into(),
+        None => JsValue::UNDEFINED,
+    }
+}
+
+fn from_js(value: &JsValue) -> Result<ExternalValue, JsError> {
+    if let Some(number) = value.as_f64() {
+        Ok(ExternalValue::Number(number))
+    } else if let Some(boolean) = value.as_bool() {
+        Ok(ExternalValue::Bool(boolean))
+    } else if let Some(text) = value.as_string() {
+        Ok(ExternalValue::Text(text))
+    } else if Array::is_array(value) {
+        let list = Array::from(value)
+            .iter()
+            .map(|item| {
+                item.as_f64()
+                    .ok_or_else(|| JsError::new("arrays may only hold numbers"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ExternalValue::List(list))
+    } else {
+        Err(JsError::new("unsupported parameter value"))
+    }
+}
+
+fn param_object(param: &HostParam) -> Result<Object, JsValue> {
+    let object = Object::new();
+    Reflect::set(&object, &"module".into(), &param.module.as_str().into())?;
+    Reflect::set(&object, &"name".into(), &param.name.as_str().into())?;
+    Reflect::set(&object, &"value".into(), &to_js(param.value.as_ref()))?;
+    Reflect::set(&object, &"min".into(), &(*param.range.start()).into())?;
+    Reflect::set(&object, &"max".into(), &(*param.range.end()).into())?;
+    let options: Array = param
+        .options
+        .iter()
+        .map(|o| JsValue::from(o.as_str()))
+        .collect();
+    Reflect::set(&object, &"options".into(), &options)?;
+    Ok(object)
+}
+
+#[wasm_bindgen]
+pub fn play() {
+    queue_command(HostCommand::Play);
+}
+
+#[wasm_bindgen]
+pub fn pause() {
+    queue_command(HostCommand::Pause);
+}
+
+/// Jumps to a time in seconds
+#[wasm_bindgen]
+pub fn seek(time: f32) {
+    queue_command(HostCommand::Seek(time));
+}
+
+/// Loops the composition every `duration` seconds, `undefined` plays on forever
+#[wasm_bindgen(js_name = setLoop)]
+pub fn set_loop(duration: Option<f32>) {
+    queue_command(HostCommand::SetLoop(duration));
+}
+
+/// Every parameter of every module as `{ module, name, value, min, max, options }`
+#[wasm_bindgen(js_name = listParams)]
+pub fn list_params() -> Result<Array, JsValue> {
+    host::params()
+        .iter()
+        .map(|param| param_object(param).map(JsValue::from))
+        .collect()
+}
+
+/// The value of a parameter, `undefined` if there is no such parameter
+#[wasm_bindgen(js_name = getParam)]
+pub fn get_param(module: &str, param: &str) -> JsValue {
+    host::params()
+        .iter()
+        .find(|p| p.module == module && p.name == param)
+        .map_or(JsValue::UNDEFINED, |p| to_js(p.value.as_ref()))
+}
+
+/// Sets a parameter of the module with the given name.
+/// Numbers, booleans, strings and arrays of numbers (vectors and colours) are accepted.
+#[wasm_bindgen(js_name = setParam)]
+pub fn set_param(module: String, param: String, value: JsValue) -> Result<(), JsError> {
+    queue_command(HostCommand::SetParam(SetParam {
+        module,
+        param,
+        value: from_js(&value)?,
+    }));
+    Ok(())
+}
+
+/// Calls `callback(module, param, value)` whenever a parameter changes, returns an id for `unsubscribe`
+#[wasm_bindgen]
+pub fn subscribe(callback: Function) -> u32 {
+    let id = NEXT_SUBSCRIBER.with_borrow_mut(|next| {
+        *next += 1;
+        *next
+    });
+    SUBSCRIBERS.with_borrow_mut(|subscribers| subscribers.push((id, callback)));
+    id
+}
+
+#[wasm_bindgen]
+pub fn unsubscribe(id: u32) {
+    SUBSCRIBERS.with_borrow_mut(|subscribers| subscribers.retain(|(other, _)| *other != id));
+}
+
+/// Triggers an action by name, like "PlayPause", as if its shortcut was pressed
+#[wasm_bindgen]
+pub fn trigger(event: &str) -> Result<(), JsError> {
+    let action = ron::from_str::<Action>(event)
+        .map_err(|_| JsError::new(&format!("unknown event {event}")))?;
+    queue_command(HostCommand::Trigger(action));
+    Ok(())
+}